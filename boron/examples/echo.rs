@@ -0,0 +1,41 @@
+//! Repeats whatever follows `!echo`, and counts reactions to its replies.
+//!
+//!     RADON_URL=http://localhost:3000 RADON_TOKEN=radon_... cargo run -p boron --example echo
+
+use std::env;
+
+use boron::{Bot, BotError, Commands};
+use futures::StreamExt;
+
+#[tokio::main]
+async fn main() -> Result<(), BotError> {
+    env_logger::init();
+
+    let url = env::var("RADON_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let token = env::var("RADON_TOKEN").expect("RADON_TOKEN must be set to a bot's API token");
+
+    let bot = Bot::connect(&url, &token).await?;
+    println!("Connected as {}", bot.me().username);
+
+    let mut reactions = bot.reactions();
+    tokio::spawn(async move {
+        while let Some(reaction) = reactions.next().await {
+            println!("{} reacted {}", reaction.username, reaction.emoji);
+        }
+    });
+
+    let commands = Commands::new()
+        .command("echo", "Say something back", |ctx, args| async move {
+            if args.is_empty() {
+                return ctx.react("🤔");
+            }
+            ctx.reply(args.rest())
+        })
+        .command("add", "Add two numbers", |ctx, args| async move {
+            let a: i64 = args.parse_at(0)?;
+            let b: i64 = args.parse_at(1)?;
+            ctx.reply(&(a + b).to_string())
+        });
+
+    bot.run(commands).await
+}
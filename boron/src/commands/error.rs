@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("a quote is never closed")]
+    UnclosedQuote,
+    #[error("there's nothing after the last backslash")]
+    TrailingEscape,
+    #[error("argument {} is missing", .0 + 1)]
+    Missing(usize),
+    #[error("argument {} ({value:?}) isn't valid", .index + 1)]
+    Invalid { index: usize, value: String },
+}
@@ -0,0 +1,272 @@
+//! Routing `!cmd arg "quoted arg"` style messages to handlers.
+//!
+//! Slash commands work the same way, but since radon treats `/name` typed
+//! into a client as one of its own commands, people reach a bot's with
+//! `//name`, which is posted as the text `/name`.
+
+pub mod error;
+
+use std::{collections::BTreeMap, fmt::Write, future::Future, str::FromStr, sync::Arc};
+
+use futures::future::BoxFuture;
+
+pub use error::ArgsError;
+
+use crate::{
+    message::{ChatMessage, MessageType},
+    Bot, BotError,
+};
+
+type Handler = Arc<dyn Fn(Context, Args) -> BoxFuture<'static, Result<(), BotError>> + Send + Sync>;
+
+struct Command {
+    help: String,
+    handler: Handler,
+}
+
+/// A set of commands and the prefixes that trigger them. Build one up and
+/// hand it to [`Bot::run`].
+pub struct Commands {
+    prefixes: Vec<String>,
+    commands: BTreeMap<String, Command>,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Commands {
+    /// No commands yet, triggered by `!` and `/`.
+    pub fn new() -> Self {
+        Self {
+            prefixes: vec!["!".to_string(), "/".to_string()],
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Replace the prefixes commands are triggered by.
+    pub fn prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.prefixes = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add a command. Names are matched case-insensitively. Unless a `help`
+    /// command is added, one listing every command's `help` line is built in.
+    pub fn command<F, Fut>(mut self, name: &str, help: &str, handler: F) -> Self
+    where
+        F: Fn(Context, Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BotError>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |ctx, args| Box::pin(handler(ctx, args)));
+        self.commands.insert(
+            name.to_lowercase(),
+            Command {
+                help: help.to_string(),
+                handler,
+            },
+        );
+        self
+    }
+
+    /// Split `text` into a command name and whatever follows it, if it starts
+    /// with one of our prefixes.
+    pub fn parse<'a>(&self, text: &'a str) -> Option<(&'a str, &'a str)> {
+        let line = self
+            .prefixes
+            .iter()
+            .find_map(|prefix| text.strip_prefix(prefix.as_str()))?;
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if name.is_empty() {
+            return None;
+        }
+        Some((name, rest.trim()))
+    }
+
+    /// The built in `help` text.
+    pub fn help(&self) -> String {
+        let prefix = self.prefixes.first().map(String::as_str).unwrap_or("");
+        let mut text = String::from("Commands:");
+        for (name, command) in &self.commands {
+            let _ = write!(text, "\n{prefix}{name} - {}", command.help);
+        }
+        text
+    }
+
+    /// Run the handler for `message` if it's a command, returning whether it
+    /// was. Handlers run on their own task so a slow one doesn't hold up the
+    /// rest. Our own messages and unknown commands are ignored, the latter
+    /// in case another bot in the room shares a prefix.
+    pub fn dispatch(&self, bot: &Bot, message: ChatMessage) -> bool {
+        if message.kind != MessageType::Text || message.user_id == Some(bot.me().user_id) {
+            return false;
+        }
+        let Some((name, rest)) = self.parse(&message.text) else {
+            return false;
+        };
+        let name = name.to_lowercase();
+
+        let Some(command) = self.commands.get(&name) else {
+            if name == "help" {
+                if let Err(e) = bot.reply(&message, &self.help()) {
+                    log::warn!("failed to send help: {e}");
+                }
+                return true;
+            }
+            return false;
+        };
+
+        let args = match Args::parse(rest) {
+            Ok(args) => args,
+            Err(e) => {
+                if let Err(e) = bot.reply(&message, &e.to_string()) {
+                    log::warn!("failed to reply to {name}: {e}");
+                }
+                return true;
+            }
+        };
+
+        let ctx = Context {
+            bot: bot.clone(),
+            message,
+            command: name,
+        };
+        let handler = command.handler.clone();
+        tokio::spawn(async move {
+            let reply_to = ctx.clone();
+            match handler(ctx, args).await {
+                Ok(()) => {}
+                // Bad arguments are the caller's to fix, so tell them.
+                Err(BotError::Args(e)) => {
+                    if let Err(e) = reply_to.reply(&e.to_string()) {
+                        log::warn!("failed to reply to {}: {e}", reply_to.command);
+                    }
+                }
+                Err(e) => log::warn!("command {} failed: {e}", reply_to.command),
+            }
+        });
+        true
+    }
+}
+
+/// What a command handler gets besides its arguments.
+#[derive(Clone)]
+pub struct Context {
+    pub bot: Bot,
+    /// The message that triggered the command.
+    pub message: ChatMessage,
+    /// The command's name, lowercased and without its prefix.
+    pub command: String,
+}
+
+impl Context {
+    /// Reply in the thread of the triggering message.
+    pub fn reply(&self, text: &str) -> Result<(), BotError> {
+        self.bot.reply(&self.message, text)
+    }
+
+    /// Post in the triggering message's room, outside any thread.
+    pub fn say(&self, text: &str) -> Result<(), BotError> {
+        self.bot.send(self.message.room_id, text)
+    }
+
+    /// React to the triggering message.
+    pub fn react(&self, emoji: &str) -> Result<(), BotError> {
+        self.bot.react(&self.message, emoji)
+    }
+}
+
+/// A command's arguments, split like a shell would: on whitespace, with
+/// `'single'` and `"double"` quotes grouping words and `\` escaping the next
+/// character outside single quotes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    raw: String,
+    args: Vec<String>,
+}
+
+impl Args {
+    pub fn parse(raw: &str) -> Result<Self, ArgsError> {
+        let mut args = Vec::new();
+        let mut current = String::new();
+        let mut in_arg = false;
+        let mut quote = None;
+        let mut chars = raw.chars();
+
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (None | Some('"'), '\\') => {
+                    current.push(chars.next().ok_or(ArgsError::TrailingEscape)?);
+                    in_arg = true;
+                }
+                (Some(_), c) => current.push(c),
+                (None, '"' | '\'') => {
+                    quote = Some(c);
+                    in_arg = true;
+                }
+                (None, c) if c.is_whitespace() => {
+                    if in_arg {
+                        args.push(std::mem::take(&mut current));
+                        in_arg = false;
+                    }
+                }
+                (None, c) => {
+                    current.push(c);
+                    in_arg = true;
+                }
+            }
+        }
+        if quote.is_some() {
+            return Err(ArgsError::UnclosedQuote);
+        }
+        if in_arg {
+            args.push(current);
+        }
+
+        Ok(Self {
+            raw: raw.trim().to_string(),
+            args,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.args.get(index).map(String::as_str)
+    }
+
+    /// Like [`Args::get`], but missing arguments are an error.
+    pub fn required(&self, index: usize) -> Result<&str, ArgsError> {
+        self.get(index).ok_or(ArgsError::Missing(index))
+    }
+
+    /// Parse argument `index` as a `T`.
+    pub fn parse_at<T: FromStr>(&self, index: usize) -> Result<T, ArgsError> {
+        let value = self.required(index)?;
+        value.parse().map_err(|_| ArgsError::Invalid {
+            index,
+            value: value.to_string(),
+        })
+    }
+
+    /// Everything after the command name, as typed.
+    pub fn rest(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.args.iter().map(String::as_str)
+    }
+}
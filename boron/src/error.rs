@@ -0,0 +1,27 @@
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+use crate::commands::ArgsError;
+
+#[derive(Error, Debug)]
+pub enum BotError {
+    #[error("radon returned {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("the server url must start with http:// or https://")]
+    InvalidUrl,
+    #[error("the bot has shut down")]
+    Closed,
+    #[error(transparent)]
+    Args(#[from] ArgsError),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// Boxed, as it's several times bigger than the rest.
+    #[error(transparent)]
+    WebSocket(Box<tungstenite::Error>),
+}
+
+impl From<tungstenite::Error> for BotError {
+    fn from(e: tungstenite::Error) -> Self {
+        BotError::WebSocket(Box::new(e))
+    }
+}
@@ -0,0 +1,538 @@
+//! A client for writing radon bots.
+//!
+//! A [`Bot`] logs in with an API token, keeps a websocket open (reconnecting
+//! and replaying missed messages when it drops), and hands out typed streams
+//! of what happens in its rooms. [`Commands`] routes `!cmd` style messages to
+//! handlers.
+//!
+//! ```no_run
+//! use boron::{Bot, BotError, Commands};
+//!
+//! # async fn run() -> Result<(), BotError> {
+//! let bot = Bot::connect("http://localhost:3000", "radon_...").await?;
+//! let commands = Commands::new().command("echo", "Say it back", |ctx, args| async move {
+//!     ctx.reply(args.rest())
+//! });
+//! bot.run(commands).await
+//! # }
+//! ```
+
+pub mod commands;
+pub mod error;
+pub mod message;
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use futures::{future, SinkExt, Stream, StreamExt};
+use reqwest::{Client, Response};
+use serde::de::DeserializeOwned;
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc, watch},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+pub use commands::{Args, ArgsError, Commands, Context};
+pub use error::BotError;
+pub use message::{ChatMessage, Event, Me, MentionInfo, ReactionEvent, Room, ServerEvent};
+
+use message::ClientFrame;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const BACKFILL_PAGE_SIZE: usize = 200;
+/// How many messages per room are replayed after a reconnect, at most.
+const MAX_BACKFILL: usize = 1000;
+const DEFAULT_EVENT_BUFFER: usize = 1024;
+
+pub struct BotBuilder {
+    server: String,
+    token: String,
+    event_buffer: usize,
+}
+
+impl BotBuilder {
+    /// How many events a slow stream may fall behind by before it starts
+    /// missing them.
+    pub fn event_buffer(mut self, size: usize) -> Self {
+        self.event_buffer = size.max(1);
+        self
+    }
+
+    /// Look up who the token belongs to and open the websocket. Fails if
+    /// radon can't be reached or doesn't accept the token; after that, the
+    /// connection is kept up in the background.
+    pub async fn connect(self) -> Result<Bot, BotError> {
+        let base_url = self.server.trim_end_matches('/').to_string();
+        let ws_url = if let Some(rest) = base_url.strip_prefix("https://") {
+            format!("wss://{rest}/ws?token={}", self.token)
+        } else if let Some(rest) = base_url.strip_prefix("http://") {
+            format!("ws://{rest}/ws?token={}", self.token)
+        } else {
+            return Err(BotError::InvalidUrl);
+        };
+
+        let (events, _) = broadcast::channel(self.event_buffer);
+        let shared = Arc::new(Shared {
+            http: Client::new(),
+            base_url,
+            ws_url,
+            token: self.token,
+            events,
+            seen: Mutex::new(HashMap::new()),
+            connected: AtomicBool::new(false),
+        });
+
+        let me: Me = shared.get("/me").await?;
+        let socket = shared.open().await?;
+
+        let (frames, frames_rx) = mpsc::unbounded_channel();
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        tokio::spawn(maintain(shared.clone(), socket, frames_rx, shutdown_rx));
+
+        Ok(Bot {
+            inner: Arc::new(Inner {
+                me,
+                shared,
+                frames,
+                shutdown,
+            }),
+        })
+    }
+}
+
+/// A connected bot. Cheap to clone; the connection closes once every clone
+/// is dropped.
+#[derive(Clone)]
+pub struct Bot {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    me: Me,
+    shared: Arc<Shared>,
+    frames: mpsc::UnboundedSender<ClientFrame>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+    }
+}
+
+/// The state the background connection task shares with the [`Bot`].
+struct Shared {
+    http: Client,
+    base_url: String,
+    ws_url: String,
+    token: String,
+    events: broadcast::Sender<Event>,
+    /// The highest `seq` seen in each room, which backfill resumes from.
+    seen: Mutex<HashMap<Uuid, i64>>,
+    connected: AtomicBool,
+}
+
+impl Bot {
+    pub fn builder(server: &str, token: &str) -> BotBuilder {
+        BotBuilder {
+            server: server.to_string(),
+            token: token.to_string(),
+            event_buffer: DEFAULT_EVENT_BUFFER,
+        }
+    }
+
+    /// Shorthand for `Bot::builder(server, token).connect()`.
+    pub async fn connect(server: &str, token: &str) -> Result<Bot, BotError> {
+        Self::builder(server, token).connect().await
+    }
+
+    /// The account the bot is acting as.
+    pub fn me(&self) -> &Me {
+        &self.inner.me
+    }
+
+    /// Whether the websocket is currently up. Frames sent while it's down
+    /// are held until it's back.
+    pub fn is_connected(&self) -> bool {
+        self.inner.shared.connected.load(Ordering::Relaxed)
+    }
+
+    /// Everything that happens from now on. Each call gets its own stream;
+    /// one that falls more than the event buffer behind skips ahead.
+    pub fn events(&self) -> impl Stream<Item = Event> {
+        BroadcastStream::new(self.inner.shared.events.subscribe()).filter_map(|event| {
+            future::ready(match event {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    log::warn!("event stream fell behind: {e}");
+                    None
+                }
+            })
+        })
+    }
+
+    /// New messages in any of the bot's rooms, including its own.
+    pub fn messages(&self) -> impl Stream<Item = ChatMessage> {
+        self.events().filter_map(|event| {
+            future::ready(match event {
+                Event::Server(ServerEvent::Message(message)) => Some(message),
+                _ => None,
+            })
+        })
+    }
+
+    /// Reactions being added to messages.
+    pub fn reactions(&self) -> impl Stream<Item = ReactionEvent> {
+        self.events().filter_map(|event| {
+            future::ready(match event {
+                Event::Server(ServerEvent::ReactionAdded {
+                    room_id,
+                    message_id,
+                    emoji,
+                    user_id,
+                    username,
+                }) => Some(ReactionEvent {
+                    room_id,
+                    message_id,
+                    emoji,
+                    user_id,
+                    username,
+                }),
+                _ => None,
+            })
+        })
+    }
+
+    /// Messages that mention the bot.
+    pub fn mentions(&self) -> impl Stream<Item = MentionInfo> {
+        self.events().filter_map(|event| {
+            future::ready(match event {
+                Event::Server(ServerEvent::Mention(mention)) => Some(mention),
+                _ => None,
+            })
+        })
+    }
+
+    fn frame(&self, frame: ClientFrame) -> Result<(), BotError> {
+        self.inner.frames.send(frame).map_err(|_| BotError::Closed)
+    }
+
+    /// Post `text` in a room. Text starting with `/` is posted as is rather
+    /// than run as a radon command.
+    pub fn send(&self, room_id: Uuid, text: &str) -> Result<(), BotError> {
+        self.post(room_id, text, None)
+    }
+
+    /// Reply to `message`, in its thread if it's already part of one.
+    pub fn reply(&self, message: &ChatMessage, text: &str) -> Result<(), BotError> {
+        let parent_id = message.parent_id.unwrap_or(message.message_id);
+        self.post(message.room_id, text, Some(parent_id))
+    }
+
+    fn post(&self, room_id: Uuid, text: &str, parent_id: Option<i64>) -> Result<(), BotError> {
+        let text = if text.starts_with('/') {
+            format!("/{text}")
+        } else {
+            text.to_string()
+        };
+        self.frame(ClientFrame::Send {
+            room_id,
+            text,
+            parent_id,
+        })
+    }
+
+    pub fn react(&self, message: &ChatMessage, emoji: &str) -> Result<(), BotError> {
+        self.frame(ClientFrame::React {
+            message_id: message.message_id,
+            emoji: emoji.to_string(),
+        })
+    }
+
+    pub fn unreact(&self, message: &ChatMessage, emoji: &str) -> Result<(), BotError> {
+        self.frame(ClientFrame::Unreact {
+            message_id: message.message_id,
+            emoji: emoji.to_string(),
+        })
+    }
+
+    pub fn typing(&self, room_id: Uuid, typing: bool) -> Result<(), BotError> {
+        self.frame(ClientFrame::Typing { room_id, typing })
+    }
+
+    pub fn mark_read(&self, message: &ChatMessage) -> Result<(), BotError> {
+        self.frame(ClientFrame::MarkRead {
+            room_id: message.room_id,
+            seq: message.seq,
+        })
+    }
+
+    pub fn set_away(&self, away: bool) -> Result<(), BotError> {
+        self.frame(ClientFrame::SetPresence { away })
+    }
+
+    /// The rooms the bot is a member of. Needs `rooms:read`.
+    pub async fn rooms(&self) -> Result<Vec<Room>, BotError> {
+        self.inner.shared.get("/rooms").await
+    }
+
+    /// Join a room. Tokens need `rooms:post` for it.
+    pub async fn join(&self, room_id: Uuid) -> Result<(), BotError> {
+        let shared = &self.inner.shared;
+        let response = shared
+            .http
+            .post(format!("{}/rooms/{room_id}/join", shared.base_url))
+            .bearer_auth(&shared.token)
+            .send()
+            .await?;
+        check(response).await?;
+        // Start backfill from here, not the start of the room's history.
+        if let Ok(latest) = shared.latest_seq(room_id).await {
+            shared.seen.lock().unwrap().entry(room_id).or_insert(latest);
+        }
+        Ok(())
+    }
+
+    /// Route messages to `commands`. Listening starts as soon as this is
+    /// called, so nothing is missed between then and the future being polled.
+    pub fn run(&self, commands: Commands) -> impl Future<Output = Result<(), BotError>> + Send {
+        let bot = self.clone();
+        let mut messages = Box::pin(self.messages());
+        async move {
+            while let Some(message) = messages.next().await {
+                commands.dispatch(&bot, message);
+            }
+            Err(BotError::Closed)
+        }
+    }
+}
+
+impl Shared {
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, BotError> {
+        let response = self
+            .http
+            .get(format!("{}{path}", self.base_url))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    async fn open(&self) -> Result<Socket, BotError> {
+        let (socket, _) = tokio_tungstenite::connect_async(self.ws_url.as_str()).await?;
+        Ok(socket)
+    }
+
+    async fn latest_seq(&self, room_id: Uuid) -> Result<i64, BotError> {
+        let latest: Vec<ChatMessage> = self
+            .get(&format!("/rooms/{room_id}/messages?limit=1"))
+            .await?;
+        Ok(latest.last().map_or(0, |message| message.seq))
+    }
+
+    /// Replay what was posted in our rooms while we were away, and note where
+    /// rooms we haven't heard from yet are at. Tokens without `rooms:read`
+    /// can't look, so they go without.
+    async fn sync(&self) -> Result<(), BotError> {
+        let rooms: Vec<Room> = match self.get("/rooms").await {
+            Ok(rooms) => rooms,
+            Err(BotError::Api { status: 403, .. }) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for room in rooms {
+            let seen = self.seen.lock().unwrap().get(&room.room_id).copied();
+            match seen {
+                Some(seq) => {
+                    for message in self.missed(room.room_id, seq).await? {
+                        self.publish(ServerEvent::Message(message));
+                    }
+                }
+                None => {
+                    let latest = self.latest_seq(room.room_id).await?;
+                    self.seen
+                        .lock()
+                        .unwrap()
+                        .entry(room.room_id)
+                        .or_insert(latest);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages in `room_id` after `after`, oldest first.
+    async fn missed(&self, room_id: Uuid, after: i64) -> Result<Vec<ChatMessage>, BotError> {
+        let mut missed = Vec::new();
+        let mut before = None;
+        loop {
+            let mut path = format!("/rooms/{room_id}/messages?limit={BACKFILL_PAGE_SIZE}");
+            if let Some(before) = before {
+                path.push_str(&format!("&before={before}"));
+            }
+            let page: Vec<ChatMessage> = self.get(&path).await?;
+            let Some(oldest) = page.first().map(|message| message.seq) else {
+                break;
+            };
+            let full = page.len() == BACKFILL_PAGE_SIZE;
+            missed.extend(
+                page.into_iter()
+                    .rev()
+                    .take_while(|message| message.seq > after),
+            );
+            if oldest <= after || !full || missed.len() >= MAX_BACKFILL {
+                break;
+            }
+            before = Some(oldest);
+        }
+        missed.truncate(MAX_BACKFILL);
+        missed.reverse();
+        Ok(missed)
+    }
+
+    /// Hand an event to every stream, dropping messages we've already seen
+    /// so backfill and the live socket can overlap.
+    fn publish(&self, event: ServerEvent) {
+        if let ServerEvent::Message(message) = &event {
+            let mut seen = self.seen.lock().unwrap();
+            let last = seen.entry(message.room_id).or_insert(0);
+            if message.seq <= *last {
+                return;
+            }
+            *last = message.seq;
+        }
+        let _ = self.events.send(Event::Server(event));
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+        let event = if connected {
+            Event::Connected
+        } else {
+            Event::Disconnected
+        };
+        let _ = self.events.send(event);
+    }
+}
+
+async fn check(response: Response) -> Result<Response, BotError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    Err(BotError::Api {
+        status: status.as_u16(),
+        message: body["error"]
+            .as_str()
+            .unwrap_or("unknown error")
+            .to_string(),
+    })
+}
+
+/// Keep the bot connected until it's dropped, backing off between failed
+/// attempts.
+async fn maintain(
+    shared: Arc<Shared>,
+    socket: Socket,
+    mut frames: mpsc::UnboundedReceiver<ClientFrame>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    if let Err(e) = shared.sync().await {
+        log::warn!("failed to sync rooms: {e}");
+    }
+    let mut socket = Some(socket);
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let current = match socket.take() {
+            Some(socket) => socket,
+            None => match reconnect(&shared).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    log::warn!("failed to reconnect to radon: {e}");
+                    if sleep(&mut shutdown, backoff).await {
+                        return;
+                    }
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            },
+        };
+        backoff = MIN_BACKOFF;
+
+        shared.set_connected(true);
+        let shut_down = pump(&shared, current, &mut frames, &mut shutdown).await;
+        shared.set_connected(false);
+        if shut_down || sleep(&mut shutdown, backoff).await {
+            return;
+        }
+    }
+}
+
+async fn reconnect(shared: &Shared) -> Result<Socket, BotError> {
+    let socket = shared.open().await?;
+    shared.sync().await?;
+    Ok(socket)
+}
+
+/// Sleep for `duration`, returning early with `true` if the bot is dropped.
+async fn sleep(shutdown: &mut watch::Receiver<bool>, duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => false,
+        _ = shutdown.changed() => true,
+    }
+}
+
+/// Shuttle events in and frames out until the socket drops, returning
+/// whether that's because the bot was dropped.
+async fn pump(
+    shared: &Shared,
+    socket: Socket,
+    frames: &mut mpsc::UnboundedReceiver<ClientFrame>,
+    shutdown: &mut watch::Receiver<bool>,
+) -> bool {
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(event) => shared.publish(event),
+                    Err(e) => log::debug!("ignoring event radon sent: {e}"),
+                },
+                Some(Ok(Message::Close(_))) | None => return false,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    log::warn!("lost connection to radon: {e}");
+                    return false;
+                }
+            },
+            frame = frames.recv() => {
+                let Some(frame) = frame else {
+                    return true;
+                };
+                let text = serde_json::to_string(&frame).expect("frames serialize");
+                if let Err(e) = sink.send(Message::Text(text)).await {
+                    log::warn!("lost connection to radon: {e}");
+                    return false;
+                }
+            }
+            _ = shutdown.changed() => {
+                let _ = sink.close().await;
+                return true;
+            }
+        }
+    }
+}
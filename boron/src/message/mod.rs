@@ -0,0 +1,211 @@
+//! The parts of radon's wire format a bot needs. Mirrors `xenon::message`,
+//! minus the events only a full client cares about.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+    Join,
+    Leave,
+    Text,
+    Action,
+    System,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub message_id: i64,
+    pub room_id: Uuid,
+    pub seq: i64,
+    pub kind: MessageType,
+    pub user_id: Option<Uuid>,
+    pub from: Option<String>,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub edited_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub upload_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub mime: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: i64,
+    pub user_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    User,
+    Here,
+    Room,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MentionInfo {
+    pub kind: MentionKind,
+    pub message: ChatMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Room {
+    pub room_id: Uuid,
+    pub name: String,
+    pub topic: String,
+}
+
+/// What `GET /me` says about the account a token belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Me {
+    pub user_id: Uuid,
+    pub username: String,
+    pub is_bot: bool,
+    /// `None` when connected with a session rather than an API token.
+    pub scopes: Option<Vec<String>>,
+}
+
+/// Frames we send to radon over `/ws`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Send {
+        room_id: Uuid,
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parent_id: Option<i64>,
+    },
+    SetPresence {
+        away: bool,
+    },
+    Typing {
+        room_id: Uuid,
+        typing: bool,
+    },
+    MarkRead {
+        room_id: Uuid,
+        seq: i64,
+    },
+    React {
+        message_id: i64,
+        emoji: String,
+    },
+    Unreact {
+        message_id: i64,
+        emoji: String,
+    },
+}
+
+/// Events radon sends us over `/ws`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Message(ChatMessage),
+    MessageEdited {
+        room_id: Uuid,
+        message_id: i64,
+        text: String,
+        edited_at: NaiveDateTime,
+    },
+    MessageDeleted {
+        room_id: Uuid,
+        message_id: i64,
+        deleted_by: Uuid,
+    },
+    ReactionAdded {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    ReactionRemoved {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    Topic {
+        room_id: Uuid,
+        topic: String,
+        set_by: String,
+    },
+    MemberJoined {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+    },
+    MemberKicked {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+    },
+    MemberBanned {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+    },
+    Mention(MentionInfo),
+    Typing {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        typing: bool,
+    },
+    Throttled {
+        scope: String,
+        retry_after_ms: u64,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+    /// Anything newer than this crate understands.
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a bot sees: radon's events plus changes to the connection itself.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The websocket is up. Anything missed while it was down has already
+    /// been replayed as [`ServerEvent::Message`]s.
+    Connected,
+    /// The websocket dropped; the bot is reconnecting.
+    Disconnected,
+    Server(ServerEvent),
+}
+
+/// A reaction being added, pulled out of [`ServerEvent::ReactionAdded`].
+#[derive(Debug, Clone)]
+pub struct ReactionEvent {
+    pub room_id: Uuid,
+    pub message_id: i64,
+    pub emoji: String,
+    pub user_id: Uuid,
+    pub username: String,
+}
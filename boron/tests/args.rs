@@ -0,0 +1,57 @@
+use boron::{Args, ArgsError, Commands};
+
+#[test]
+fn splits_on_whitespace() {
+    let args = Args::parse("  one two\tthree ").unwrap();
+    assert_eq!(args.iter().collect::<Vec<_>>(), ["one", "two", "three"]);
+    assert_eq!(args.rest(), "one two\tthree");
+}
+
+#[test]
+fn quotes_group_words() {
+    let args = Args::parse(r#"say "hello world" 'it''s' """#).unwrap();
+    assert_eq!(
+        args.iter().collect::<Vec<_>>(),
+        ["say", "hello world", "its", ""]
+    );
+}
+
+#[test]
+fn backslashes_escape() {
+    let args = Args::parse(r#"a\ b "c\"d" 'e\f'"#).unwrap();
+    assert_eq!(args.iter().collect::<Vec<_>>(), ["a b", "c\"d", "e\\f"]);
+}
+
+#[test]
+fn rejects_unbalanced_input() {
+    assert_eq!(Args::parse("\"open"), Err(ArgsError::UnclosedQuote));
+    assert_eq!(Args::parse("trailing\\"), Err(ArgsError::TrailingEscape));
+}
+
+#[test]
+fn typed_access() {
+    let args = Args::parse("3 four").unwrap();
+    assert_eq!(args.parse_at::<i64>(0), Ok(3));
+    assert_eq!(
+        args.parse_at::<i64>(1),
+        Err(ArgsError::Invalid {
+            index: 1,
+            value: "four".to_string()
+        })
+    );
+    assert_eq!(args.required(2), Err(ArgsError::Missing(2)));
+    assert_eq!(ArgsError::Missing(2).to_string(), "argument 3 is missing");
+}
+
+#[test]
+fn parses_prefixes() {
+    let commands = Commands::new();
+    assert_eq!(commands.parse("!echo hi there"), Some(("echo", "hi there")));
+    assert_eq!(commands.parse("/echo"), Some(("echo", "")));
+    assert_eq!(commands.parse("! echo"), None);
+    assert_eq!(commands.parse("echo"), None);
+
+    let commands = Commands::new().prefixes([".", "bot:"]);
+    assert_eq!(commands.parse("bot:ping"), Some(("ping", "")));
+    assert_eq!(commands.parse("!ping"), None);
+}
@@ -0,0 +1,185 @@
+//! Runs a bot against a real radon, served in-process on a free port. Needs a
+//! postgres to migrate and write to, so these are ignored by default. Run
+//! them with:
+//!
+//! ```sh
+//! DATABASE_URL=postgres://localhost/radon_test cargo test -p boron -- --ignored
+//! ```
+
+use std::{env, net::TcpListener, sync::Arc, time::Duration};
+
+use boron::{Bot, Commands, Event};
+use futures::StreamExt;
+use radon::{
+    api::AppState,
+    config::{RateLimit, ServerConfig},
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use uuid::Uuid;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+/// Start radon, returning its base url.
+async fn start_radon() -> String {
+    let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for these tests");
+    let db = PgPoolOptions::new().connect(&url).await.unwrap();
+    radon::MIGRATOR.run(&db).await.unwrap();
+
+    let mut config = ServerConfig {
+        profile: "dev".to_string(),
+        ..Default::default()
+    };
+    let unlimited = RateLimit {
+        burst: 0,
+        per_minute: 0,
+    };
+    config.rate_limits.register = unlimited;
+    config.rate_limits.login = unlimited;
+    let state = Arc::new(AppState::new(config, db).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(radon::api::serve(state, listener));
+    format!("http://{addr}")
+}
+
+fn unique(name: &str) -> String {
+    format!("{name}_{}", &Uuid::new_v4().simple().to_string()[..8])
+}
+
+async fn call(request: reqwest::RequestBuilder) -> Value {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+    assert!(status.is_success(), "radon returned {status}: {body}");
+    body
+}
+
+/// Register someone and log them in, returning their access token.
+async fn register(http: &Client, server: &str, username: &str) -> String {
+    let user = json!({ "username": username, "password": PASSWORD });
+    call(http.post(format!("{server}/users")).json(&user)).await;
+    let session = call(http.post(format!("{server}/login")).json(&user)).await;
+    session["access_token"].as_str().unwrap().to_string()
+}
+
+/// A room owned by a fresh user, and a bot of theirs with a token scoped to
+/// it. Returns the owner's session token, the room, and the bot's token.
+async fn room_with_bot(http: &Client, server: &str) -> (String, Uuid, String) {
+    let session = register(http, server, &unique("owner")).await;
+
+    let room = call(
+        http.post(format!("{server}/rooms"))
+            .bearer_auth(&session)
+            .json(&json!({ "name": unique("room") })),
+    )
+    .await;
+    let room_id: Uuid = room["room_id"].as_str().unwrap().parse().unwrap();
+
+    let bot = call(
+        http.post(format!("{server}/me/bots"))
+            .bearer_auth(&session)
+            .json(&json!({ "username": unique("echo") })),
+    )
+    .await;
+    let token = call(
+        http.post(format!("{server}/me/tokens"))
+            .bearer_auth(&session)
+            .json(&json!({
+                "name": "tests",
+                "bot_id": bot["user_id"],
+                "scopes": ["rooms:read", "rooms:post"],
+                "rooms": [room_id],
+            })),
+    )
+    .await;
+
+    (
+        session,
+        room_id,
+        token["token"].as_str().unwrap().to_string(),
+    )
+}
+
+fn echo() -> Commands {
+    Commands::new().command("echo", "Say it back", |ctx, args| async move {
+        ctx.reply(args.rest())
+    })
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres in DATABASE_URL"]
+async fn echo_bot_replies_in_thread() {
+    let server = start_radon().await;
+    let http = Client::new();
+    let (session, room_id, token) = room_with_bot(&http, &server).await;
+
+    let bot = Bot::connect(&server, &token).await.unwrap();
+    assert!(bot.me().is_bot);
+    bot.join(room_id).await.unwrap();
+    tokio::spawn(bot.run(echo()));
+
+    let owner = Bot::connect(&server, &session).await.unwrap();
+    assert!(!owner.me().is_bot);
+    let mut messages = owner.messages();
+    owner.send(room_id, "!echo hello \"big world\"").unwrap();
+
+    let bot_id = bot.me().user_id;
+    let reply = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let message = messages.next().await.unwrap();
+            if message.user_id == Some(bot_id) && message.parent_id.is_some() {
+                return message;
+            }
+        }
+    })
+    .await
+    .expect("the bot never replied");
+    assert_eq!(reply.text, "hello \"big world\"");
+    assert_eq!(reply.room_id, room_id);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres in DATABASE_URL"]
+async fn help_lists_commands() {
+    let server = start_radon().await;
+    let http = Client::new();
+    let (session, room_id, token) = room_with_bot(&http, &server).await;
+
+    let bot = Bot::connect(&server, &token).await.unwrap();
+    bot.join(room_id).await.unwrap();
+    tokio::spawn(bot.run(echo()));
+
+    let owner = Bot::connect(&server, &session).await.unwrap();
+    let mut events = owner.events();
+    owner.send(room_id, "!help").unwrap();
+
+    let bot_id = bot.me().user_id;
+    let help = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Event::Server(boron::ServerEvent::Message(message)) =
+                events.next().await.unwrap()
+            {
+                if message.user_id == Some(bot_id) && message.parent_id.is_some() {
+                    return message.text;
+                }
+            }
+        }
+    })
+    .await
+    .expect("the bot never answered");
+    assert!(help.contains("!echo - Say it back"), "{help}");
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres in DATABASE_URL"]
+async fn bad_tokens_are_refused() {
+    let server = start_radon().await;
+    let err = Bot::connect(&server, "radon_nope").await.err().unwrap();
+    assert!(
+        matches!(err, boron::BotError::Api { status: 401, .. }),
+        "{err}"
+    );
+}
@@ -0,0 +1,17 @@
+create table "pins" (
+    message_id bigint    primary key references "messages" (message_id) on delete cascade,
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    pinned_by  uuid      references "users" (user_id) on delete set null,
+    pinned_at  timestamp not null
+);
+
+create index pins_room_id_idx on "pins" (room_id, pinned_at desc);
+
+create table "bookmarks" (
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    message_id bigint    not null references "messages" (message_id) on delete cascade,
+    created_at timestamp not null,
+    primary key (user_id, message_id)
+);
+
+create index bookmarks_user_id_idx on "bookmarks" (user_id, created_at desc);
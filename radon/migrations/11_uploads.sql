@@ -0,0 +1,27 @@
+-- One row per stored file. Identical uploads share a blob.
+create table "blobs" (
+    sha256     text      primary key,
+    size       bigint    not null,
+    mime       text      not null,
+    created_at timestamp not null
+);
+
+create table "uploads" (
+    upload_id  uuid      primary key,
+    user_id    uuid      references "users" (user_id) on delete set null,
+    sha256     text      not null references "blobs" (sha256),
+    filename   text      not null,
+    size       bigint    not null,
+    mime       text      not null,
+    created_at timestamp not null
+);
+
+create index uploads_user_id_idx on "uploads" (user_id);
+
+create table "message_attachments" (
+    message_id bigint not null references "messages" (message_id) on delete cascade,
+    upload_id  uuid   not null references "uploads" (upload_id) on delete cascade,
+    primary key (message_id, upload_id)
+);
+
+create index message_attachments_upload_id_idx on "message_attachments" (upload_id);
@@ -0,0 +1,9 @@
+alter table "users" add column is_admin boolean not null default false;
+
+-- guest, member, moderator, admin or owner.
+alter table "room_members" add column role text not null default 'member';
+
+update "room_members" m
+set role = 'owner'
+from "rooms" r
+where r.room_id = m.room_id and r.created_by = m.user_id;
@@ -0,0 +1,42 @@
+alter table "rooms" add column slow_mode_secs integer not null default 0;
+
+-- A null expiry means until lifted.
+create table "room_bans" (
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    banned_by  uuid      references "users" (user_id) on delete set null,
+    reason     text,
+    created_at timestamp not null,
+    expires_at timestamp,
+    primary key (room_id, user_id)
+);
+
+create table "room_mutes" (
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    muted_by   uuid      references "users" (user_id) on delete set null,
+    reason     text,
+    created_at timestamp not null,
+    expires_at timestamp,
+    primary key (room_id, user_id)
+);
+
+create index room_bans_expires_at_idx on "room_bans" (expires_at) where expires_at is not null;
+create index room_mutes_expires_at_idx on "room_mutes" (expires_at) where expires_at is not null;
+
+-- Every kick, ban, mute and slow mode change, including ones that expired.
+create table "moderation_log" (
+    entry_id   bigserial primary key,
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    action     text      not null,
+    target_id  uuid      references "users" (user_id) on delete set null,
+    actor_id   uuid      references "users" (user_id) on delete set null,
+    reason     text,
+    expires_at timestamp,
+    created_at timestamp not null
+);
+
+create index moderation_log_room_id_idx on "moderation_log" (room_id, created_at desc);
+
+-- Slow mode looks up a member's latest post.
+create index messages_room_user_idx on "messages" (room_id, user_id, created_at desc);
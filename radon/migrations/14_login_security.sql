@@ -0,0 +1,16 @@
+-- Consecutive failed logins since the last success.
+alter table "users" add column failed_logins integer not null default 0;
+alter table "users" add column locked_until timestamp;
+
+create table "login_events" (
+    event_id   bigserial primary key,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    success    boolean   not null,
+    -- Why a failed attempt failed: bad_password or locked.
+    reason     text,
+    ip         text      not null,
+    user_agent text,
+    created_at timestamp not null
+);
+
+create index login_events_user_id_idx on "login_events" (user_id, created_at desc);
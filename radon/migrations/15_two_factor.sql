@@ -0,0 +1,15 @@
+-- Base32 TOTP secret. Set by setup, but only checked at login once
+-- confirmed.
+alter table "users" add column totp_secret text;
+alter table "users" add column totp_enabled boolean not null default false;
+-- The last time step a code was accepted for, so a code can't be replayed.
+alter table "users" add column totp_last_step bigint;
+
+create table "recovery_codes" (
+    code_id   bigserial primary key,
+    user_id   uuid      not null references "users" (user_id) on delete cascade,
+    code_hash text      not null,
+    used_at   timestamp
+);
+
+create index recovery_codes_user_id_idx on "recovery_codes" (user_id);
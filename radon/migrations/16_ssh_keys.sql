@@ -0,0 +1,21 @@
+create table "ssh_keys" (
+    key_id       uuid      primary key,
+    user_id      uuid      not null references "users" (user_id) on delete cascade,
+    -- SHA256 fingerprint, as ssh-keygen -l prints it.
+    fingerprint  text      not null unique,
+    -- The key in OpenSSH format, without its comment.
+    public_key   text      not null,
+    name         text      not null,
+    created_at   timestamp not null,
+    last_used_at timestamp
+);
+
+create index ssh_keys_user_id_idx on "ssh_keys" (user_id);
+
+-- Nonces handed out for key logins. Each is used at most once.
+create table "ssh_challenges" (
+    challenge_id uuid      primary key,
+    user_id      uuid      not null references "users" (user_id) on delete cascade,
+    nonce        text      not null,
+    expires_at   timestamp not null
+);
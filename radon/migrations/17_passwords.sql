@@ -0,0 +1,15 @@
+-- Tokens issued before this are no longer accepted.
+alter table "users" add column sessions_valid_after timestamp;
+
+-- Single use password reset tokens issued by server admins. Only a hash of
+-- the token is kept.
+create table "password_resets" (
+    token_hash text      primary key,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    created_by uuid      references "users" (user_id) on delete set null,
+    created_at timestamp not null,
+    expires_at timestamp not null,
+    used_at    timestamp
+);
+
+create index password_resets_user_id_idx on "password_resets" (user_id);
@@ -0,0 +1,26 @@
+-- Bots are users run by a program on behalf of the human who owns them. They
+-- have no usable password and act only through API tokens.
+alter table "users" add column is_bot boolean not null default false;
+alter table "users" add column owner_id uuid references "users" (user_id) on delete cascade;
+
+-- Long lived bearer tokens for scripts and bots. Only a hash of the token is
+-- kept; it is shown once when created.
+create table "api_tokens" (
+    token_id     uuid      primary key,
+    -- Who the token acts as: the creator, or one of their bots.
+    user_id      uuid      not null references "users" (user_id) on delete cascade,
+    created_by   uuid      not null references "users" (user_id) on delete cascade,
+    name         text      not null,
+    token_hash   text      not null unique,
+    -- The start of the token, to tell tokens apart in listings.
+    prefix       text      not null,
+    scopes       text[]    not null,
+    -- Rooms the token may post in.
+    rooms        uuid[]    not null default '{}',
+    created_at   timestamp not null,
+    expires_at   timestamp,
+    last_used_at timestamp,
+    revoked_at   timestamp
+);
+
+create index api_tokens_created_by_idx on "api_tokens" (created_by);
@@ -0,0 +1,18 @@
+-- The name a message was posted under when it isn't its author's username,
+-- as incoming webhooks can set.
+alter table "messages" add column display_name text;
+
+-- URLs that post into a room as a bot. Only a hash of the secret token is
+-- kept.
+create table "incoming_webhooks" (
+    hook_id      uuid      primary key,
+    room_id      uuid      not null references "rooms" (room_id) on delete cascade,
+    bot_id       uuid      not null references "users" (user_id) on delete cascade,
+    created_by   uuid      references "users" (user_id) on delete set null,
+    name         text      not null,
+    token_hash   text      not null,
+    created_at   timestamp not null,
+    last_used_at timestamp
+);
+
+create index incoming_webhooks_room_id_idx on "incoming_webhooks" (room_id);
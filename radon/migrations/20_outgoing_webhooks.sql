@@ -0,0 +1,46 @@
+-- HTTP endpoints told about events in a room. The secret signs every
+-- payload, so unlike other secrets it is kept as is.
+create table "outgoing_webhooks" (
+    hook_id    uuid      primary key,
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    created_by uuid      references "users" (user_id) on delete set null,
+    name       text      not null,
+    url        text      not null,
+    secret     text      not null,
+    events     text[]    not null,
+    created_at timestamp not null
+);
+
+create index outgoing_webhooks_room_id_idx on "outgoing_webhooks" (room_id);
+
+-- One event to send to one hook. Pending deliveries are retried with backoff
+-- until they succeed or run out of attempts and are marked dead.
+create table "webhook_deliveries" (
+    delivery_id     bigserial primary key,
+    hook_id         uuid      not null references "outgoing_webhooks" (hook_id) on delete cascade,
+    event           text      not null,
+    -- The exact body sent, which the signature covers.
+    payload         text      not null,
+    -- pending, delivered or dead.
+    status          text      not null default 'pending',
+    attempts        integer   not null default 0,
+    next_attempt_at timestamp not null,
+    created_at      timestamp not null,
+    delivered_at    timestamp
+);
+
+create index webhook_deliveries_due_idx on "webhook_deliveries" (next_attempt_at)
+    where status = 'pending';
+create index webhook_deliveries_hook_id_idx on "webhook_deliveries" (hook_id, delivery_id);
+
+create table "webhook_attempts" (
+    attempt_id   bigserial primary key,
+    delivery_id  bigint    not null references "webhook_deliveries" (delivery_id) on delete cascade,
+    attempted_at timestamp not null,
+    -- Missing when the request never got a response.
+    status_code  integer,
+    error        text,
+    duration_ms  integer   not null
+);
+
+create index webhook_attempts_delivery_id_idx on "webhook_attempts" (delivery_id);
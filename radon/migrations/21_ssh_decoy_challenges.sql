@@ -0,0 +1,3 @@
+-- Challenges for usernames nobody has are stored without a user, so they
+-- expire and get used up like real ones.
+alter table "ssh_challenges" alter column user_id drop not null;
@@ -0,0 +1,7 @@
+-- Challenges handed out by /login to accounts with two-factor
+-- authentication, by the SHA-256 of the token. Each is used at most once.
+create table "mfa_challenges" (
+    token_hash text      primary key,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    expires_at timestamp not null
+);
@@ -0,0 +1,2 @@
+-- Rooms only members can add people to, with /invite.
+alter table "rooms" add column invite_only boolean not null default false;
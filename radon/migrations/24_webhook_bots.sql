@@ -0,0 +1,2 @@
+-- Set when the bot was made for the hook, so deleting the hook deletes it.
+alter table "incoming_webhooks" add column made_bot boolean not null default false;
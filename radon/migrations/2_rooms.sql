@@ -0,0 +1,26 @@
+create table "rooms" (
+    room_id    uuid primary key default gen_random_uuid(),
+    name       text unique not null,
+    topic      text        not null default '',
+    created_by uuid        not null references "users" (user_id),
+    last_seq   bigint      not null default 0,
+    created_at timestamp   not null
+);
+
+create table "room_members" (
+    room_id   uuid      not null references "rooms" (room_id) on delete cascade,
+    user_id   uuid      not null references "users" (user_id) on delete cascade,
+    joined_at timestamp not null,
+    primary key (room_id, user_id)
+);
+
+create table "messages" (
+    message_id bigserial primary key,
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    user_id    uuid      references "users" (user_id) on delete set null,
+    seq        bigint    not null,
+    kind       text      not null,
+    body       text      not null,
+    created_at timestamp not null,
+    unique (room_id, seq)
+);
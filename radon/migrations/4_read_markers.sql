@@ -0,0 +1 @@
+alter table "room_members" add column last_read_seq bigint not null default 0;
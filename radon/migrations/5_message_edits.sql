@@ -0,0 +1,14 @@
+alter table "messages"
+    add column edited_at  timestamp,
+    add column deleted_at timestamp,
+    add column deleted_by uuid references "users" (user_id) on delete set null;
+
+create table "message_edits" (
+    edit_id       bigserial primary key,
+    message_id    bigint    not null references "messages" (message_id) on delete cascade,
+    previous_body text      not null,
+    edited_by     uuid      references "users" (user_id) on delete set null,
+    edited_at     timestamp not null
+);
+
+create index message_edits_message_id_idx on "message_edits" (message_id);
@@ -0,0 +1,7 @@
+alter table "messages"
+    add column parent_id     bigint references "messages" (message_id) on delete set null,
+    add column reply_count   integer not null default 0,
+    add column last_reply_at timestamp,
+    add column last_reply_by uuid references "users" (user_id) on delete set null;
+
+create index messages_parent_id_idx on "messages" (parent_id);
@@ -0,0 +1,7 @@
+create table "reactions" (
+    message_id bigint    not null references "messages" (message_id) on delete cascade,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    emoji      text      not null,
+    created_at timestamp not null default now(),
+    primary key (message_id, user_id, emoji)
+);
@@ -0,0 +1,12 @@
+create table "mentions" (
+    message_id bigint    not null references "messages" (message_id) on delete cascade,
+    user_id    uuid      not null references "users" (user_id) on delete cascade,
+    room_id    uuid      not null references "rooms" (room_id) on delete cascade,
+    seq        bigint    not null,
+    kind       text      not null,
+    created_at timestamp not null,
+    primary key (message_id, user_id)
+);
+
+create index mentions_user_id_idx on "mentions" (user_id, message_id desc);
+create index mentions_room_id_seq_idx on "mentions" (room_id, user_id, seq);
@@ -0,0 +1,4 @@
+alter table "messages"
+    add column search tsvector generated always as (to_tsvector('english', body)) stored;
+
+create index messages_search_idx on "messages" using gin (search);
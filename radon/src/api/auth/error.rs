@@ -2,12 +2,31 @@ use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 use thiserror::Error;
 
+use crate::{
+    api::{mfa::error::MfaError, ssh_keys::error::SshKeysError},
+    ratelimit::error::Throttled,
+};
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
     #[error("Invalid request")]
     Invalid,
+    #[error("Invalid code")]
+    InvalidCode,
+    #[error("Login expired, start again")]
+    ChallengeExpired,
+    #[error(transparent)]
+    Mfa(#[from] MfaError),
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error(transparent)]
+    SshKeys(#[from] SshKeysError),
+    #[error("Account locked until {0}")]
+    Locked(chrono::NaiveDateTime),
+    #[error(transparent)]
+    RateLimited(#[from] Throttled),
 }
 
 // should I implement into response or just respond_with_json
@@ -19,6 +38,21 @@ impl IntoResponse for AuthError {
                 format!("Database error: {}", e),
             ),
             AuthError::Invalid => (StatusCode::BAD_REQUEST, "Invalid request".to_string()),
+            AuthError::InvalidCode => (StatusCode::UNAUTHORIZED, "Invalid code".to_string()),
+            AuthError::ChallengeExpired => (
+                StatusCode::UNAUTHORIZED,
+                "Login expired, start again".to_string(),
+            ),
+            AuthError::Mfa(e) => return e.into_response(),
+            AuthError::InvalidSignature => {
+                (StatusCode::UNAUTHORIZED, "Invalid signature".to_string())
+            }
+            AuthError::SshKeys(e) => return e.into_response(),
+            AuthError::Locked(until) => (
+                StatusCode::LOCKED,
+                format!("Too many failed logins, account locked until {} UTC", until),
+            ),
+            AuthError::RateLimited(throttled) => return throttled.into_response(),
             // handle other variants
         };
 
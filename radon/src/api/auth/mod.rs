@@ -1,22 +1,50 @@
 pub mod error;
+pub mod keys;
+pub mod sessions;
 pub mod utils;
 
 use rand::Rng;
-use std::{sync::Arc, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use uuid::Uuid;
 
 use axum::{
     body::{Body, Bytes},
-    http::{Response, StatusCode},
-    Extension, Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Response, StatusCode},
+    routing::{get, post},
+    Extension, Json, Router,
 };
 use serde_derive::{Deserialize, Serialize};
-use sqlx::PgExecutor;
 use validator::Validate;
 
 use self::error::AuthError;
+use crate::ratelimit::Scope;
 
-use super::AppState;
+use super::{
+    logins::{self, Failure, LoginClient},
+    mfa, ssh_keys, users, AppState,
+};
+
+const MFA_ISSUER: &str = "radon-mfa";
+/// How long a user has to enter their second factor after their password.
+const MFA_CHALLENGE_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/login", post(login))
+        .route("/login/2fa", post(login_mfa))
+        .route("/login/ssh/challenge", post(ssh_challenge))
+        .route("/login/ssh", post(login_ssh))
+        .route("/.well-known/jwks.json", get(jwks))
+        .with_state(state)
+}
+
+/// The public keys radon tokens are signed with, for other services to
+/// check them.
+#[axum_macros::debug_handler]
+async fn jwks(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(state.keys.jwks().clone())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginRequest {
@@ -30,6 +58,45 @@ pub struct LoginResponse {
     pub username: String,
     pub access_token: String,
     pub refresh_token: String,
+    /// Failed attempts at this account since its last successful login.
+    pub failed_attempts: i64,
+}
+
+/// Handed back by `/login` instead of a session when the account has
+/// two-factor authentication on. Finish logging in at `/login/2fa`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Session(LoginResponse),
+    Challenge(MfaChallenge),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaLoginRequest {
+    pub challenge_token: String,
+    /// A code from the user's authenticator, or a recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshChallengeRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshLoginRequest {
+    pub challenge_id: Uuid,
+    /// The key that signed, in OpenSSH format.
+    pub public_key: String,
+    /// The challenge nonce signed in the `radon-login` namespace, as an
+    /// armored SSHSIG like `ssh-keygen -Y sign` writes.
+    pub signature: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,18 +121,195 @@ pub async fn refresh(
 }
 
 pub async fn login(
-    Extension(state): Extension<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(login_attempt): Json<LoginRequest>,
+) -> Result<(StatusCode, Json<LoginOutcome>), AuthError> {
+    // Per address limits are applied by the rate limit middleware; this
+    // stops a distributed guess at one account.
+    state.limiter.check(
+        Scope::LoginUser,
+        &login_attempt.username,
+        state.config.rate_limits.login,
+    )?;
+
+    // No password this long was ever allowed, and hashing it is expensive.
+    if login_attempt.password.len() > state.config.password_policy.max_length {
+        return Err(AuthError::Invalid);
+    }
+
+    let client = LoginClient::new(connect_info, &headers);
+    let user_id = login_attempt.clone().verify(&state, &client).await?;
+
+    if mfa::is_enabled(&state.db, user_id).await? {
+        let challenge_token = utils::make_jwt(
+            user_id,
+            MFA_ISSUER.to_string(),
+            &state.keys,
+            MFA_CHALLENGE_EXPIRY,
+        )
+        .unwrap();
+        let expires_at = chrono::Utc::now().naive_utc()
+            + chrono::Duration::from_std(MFA_CHALLENGE_EXPIRY).unwrap();
+        mfa::store_challenge(&state.db, user_id, &challenge_token, expires_at).await?;
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(LoginOutcome::Challenge(MfaChallenge {
+                mfa_required: true,
+                challenge_token,
+            })),
+        ));
+    }
+
+    let failed_attempts = logins::record_success(&state.db, user_id, &client).await?;
+    let session = issue_session(&state, user_id, login_attempt.username, failed_attempts);
+    Ok((StatusCode::OK, Json(LoginOutcome::Session(session))))
+}
+
+/// The second step of logging in to an account with two-factor
+/// authentication. Wrong codes count towards locking the account just like
+/// wrong passwords. A challenge logs in once, and not at all once the user's
+/// sessions have been revoked.
+#[axum_macros::debug_handler]
+pub async fn login_mfa(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<MfaLoginRequest>,
+) -> Result<(StatusCode, Json<LoginResponse>), AuthError> {
+    let claims = utils::decode_jwt(&req.challenge_token, &state.keys)
+        .map_err(|_| AuthError::ChallengeExpired)?;
+    if claims.iss != MFA_ISSUER {
+        return Err(AuthError::ChallengeExpired);
+    }
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AuthError::ChallengeExpired)?;
+    if state.revocations.is_revoked(user_id, claims.iat)
+        || !mfa::challenge_pending(&state.db, &req.challenge_token).await?
+    {
+        return Err(AuthError::ChallengeExpired);
+    }
+
+    state.limiter.check(
+        Scope::LoginUser,
+        &user_id.to_string(),
+        state.config.rate_limits.login,
+    )?;
+
+    let client = LoginClient::new(connect_info, &headers);
+    if let Some(until) = logins::locked_until(&state.db, user_id).await? {
+        logins::record_failure(&state.db, &state.config, user_id, &client, Failure::Locked).await?;
+        return Err(AuthError::Locked(until));
+    }
+
+    if !mfa::check_code(&state.db, user_id, &req.code).await? {
+        let locked =
+            logins::record_failure(&state.db, &state.config, user_id, &client, Failure::BadCode)
+                .await?;
+        return Err(match locked {
+            Some(until) => AuthError::Locked(until),
+            None => AuthError::InvalidCode,
+        });
+    }
+    // Two right codes raced through with one challenge only log in once.
+    if !mfa::take_challenge(&state.db, &req.challenge_token).await? {
+        return Err(AuthError::ChallengeExpired);
+    }
+
+    let failed_attempts = logins::record_success(&state.db, user_id, &client).await?;
+    let username = users::username(&state.db, user_id)
+        .await?
+        .ok_or(AuthError::Invalid)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(issue_session(&state, user_id, username, failed_attempts)),
+    ))
+}
+
+/// Hand out a challenge for a key login. Each one is a row until it's used or
+/// expires, so callers are limited by address as well as by the username they
+/// ask about.
+#[axum_macros::debug_handler]
+pub async fn ssh_challenge(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<SshChallengeRequest>,
+) -> Result<(StatusCode, Json<ssh_keys::Challenge>), AuthError> {
+    let client = LoginClient::new(connect_info, &headers);
+    state
+        .limiter
+        .check(Scope::Login, &client.ip, state.config.rate_limits.login)?;
+    state.limiter.check(
+        Scope::LoginUser,
+        &req.username,
+        state.config.rate_limits.login,
+    )?;
+
+    let challenge = ssh_keys::challenge(&state.db, &req.username).await?;
+    Ok((StatusCode::OK, Json(challenge)))
+}
+
+/// Log in by signing a challenge with a registered SSH key. This stands in
+/// for both the password and the second factor.
+#[axum_macros::debug_handler]
+pub async fn login_ssh(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<SshLoginRequest>,
 ) -> Result<(StatusCode, Json<LoginResponse>), AuthError> {
-    let user_id = login_attempt.clone().verify(&state.db).await?;
+    let (user_id, nonce) = ssh_keys::take_challenge(&state.db, req.challenge_id).await?;
+    // Challenges for unknown usernames fail the way a wrong key does.
+    let Some(user_id) = user_id else {
+        return Err(AuthError::InvalidSignature);
+    };
+
+    let client = LoginClient::new(connect_info, &headers);
+    if let Some(until) = logins::locked_until(&state.db, user_id).await? {
+        logins::record_failure(&state.db, &state.config, user_id, &client, Failure::Locked).await?;
+        return Err(AuthError::Locked(until));
+    }
+
+    let verified =
+        ssh_keys::verify_signature(&state.db, user_id, &nonce, &req.public_key, &req.signature)
+            .await?;
+    if !verified {
+        let locked =
+            logins::record_failure(&state.db, &state.config, user_id, &client, Failure::BadKey)
+                .await?;
+        return Err(match locked {
+            Some(until) => AuthError::Locked(until),
+            None => AuthError::InvalidSignature,
+        });
+    }
+
+    let failed_attempts = logins::record_success(&state.db, user_id, &client).await?;
+    let username = users::username(&state.db, user_id)
+        .await?
+        .ok_or(AuthError::Invalid)?;
 
+    Ok((
+        StatusCode::OK,
+        Json(issue_session(&state, user_id, username, failed_attempts)),
+    ))
+}
+
+/// Access and refresh tokens for a user who has just proven who they are.
+pub fn issue_session(
+    state: &AppState,
+    user_id: Uuid,
+    username: String,
+    failed_attempts: i64,
+) -> LoginResponse {
     let access_token_expiry = 60 * 60; // 1 hour
     let refresh_token_expiry = 60 * 60 * 24 * 60; // 60 days
 
     let access_jwt = utils::make_jwt(
         user_id,
         "radon-access".to_string(),
-        &state.config.jwt_secret,
+        &state.keys,
         Duration::from_secs(access_token_expiry),
     )
     .unwrap();
@@ -73,41 +317,70 @@ pub async fn login(
     let refresh_jwt = utils::make_jwt(
         user_id,
         "radon-refresh".to_string(),
-        &state.config.jwt_secret,
+        &state.keys,
         Duration::from_secs(refresh_token_expiry),
     )
     .unwrap();
 
-    Ok((
-        StatusCode::OK,
-        Json(LoginResponse {
-            id: user_id.to_string(),
-            username: login_attempt.username,
-            access_token: access_jwt,
-            refresh_token: refresh_jwt,
-        }),
-    ))
+    LoginResponse {
+        id: user_id.to_string(),
+        username,
+        access_token: access_jwt,
+        refresh_token: refresh_jwt,
+        failed_attempts,
+    }
 }
 
 impl LoginRequest {
     // NOTE: normally we wouldn't want to verify the username and password every time,
     // but persistent sessions would have complicated the example.
-    pub async fn verify(self, db: impl PgExecutor<'_> + Send) -> Result<Uuid, AuthError> {
+    //
+    // Failed attempts count towards locking the account. A locked account is
+    // refused before its password is checked, so guessing gets nowhere.
+    pub async fn verify(self, state: &AppState, client: &LoginClient) -> Result<Uuid, AuthError> {
         let maybe_user = sqlx::query!(
             r#"select user_id, password_hash from "users" where username = $1"#,
             self.username
         )
-        .fetch_optional(db)
+        .fetch_optional(&state.db)
         .await?;
 
         if let Some(user) = maybe_user {
-            let verified = crate::api::auth::utils::verify(self.password, user.password_hash)
-                .await
-                .map_err(|_| AuthError::Invalid)?;
+            if let Some(until) = logins::locked_until(&state.db, user.user_id).await? {
+                logins::record_failure(
+                    &state.db,
+                    &state.config,
+                    user.user_id,
+                    client,
+                    Failure::Locked,
+                )
+                .await?;
+                return Err(AuthError::Locked(until));
+            }
+
+            let verified =
+                crate::api::auth::utils::verify(self.password.clone(), user.password_hash.clone())
+                    .await
+                    .map_err(|_| AuthError::Invalid)?;
 
             if verified {
+                if utils::needs_rehash(&user.password_hash, &state.config.argon2) {
+                    spawn_rehash(state, user.user_id, self.password, user.password_hash);
+                }
                 return Ok(user.user_id);
             }
+
+            let locked = logins::record_failure(
+                &state.db,
+                &state.config,
+                user.user_id,
+                client,
+                Failure::BadPassword,
+            )
+            .await?;
+            if let Some(until) = locked {
+                return Err(AuthError::Locked(until));
+            }
         }
 
         // Sleep a random amount of time to avoid leaking existence of a user in timing.
@@ -118,3 +391,35 @@ impl LoginRequest {
         Err(AuthError::Invalid)
     }
 }
+
+/// Replace a password hash made with weaker settings than we use now. This
+/// runs in the background so the login it happens on isn't slowed down.
+fn spawn_rehash(state: &AppState, user_id: Uuid, password: String, old_hash: String) {
+    let db = state.db.clone();
+    let config = state.config.argon2;
+    tokio::spawn(async move {
+        let password_hash = match utils::hash(password, config).await {
+            Ok(password_hash) => password_hash,
+            Err(e) => {
+                tracing::error!("failed to rehash password for {user_id}: {e}");
+                return;
+            }
+        };
+        // Leave it alone if the password changed in the meantime.
+        let res = sqlx::query!(
+            // language=PostgreSQL
+            r#"
+                update "users" set password_hash = $1
+                where user_id = $2 and password_hash = $3
+            "#,
+            password_hash,
+            user_id,
+            old_hash
+        )
+        .execute(&db)
+        .await;
+        if let Err(e) = res {
+            tracing::error!("failed to store rehashed password for {user_id}: {e}");
+        }
+    });
+}
@@ -0,0 +1,68 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// When each user's sessions were last revoked. Tokens are checked on every
+/// request, so this is kept in memory and loaded from the database at
+/// startup.
+#[derive(Debug, Default)]
+pub struct Revocations {
+    valid_after: Mutex<HashMap<Uuid, i64>>,
+}
+
+impl Revocations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn load(&self, db: &PgPool) -> Result<(), sqlx::Error> {
+        let records = sqlx::query!(
+            // language=PostgreSQL
+            r#"
+                select user_id, sessions_valid_after as "valid_after!"
+                from "users"
+                where sessions_valid_after is not null
+            "#
+        )
+        .fetch_all(db)
+        .await?;
+
+        let mut valid_after = self.valid_after.lock().unwrap();
+        for record in records {
+            valid_after.insert(record.user_id, record.valid_after.timestamp());
+        }
+        Ok(())
+    }
+
+    /// Whether a token issued to `user_id` at `issued_at` has been revoked.
+    pub fn is_revoked(&self, user_id: Uuid, issued_at: i64) -> bool {
+        self.valid_after
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .is_some_and(|valid_after| issued_at < *valid_after)
+    }
+
+    /// Reject every token issued to `user_id` before `at`.
+    pub async fn revoke(
+        &self,
+        db: &PgPool,
+        user_id: Uuid,
+        at: NaiveDateTime,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"update "users" set sessions_valid_after = $1 where user_id = $2"#,
+            at,
+            user_id
+        )
+        .execute(db)
+        .await?;
+        self.valid_after
+            .lock()
+            .unwrap()
+            .insert(user_id, at.timestamp());
+        Ok(())
+    }
+}
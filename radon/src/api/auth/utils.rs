@@ -1,29 +1,52 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use chrono::Utc;
-use jsonwebtoken::{
-    decode, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
-};
+use jsonwebtoken::{decode, decode_header, encode, Header, TokenData, Validation};
 use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::task;
 
 use argon2::password_hash::SaltString;
-use argon2::{password_hash, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::{
+    password_hash, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+};
 use uuid::Uuid;
 
+use crate::config::Argon2Config;
+
+use super::keys::JwtKeys;
+
+/// The most memory `suggest_params` will try.
+const MAX_SUGGESTED_MEMORY_KIB: u32 = 1024 * 1024;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    iss: String,
-    iat: i64,
-    exp: i64,
-    sub: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub sub: String,
 }
 
-pub async fn hash(password: String) -> Result<String> {
+fn argon2(config: &Argon2Config) -> Result<Argon2<'static>> {
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )
+    .map_err(|e| anyhow::anyhow!(e).context("invalid argon2 parameters"))?;
+    Ok(Argon2::new(
+        argon2::Algorithm::Argon2id,
+        Version::V0x13,
+        params,
+    ))
+}
+
+pub async fn hash(password: String, config: Argon2Config) -> Result<String> {
     task::spawn_blocking(move || {
         let salt = SaltString::generate(rand::thread_rng());
-        Ok(Argon2::default()
+        Ok(argon2(&config)?
             .hash_password(password.as_bytes(), &salt)
             .map_err(|e| anyhow::anyhow!(e).context("failed to hash password"))?
             .to_string())
@@ -49,10 +72,90 @@ pub async fn verify(password: String, hash: String) -> anyhow::Result<bool> {
     .context("panic in verify()")?
 }
 
+/// Whether `hash` was made with weaker settings than `config`, or isn't
+/// Argon2id at all, and so should be replaced the next time we have the
+/// password.
+pub fn needs_rehash(hash: &str, config: &Argon2Config) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return true;
+    };
+    if hash.algorithm != argon2::Algorithm::Argon2id.ident()
+        || hash.version != Some(Version::V0x13 as u32)
+    {
+        return true;
+    }
+    match Params::try_from(&hash) {
+        Ok(params) => {
+            params.m_cost() < config.memory_kib
+                || params.t_cost() < config.iterations
+                || params.p_cost() < config.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+/// Hash a random token (API tokens, reset tokens, webhook tokens) for
+/// storage. They're long and random, so unlike passwords a plain SHA-256 is
+/// enough.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// How long one hash takes with `config` on this machine.
+pub fn time_hash(config: &Argon2Config) -> Result<Duration> {
+    let argon2 = argon2(config)?;
+    let salt = SaltString::generate(rand::thread_rng());
+    let start = Instant::now();
+    argon2
+        .hash_password(b"correct horse battery staple", &salt)
+        .map_err(|e| anyhow::anyhow!(e).context("failed to hash password"))?;
+    Ok(start.elapsed())
+}
+
+/// Find settings that take about `target` to hash with on this machine,
+/// returning them and how long they took. Memory is raised first, since
+/// that's what makes cracking on GPUs expensive, then iterations. Never
+/// suggests less than the defaults.
+pub fn suggest_params(target: Duration, parallelism: u32) -> Result<(Argon2Config, Duration)> {
+    let mut best = Argon2Config {
+        parallelism,
+        ..Argon2Config::default()
+    };
+    let mut best_time = time_hash(&best)?;
+
+    while best.memory_kib < MAX_SUGGESTED_MEMORY_KIB {
+        let candidate = Argon2Config {
+            memory_kib: best.memory_kib * 2,
+            ..best
+        };
+        let time = time_hash(&candidate)?;
+        if time > target {
+            break;
+        }
+        best = candidate;
+        best_time = time;
+    }
+
+    loop {
+        let candidate = Argon2Config {
+            iterations: best.iterations + 1,
+            ..best
+        };
+        let time = time_hash(&candidate)?;
+        if time > target {
+            break;
+        }
+        best = candidate;
+        best_time = time;
+    }
+
+    Ok((best, best_time))
+}
+
 pub fn make_jwt(
     user_id: Uuid,
     issuer: String,
-    user_secret: &str,
+    keys: &JwtKeys,
     expires_in: Duration,
 ) -> Result<String> {
     let now = Utc::now();
@@ -64,23 +167,90 @@ pub fn make_jwt(
         exp: expiration.timestamp(),
         sub: user_id.to_string(),
     };
-    let header = Header::new(jsonwebtoken::Algorithm::HS256);
-    let encoding_key = EncodingKey::from_secret(user_secret.as_ref());
-    let token = encode(&header, &claims, &encoding_key).unwrap();
+    let (kid, algorithm, encoding_key) = keys.signing();
+    let mut header = Header::new(algorithm);
+    header.kid = Some(kid.to_string());
+    let token = encode(&header, &claims, encoding_key).context("failed to sign token")?;
     Ok(token)
 }
 
-pub fn validate_jwt(token: &str, secret: &str) -> Result<(String, String)> {
+pub fn validate_jwt(token: &str, keys: &JwtKeys) -> Result<(String, String)> {
     // (user_id, issuer)
-    let mut validation = Validation::default();
-    validation.algorithms = vec![Algorithm::HS256];
+    let claims = decode_jwt(token, keys)?;
+    Ok((claims.iss, claims.sub))
+}
+
+/// Check a token's signature and expiry and return all of its claims. The
+/// token is checked against the key its `kid` names, and only with that
+/// key's algorithm.
+pub fn decode_jwt(token: &str, keys: &JwtKeys) -> Result<Claims> {
+    let header = decode_header(token)?;
+    let (algorithm, decoding_key) = keys
+        .verifying(header.kid.as_deref())
+        .context("token signed with an unknown key")?;
+
+    let mut validation = Validation::new(*algorithm);
     validation.leeway = 0;
 
-    let decoding_key = DecodingKey::from_secret(secret.as_ref());
-    let token_data: TokenData<Claims> = decode(token, &decoding_key, &validation)?;
-    println!("{:?}", token_data);
-    let issuer = token_data.claims.iss;
-    let user_id = token_data.claims.sub;
+    let token_data: TokenData<Claims> = decode(token, decoding_key, &validation)?;
+    Ok(token_data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough to hash in tests.
+    const WEAK: Argon2Config = Argon2Config {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[tokio::test]
+    async fn rehash_only_when_weaker_than_config() {
+        let hash = hash("hunter22".to_string(), WEAK).await.unwrap();
+        assert!(verify("hunter22".to_string(), hash.clone()).await.unwrap());
+        assert!(!needs_rehash(&hash, &WEAK));
+
+        let stronger = [
+            Argon2Config {
+                memory_kib: 128,
+                ..WEAK
+            },
+            Argon2Config {
+                iterations: 2,
+                ..WEAK
+            },
+            Argon2Config {
+                parallelism: 2,
+                ..WEAK
+            },
+        ];
+        for config in stronger {
+            assert!(needs_rehash(&hash, &config), "{config:?}");
+        }
+
+        // Lowering the settings doesn't make good hashes worse.
+        let weaker = Argon2Config {
+            memory_kib: 32,
+            ..WEAK
+        };
+        assert!(!needs_rehash(&hash, &weaker));
+    }
+
+    #[test]
+    fn rehash_other_algorithms() {
+        let params = Params::new(WEAK.memory_kib, WEAK.iterations, WEAK.parallelism, None).unwrap();
+        let salt = SaltString::generate(rand::thread_rng());
+        let argon2i = Argon2::new(argon2::Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"hunter22", &salt)
+            .unwrap()
+            .to_string();
+        assert!(needs_rehash(&argon2i, &WEAK));
 
-    Ok((issuer, user_id))
+        let bcrypt = "$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW";
+        assert!(needs_rehash(bcrypt, &WEAK));
+        assert!(needs_rehash("not a hash", &WEAK));
+    }
 }
@@ -0,0 +1,44 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BookmarksError {
+    #[error("Message not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    Forbidden,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl BookmarksError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BookmarksError::NotFound => "not_found",
+            BookmarksError::Forbidden => "forbidden",
+            BookmarksError::Database(_) => "internal",
+        }
+    }
+}
+
+impl IntoResponse for BookmarksError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            BookmarksError::NotFound => (StatusCode::NOT_FOUND, "Message not found".to_string()),
+            BookmarksError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            BookmarksError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
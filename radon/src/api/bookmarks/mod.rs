@@ -0,0 +1,166 @@
+pub mod error;
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use error::BookmarksError;
+use uuid::Uuid;
+
+use crate::{
+    message::{Bookmark, ServerEvent},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{messages, rooms, AppState};
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/bookmarks", get(fetch_bookmarks))
+        .route(
+            "/messages/:message_id/bookmark",
+            put(bookmark_handler).delete(unbookmark_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+/// Save a message for later. Bookmarks are private, so only the user's own
+/// connections hear about it.
+pub async fn add_bookmark(
+    state: &AppState,
+    user_id: Uuid,
+    message_id: i64,
+) -> Result<(), BookmarksError> {
+    let message = messages::load_message(&state.db, message_id)
+        .await?
+        .filter(|message| !message.deleted)
+        .ok_or(BookmarksError::NotFound)?;
+    if !rooms::is_member(&state.db, message.room_id, user_id).await? {
+        return Err(BookmarksError::Forbidden);
+    }
+
+    let created_at = chrono::Utc::now().naive_utc();
+    let added = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "bookmarks"(user_id, message_id, created_at)
+            values ($1, $2, $3)
+            on conflict do nothing
+        "#,
+        user_id,
+        message_id,
+        created_at
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if added {
+        state.clients.send_to_user(
+            user_id,
+            ServerEvent::BookmarkAdded(Bookmark {
+                message,
+                created_at,
+            }),
+        );
+    }
+    Ok(())
+}
+
+pub async fn remove_bookmark(
+    state: &AppState,
+    user_id: Uuid,
+    message_id: i64,
+) -> Result<(), BookmarksError> {
+    let removed = sqlx::query!(
+        r#"delete from "bookmarks" where user_id = $1 and message_id = $2"#,
+        user_id,
+        message_id
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if !removed {
+        return Err(BookmarksError::NotFound);
+    }
+    state
+        .clients
+        .send_to_user(user_id, ServerEvent::BookmarkRemoved { message_id });
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+async fn bookmark_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<StatusCode, BookmarksError> {
+    add_bookmark(&state, user.user_id, message_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn unbookmark_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<StatusCode, BookmarksError> {
+    remove_bookmark(&state, user.user_id, message_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Our saved messages, most recently saved first. Messages from rooms we've
+/// since left are kept but not shown.
+#[axum_macros::debug_handler]
+async fn fetch_bookmarks(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Vec<Bookmark>>), BookmarksError> {
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select b.message_id, b.created_at
+            from "bookmarks" b
+            join "messages" m on m.message_id = b.message_id
+            join "room_members" rm on rm.room_id = m.room_id and rm.user_id = b.user_id
+            where b.user_id = $1 and m.deleted_at is null
+            order by b.created_at desc
+        "#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let ids = records
+        .iter()
+        .map(|record| record.message_id)
+        .collect::<Vec<i64>>();
+    let mut messages = messages::load_messages(&state.db, &ids)
+        .await?
+        .into_iter()
+        .map(|message| (message.message_id, message))
+        .collect::<HashMap<i64, _>>();
+
+    let bookmarks = records
+        .into_iter()
+        .filter_map(|record| {
+            Some(Bookmark {
+                message: messages.remove(&record.message_id)?,
+                created_at: record.created_at,
+            })
+        })
+        .collect::<Vec<Bookmark>>();
+
+    Ok((StatusCode::OK, Json(bookmarks)))
+}
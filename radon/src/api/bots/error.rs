@@ -0,0 +1,41 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BotsError {
+    #[error("Invalid bot name")]
+    Invalid,
+    #[error("Username already taken")]
+    UsernameTaken,
+    #[error("Bot not found")]
+    NotFound,
+    #[error("Internal error")]
+    Internal,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for BotsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            BotsError::Invalid => (StatusCode::BAD_REQUEST, "Invalid bot name".to_string()),
+            BotsError::UsernameTaken => {
+                (StatusCode::CONFLICT, "Username already taken".to_string())
+            }
+            BotsError::NotFound => (StatusCode::NOT_FOUND, "Bot not found".to_string()),
+            BotsError::Internal => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal error".to_string(),
+            ),
+            BotsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
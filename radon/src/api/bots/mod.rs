@@ -0,0 +1,156 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::BotsError;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::middleware::{requires_auth, AuthUser};
+
+use super::{auth::utils, AppState};
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/bots", get(fetch_bots).post(create_bot))
+        .route("/me/bots/:bot_id", delete(delete_bot))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bot {
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBotRequest {
+    username: String,
+}
+
+/// Whether `bot_id` is a bot owned by `owner_id`.
+pub async fn owns(
+    db: impl sqlx::PgExecutor<'_>,
+    owner_id: Uuid,
+    bot_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let owned = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            select exists(
+                select 1 from "users" where user_id = $1 and owner_id = $2 and is_bot
+            ) as "owned!"
+        "#,
+        bot_id,
+        owner_id
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(owned)
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_bots(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Vec<Bot>>), BotsError> {
+    let bots = sqlx::query_as!(
+        Bot,
+        // language=PostgreSQL
+        r#"
+            select user_id, username, created_at
+            from "users"
+            where owner_id = $1 and is_bot
+            order by created_at
+        "#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(bots)))
+}
+
+/// Make a bot owned by `owner_id`. It gets a password nobody knows, so it
+/// can only act through tokens and webhooks its owner sets up.
+pub async fn create(state: &AppState, owner_id: Uuid, username: String) -> Result<Bot, BotsError> {
+    if username.is_empty() || username.chars().count() > 32 {
+        return Err(BotsError::Invalid);
+    }
+
+    let password = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    let password_hash = utils::hash(password, state.config.argon2)
+        .await
+        .map_err(|_| BotsError::Internal)?;
+    let time = chrono::Utc::now().naive_utc();
+
+    let res = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            insert into "users"(username, password_hash, created_at, updated_at, is_bot, owner_id)
+            values ($1, $2, $3, $3, true, $4)
+            returning user_id
+        "#,
+        username,
+        password_hash,
+        time,
+        owner_id
+    )
+    .fetch_one(&state.db)
+    .await;
+    let user_id = match res {
+        Ok(user_id) => user_id,
+        Err(sqlx::Error::Database(dbe)) if dbe.constraint() == Some("user_username_key") => {
+            return Err(BotsError::UsernameTaken)
+        }
+        Err(e) => return Err(BotsError::Database(e)),
+    };
+
+    Ok(Bot {
+        user_id,
+        username,
+        created_at: time,
+    })
+}
+
+#[axum_macros::debug_handler]
+async fn create_bot(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<CreateBotRequest>,
+) -> Result<(StatusCode, Json<Bot>), BotsError> {
+    let bot = create(&state, user.user_id, req.username).await?;
+    Ok((StatusCode::CREATED, Json(bot)))
+}
+
+/// Delete a bot, along with its tokens and memberships. Its messages stay.
+#[axum_macros::debug_handler]
+async fn delete_bot(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(bot_id): Path<Uuid>,
+) -> Result<StatusCode, BotsError> {
+    let deleted = sqlx::query!(
+        r#"delete from "users" where user_id = $1 and owner_id = $2 and is_bot"#,
+        bot_id,
+        user.user_id
+    )
+    .execute(&state.db)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(BotsError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,27 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EventsError {
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for EventsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            EventsError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            EventsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,269 @@
+//! The `/ws` event stream for clients behind proxies that break websockets:
+//! Server-Sent Events on `/events`, and long-polling on `/events/poll`. Both
+//! read from the same registry `/ws` does, and post with
+//! `POST /rooms/:room_id/messages`.
+
+pub mod error;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Json, Router,
+};
+use error::EventsError;
+use futures::{future, stream, Stream, StreamExt};
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    client::{Client, Delivery},
+    message::ServerEvent,
+    middleware::{self, AuthUser},
+};
+
+use super::{users, ws, AppState};
+
+const DEFAULT_POLL_SECS: u64 = 25;
+const MAX_POLL_SECS: u64 = 60;
+/// How long a long-poll client stays connected after a poll returns, so it
+/// doesn't flicker offline between one poll and the next.
+const POLL_LINGER: Duration = Duration::from_secs(10);
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/events", get(event_stream))
+        .route("/events/poll", get(poll_events))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Access token, for `EventSource`, which can't set headers.
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PollQuery {
+    token: Option<String>,
+    /// The `next` of the previous poll. Without it, the poll returns straight
+    /// away with where to start from.
+    since: Option<u64>,
+    /// Seconds to wait for something to happen, at most 60.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolledEvent {
+    pub id: u64,
+    pub event: ServerEvent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Poll {
+    pub events: Vec<PolledEvent>,
+    /// Pass back as `since` next time.
+    pub next: u64,
+    /// Set when events since `since` are gone for good. Refetch whatever
+    /// you show before carrying on from `next`.
+    pub reset: bool,
+}
+
+/// Authenticate with a bearer token or, failing that, `?token=`.
+async fn authenticate(
+    state: &AppState,
+    headers: &HeaderMap,
+    token: Option<&str>,
+) -> Result<AuthUser, EventsError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .or(token)
+        .ok_or(EventsError::Unauthorized)?;
+    middleware::authenticate(state, token)
+        .await
+        .map_err(|_| EventsError::Unauthorized)
+}
+
+/// An event stream's place in the registry, given up `linger` after the
+/// client goes.
+struct Connection {
+    state: Arc<AppState>,
+    user_id: Uuid,
+    client_id: Uuid,
+    rx: mpsc::UnboundedReceiver<Delivery>,
+    linger: Duration,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let (user_id, client_id, linger) = (self.user_id, self.client_id, self.linger);
+        tokio::spawn(async move {
+            tokio::time::sleep(linger).await;
+            ws::disconnect(&state, user_id, client_id).await
+        });
+    }
+}
+
+fn resync() -> ServerEvent {
+    ServerEvent::Error {
+        code: "resync".to_string(),
+        message: "Some events were missed for good, refetch before carrying on".to_string(),
+    }
+}
+
+#[axum_macros::debug_handler]
+async fn event_stream(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, EventsError> {
+    let user = authenticate(&state, &headers, query.token.as_deref()).await?;
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(EventsError::Unauthorized)?;
+    let joined = ws::subscriptions(&state, &user).await?;
+    let rooms = joined.keys().copied().collect();
+
+    // Browsers send this by themselves when they reconnect.
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|id| id.parse::<u64>().ok());
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let token_id = user.token.as_ref().map(|grant| grant.token_id);
+    let client = Client::new(user.user_id, username, addr, token_id, rooms, tx);
+    let connection = Connection {
+        state: state.clone(),
+        user_id: user.user_id,
+        client_id: client.id,
+        rx,
+        linger: Duration::ZERO,
+    };
+    state.clients.connect(client);
+
+    // Connect first and replay second, so nothing falls in between. Live
+    // events the replay already covered are skipped.
+    let (backlog, replayed) = match last_event_id {
+        Some(after) => {
+            let replay = state.clients.replay(user.user_id, &joined, after);
+            let backlog = if replay.complete {
+                replay.events
+            } else {
+                vec![Delivery {
+                    id: replay.latest,
+                    event: resync(),
+                }]
+            };
+            (backlog, replay.latest)
+        }
+        None => (Vec::new(), 0),
+    };
+
+    let live = stream::unfold(connection, |mut connection| async move {
+        let delivery = connection.rx.recv().await?;
+        Some((delivery, connection))
+    })
+    .filter(move |delivery| future::ready(delivery.id > replayed));
+    let events = stream::iter(backlog).chain(live).map(|delivery| {
+        Event::default()
+            .id(delivery.id.to_string())
+            .json_data(&delivery.event)
+            .map_err(axum::Error::new)
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Wait for events after `since`. The client counts as connected while it
+/// polls, and for a little while after.
+#[axum_macros::debug_handler]
+async fn poll_events(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<PollQuery>,
+) -> Result<(StatusCode, Json<Poll>), EventsError> {
+    let user = authenticate(&state, &headers, query.token.as_deref()).await?;
+    let mut latest = state.clients.watch();
+    let Some(mut since) = query.since else {
+        let next = *latest.borrow();
+        return Ok((
+            StatusCode::OK,
+            Json(Poll {
+                events: Vec::new(),
+                next,
+                reset: false,
+            }),
+        ));
+    };
+    let joined = ws::subscriptions(&state, &user).await?;
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(EventsError::Unauthorized)?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let token_id = user.token.as_ref().map(|grant| grant.token_id);
+    let rooms = joined.keys().copied().collect();
+    let client = Client::new(user.user_id, username, addr, token_id, rooms, tx);
+    let _connection = Connection {
+        state: state.clone(),
+        user_id: user.user_id,
+        client_id: client.id,
+        rx,
+        linger: POLL_LINGER,
+    };
+    state.clients.connect(client);
+
+    let timeout = query
+        .timeout
+        .unwrap_or(DEFAULT_POLL_SECS)
+        .min(MAX_POLL_SECS);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout);
+    loop {
+        latest.borrow_and_update();
+        let replay = state.clients.replay(user.user_id, &joined, since);
+        if !replay.events.is_empty() || !replay.complete {
+            let events = replay
+                .events
+                .into_iter()
+                .map(|delivery| PolledEvent {
+                    id: delivery.id,
+                    event: delivery.event,
+                })
+                .collect();
+            return Ok((
+                StatusCode::OK,
+                Json(Poll {
+                    events,
+                    next: replay.latest,
+                    reset: !replay.complete,
+                }),
+            ));
+        }
+        // Nothing for us so far; don't look at the same events again.
+        since = replay.latest;
+
+        match tokio::time::timeout_at(deadline, latest.changed()).await {
+            Ok(Ok(())) => continue,
+            _ => break,
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(Poll {
+            events: Vec::new(),
+            next: since,
+            reset: false,
+        }),
+    ))
+}
@@ -0,0 +1,33 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LoginsError {
+    #[error("User not found")]
+    NotFound,
+    #[error("You do not have permission to do that")]
+    Forbidden,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for LoginsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            LoginsError::NotFound => (StatusCode::NOT_FOUND, "User not found".to_string()),
+            LoginsError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "You do not have permission to do that".to_string(),
+            ),
+            LoginsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
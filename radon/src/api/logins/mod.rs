@@ -0,0 +1,312 @@
+pub mod error;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header::USER_AGENT, HeaderMap, StatusCode},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::LoginsError;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    config::ServerConfig,
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{roles, AppState};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/logins", get(fetch_logins))
+        .route("/admin/users/:user_id/unlock", post(unlock_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+/// Where a login attempt came from.
+#[derive(Debug, Clone)]
+pub struct LoginClient {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+impl LoginClient {
+    pub fn new(connect_info: Option<ConnectInfo<SocketAddr>>, headers: &HeaderMap) -> Self {
+        Self {
+            ip: connect_info
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            user_agent: headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// Why a login attempt was turned away.
+#[derive(Debug, Clone, Copy)]
+pub enum Failure {
+    BadPassword,
+    /// A wrong second factor.
+    BadCode,
+    /// A signature from an unregistered key, or one that didn't verify.
+    BadKey,
+    Locked,
+}
+
+impl Failure {
+    fn as_str(self) -> &'static str {
+        match self {
+            Failure::BadPassword => "bad_password",
+            Failure::BadCode => "bad_code",
+            Failure::BadKey => "bad_key",
+            Failure::Locked => "locked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginEvent {
+    pub success: bool,
+    pub reason: Option<String>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// How long an account is locked for after `failures` in a row: nothing
+/// until the threshold, then the base lockout doubling with every further
+/// failure.
+fn lockout(config: &ServerConfig, failures: i32) -> Option<chrono::Duration> {
+    if config.lockout_threshold <= 0 || failures < config.lockout_threshold {
+        return None;
+    }
+    let doublings = (failures - config.lockout_threshold) as u32;
+    let secs = config
+        .lockout_base_secs
+        .saturating_mul(2u64.saturating_pow(doublings))
+        .min(config.lockout_max_secs);
+    Some(chrono::Duration::seconds(secs as i64))
+}
+
+/// The time an account is locked until, if it is locked now.
+pub async fn locked_until(
+    db: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<NaiveDateTime>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let locked_until = sqlx::query_scalar!(
+        r#"select locked_until from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .flatten();
+    Ok(locked_until.filter(|until| *until > now))
+}
+
+/// Record a failed login, locking the account if it has now failed too many
+/// times in a row. Returns when the account is locked until, if it is.
+pub async fn record_failure(
+    db: &PgPool,
+    config: &ServerConfig,
+    user_id: Uuid,
+    client: &LoginClient,
+    failure: Failure,
+) -> Result<Option<NaiveDateTime>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = db.begin().await?;
+
+    insert_event(&mut *tx, user_id, client, Some(failure), now).await?;
+
+    // Attempts made while locked don't extend the lock, or anyone could keep
+    // an account locked forever.
+    if let Failure::Locked = failure {
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    let failures = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            update "users" set failed_logins = failed_logins + 1
+            where user_id = $1
+            returning failed_logins
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let locked_until = lockout(config, failures).map(|duration| now + duration);
+    if let Some(until) = locked_until {
+        sqlx::query!(
+            r#"update "users" set locked_until = $1 where user_id = $2"#,
+            until,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tracing::warn!(
+            "locked account {} until {} after {} failed logins",
+            user_id,
+            until,
+            failures
+        );
+    }
+
+    tx.commit().await?;
+    Ok(locked_until)
+}
+
+/// Record a successful login and clear any lockout. Returns how many failed
+/// attempts there were since the user last logged in.
+pub async fn record_success(
+    db: &PgPool,
+    user_id: Uuid,
+    client: &LoginClient,
+) -> Result<i64, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = db.begin().await?;
+
+    let failed_attempts = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            select count(*) as "count!"
+            from "login_events"
+            where user_id = $1 and not success and created_at > coalesce(
+                (select max(created_at) from "login_events" where user_id = $1 and success),
+                '-infinity'
+            )
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"update "users" set failed_logins = 0, locked_until = null where user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    insert_event(&mut *tx, user_id, client, None, now).await?;
+
+    tx.commit().await?;
+    Ok(failed_attempts)
+}
+
+async fn insert_event(
+    db: impl PgExecutor<'_>,
+    user_id: Uuid,
+    client: &LoginClient,
+    failure: Option<Failure>,
+    now: NaiveDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "login_events"(user_id, success, reason, ip, user_agent, created_at)
+            values ($1, $2, $3, $4, $5, $6)
+        "#,
+        user_id,
+        failure.is_none(),
+        failure.map(Failure::as_str),
+        client.ip,
+        client.user_agent,
+        now
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// A user's most recent login attempts, newest first.
+pub async fn recent(
+    db: &PgPool,
+    user_id: Uuid,
+    limit: i64,
+) -> Result<Vec<LoginEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginEvent,
+        // language=PostgreSQL
+        r#"
+            select success, reason, ip, user_agent, created_at
+            from "login_events"
+            where user_id = $1
+            order by created_at desc
+            limit $2
+        "#,
+        user_id,
+        limit
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Forget a user's failed logins. Returns whether the user exists.
+pub async fn clear_lockout(db: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let cleared = sqlx::query!(
+        r#"update "users" set failed_logins = 0, locked_until = null where user_id = $1"#,
+        user_id
+    )
+    .execute(db)
+    .await?;
+    Ok(cleared.rows_affected() > 0)
+}
+
+/// Let a locked out user try again. Only server admins may do this.
+pub async fn unlock(db: &PgPool, admin_id: Uuid, user_id: Uuid) -> Result<(), LoginsError> {
+    if !roles::is_server_admin(db, admin_id).await? {
+        return Err(LoginsError::Forbidden);
+    }
+
+    if !clear_lockout(db, user_id).await? {
+        return Err(LoginsError::NotFound);
+    }
+
+    tracing::info!("{} unlocked account {}", admin_id, user_id);
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginsQuery {
+    limit: Option<i64>,
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_logins(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Query(query): Query<LoginsQuery>,
+) -> Result<(StatusCode, Json<Vec<LoginEvent>>), LoginsError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let events = recent(&state.db, user.user_id, limit).await?;
+    Ok((StatusCode::OK, Json(events)))
+}
+
+#[axum_macros::debug_handler]
+async fn unlock_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, LoginsError> {
+    unlock(&state.db, user.user_id, user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
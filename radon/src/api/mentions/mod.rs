@@ -0,0 +1,210 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use error::MentionsError;
+use serde_derive::Deserialize;
+
+use crate::{
+    message::{ChatMessage, MentionInfo, MentionKind, MessageType, ServerEvent},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::AppState;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/mentions", get(fetch_mentions))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MentionsQuery {
+    /// Only return mentions in messages older than this id.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Who a message pings, as written.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Mentions {
+    /// Lowercased usernames from `@username`.
+    pub usernames: Vec<String>,
+    pub here: bool,
+    pub room: bool,
+}
+
+impl Mentions {
+    pub fn is_empty(&self) -> bool {
+        self.usernames.is_empty() && !self.here && !self.room
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Find `@username`, `@here` and `@room` in a message. An `@` in the middle
+/// of a word, like in an email address, doesn't count.
+pub fn parse(text: &str) -> Mentions {
+    let mut mentions = Mentions::default();
+    let mut previous = None;
+
+    for (i, c) in text.char_indices() {
+        let at_word_start = previous.is_none_or(|p: char| !is_name_char(p));
+        previous = Some(c);
+        if c != '@' || !at_word_start {
+            continue;
+        }
+
+        let rest = &text[i + 1..];
+        let end = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+        // Trailing dots are punctuation: "thanks @bob."
+        let name = rest[..end].trim_end_matches('.').to_lowercase();
+        match name.as_str() {
+            "" => {}
+            "here" => mentions.here = true,
+            "room" => mentions.room = true,
+            _ => {
+                if !mentions.usernames.contains(&name) {
+                    mentions.usernames.push(name);
+                }
+            }
+        }
+    }
+
+    mentions
+}
+
+/// Record who a message mentions and ping them on every connection, whatever
+/// room they're looking at. `@username` only reaches room members, `@here`
+/// members who are online, `@room` every member. The author is never pinged,
+/// and nobody is pinged twice for the same message, so this is safe to call
+/// again after an edit.
+pub async fn notify(state: &AppState, message: &ChatMessage) -> Result<(), sqlx::Error> {
+    let mentions = parse(&message.text);
+    if mentions.is_empty() || message.deleted {
+        return Ok(());
+    }
+
+    let online = if mentions.here {
+        state.clients.online_users()
+    } else {
+        Vec::new()
+    };
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "mentions"(message_id, user_id, room_id, seq, kind, created_at)
+            select $1, m.user_id, m.room_id, $3,
+                   case when lower(u.username) = any($5) then 'user'
+                        when $6 then 'room'
+                        else 'here' end,
+                   $8
+            from "room_members" m
+            join "users" u on u.user_id = m.user_id
+            where m.room_id = $2
+              and m.user_id is distinct from $4
+              and (lower(u.username) = any($5) or $6 or m.user_id = any($7))
+            on conflict do nothing
+            returning user_id, kind
+        "#,
+        message.message_id,
+        message.room_id,
+        message.seq,
+        message.user_id,
+        &mentions.usernames,
+        mentions.room,
+        &online,
+        message.created_at
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for record in records {
+        state.clients.send_to_user(
+            record.user_id,
+            ServerEvent::Mention(MentionInfo {
+                kind: record.kind.parse().unwrap_or(MentionKind::User),
+                message: message.clone(),
+            }),
+        );
+    }
+
+    Ok(())
+}
+
+/// Our mentions, newest first.
+#[axum_macros::debug_handler]
+async fn fetch_mentions(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Query(query): Query<MentionsQuery>,
+) -> Result<(StatusCode, Json<Vec<MentionInfo>>), MentionsError> {
+    let before = query.before.unwrap_or(i64::MAX);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select n.kind, m.message_id, m.room_id, m.seq, m.kind as message_kind, m.user_id,
+                   coalesce(m.display_name, u.username) as "from?", m.body, m.created_at,
+                   m.edited_at, m.parent_id
+            from "mentions" n
+            join "messages" m on m.message_id = n.message_id
+            join "room_members" rm on rm.room_id = n.room_id and rm.user_id = n.user_id
+            left join "users" u on u.user_id = m.user_id
+            where n.user_id = $1 and n.message_id < $2 and m.deleted_at is null
+            order by n.message_id desc
+            limit $3
+        "#,
+        user.user_id,
+        before,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mentions = records
+        .into_iter()
+        .map(|record| MentionInfo {
+            kind: record.kind.parse().unwrap_or(MentionKind::User),
+            message: ChatMessage {
+                message_id: record.message_id,
+                room_id: record.room_id,
+                seq: record.seq,
+                kind: record.message_kind.parse().unwrap_or(MessageType::Text),
+                user_id: record.user_id,
+                from: record.from,
+                text: record.body,
+                created_at: record.created_at,
+                edited_at: record.edited_at,
+                deleted: false,
+                parent_id: record.parent_id,
+                reply_to: None,
+                thread: None,
+                reactions: Vec::new(),
+                attachments: Vec::new(),
+            },
+        })
+        .collect::<Vec<MentionInfo>>();
+
+    Ok((StatusCode::OK, Json(mentions)))
+}
@@ -0,0 +1,100 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::api::{
+    moderation::error::ModerationError, roles::error::RolesError, uploads::error::UploadsError,
+};
+
+#[derive(Error, Debug)]
+pub enum MessagesError {
+    #[error("Invalid request")]
+    Invalid,
+    #[error("Message not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    Forbidden,
+    #[error("Only the author can do that")]
+    NotAuthor,
+    #[error("This message can no longer be edited")]
+    EditWindowClosed,
+    #[error("This message has too many different reactions")]
+    TooManyReactions,
+    #[error("Message is too long")]
+    TooLong,
+    /// Kept as is when posting. Elsewhere role errors fold into the above.
+    #[error(transparent)]
+    Roles(RolesError),
+    #[error(transparent)]
+    Moderation(#[from] ModerationError),
+    #[error(transparent)]
+    Uploads(#[from] UploadsError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl MessagesError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MessagesError::Invalid => "invalid",
+            MessagesError::NotFound => "not_found",
+            MessagesError::Forbidden | MessagesError::NotAuthor => "forbidden",
+            MessagesError::EditWindowClosed => "edit_window_closed",
+            MessagesError::TooManyReactions => "too_many_reactions",
+            MessagesError::TooLong => "too_long",
+            MessagesError::Roles(e) => e.code(),
+            MessagesError::Moderation(e) => e.code(),
+            MessagesError::Uploads(e) => e.code(),
+            MessagesError::Database(_) => "internal",
+        }
+    }
+}
+
+impl From<RolesError> for MessagesError {
+    fn from(e: RolesError) -> Self {
+        match e {
+            RolesError::NotMember => MessagesError::Forbidden,
+            RolesError::Forbidden => MessagesError::NotAuthor,
+            RolesError::Invalid | RolesError::NotFound => MessagesError::NotFound,
+            RolesError::Database(e) => MessagesError::Database(e),
+        }
+    }
+}
+
+impl IntoResponse for MessagesError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            MessagesError::Invalid => (StatusCode::BAD_REQUEST, "Invalid request".to_string()),
+            MessagesError::NotFound => (StatusCode::NOT_FOUND, "Message not found".to_string()),
+            MessagesError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            MessagesError::NotAuthor => (
+                StatusCode::FORBIDDEN,
+                "Only the author can do that".to_string(),
+            ),
+            MessagesError::EditWindowClosed => (
+                StatusCode::FORBIDDEN,
+                "This message can no longer be edited".to_string(),
+            ),
+            MessagesError::TooManyReactions => (
+                StatusCode::CONFLICT,
+                "This message has too many different reactions".to_string(),
+            ),
+            MessagesError::TooLong => (StatusCode::BAD_REQUEST, "Message is too long".to_string()),
+            MessagesError::Roles(e) => return e.into_response(),
+            MessagesError::Moderation(e) => return e.into_response(),
+            MessagesError::Uploads(e) => return e.into_response(),
+            MessagesError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
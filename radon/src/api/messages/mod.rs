@@ -0,0 +1,765 @@
+pub mod error;
+pub mod reactions;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::MessagesError;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Acquire, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    message::{ChatMessage, MessageType, ReplyPreview, ServerEvent, ThreadSummary},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{
+    mentions, moderation,
+    roles::{self, Permissions},
+    rooms, uploads, users, AppState,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// Longest message body we accept, in characters.
+pub const MAX_MESSAGE_LEN: usize = 4000;
+/// How much of a parent message is quoted above a reply.
+const QUOTE_LEN: usize = 80;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/rooms/:room_id/messages",
+            get(fetch_messages).post(post_message_handler),
+        )
+        .route(
+            "/messages/:message_id",
+            put(edit_message_handler).delete(delete_message_handler),
+        )
+        .route("/messages/:message_id/history", get(fetch_edit_history))
+        .route("/messages/:message_id/thread", get(fetch_thread))
+        .route(
+            "/messages/:message_id/reactions/:emoji",
+            put(reactions::add_reaction_handler).delete(reactions::remove_reaction_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Only return messages with a sequence number lower than this.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// A message as clients post it, over `/ws` or `POST /rooms/:room_id/messages`.
+#[derive(Debug, Deserialize)]
+pub struct NewMessage {
+    pub text: String,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    /// Uploads of the poster's to attach.
+    #[serde(default)]
+    pub attachments: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditRequest {
+    text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub previous_text: String,
+    pub edited_by: Option<String>,
+    pub edited_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Thread {
+    pub root: ChatMessage,
+    pub replies: Vec<ChatMessage>,
+}
+
+/// A `messages` row joined with the usernames and parent it refers to.
+struct MessageRow {
+    message_id: i64,
+    room_id: Uuid,
+    seq: i64,
+    kind: String,
+    user_id: Option<Uuid>,
+    from: Option<String>,
+    body: String,
+    created_at: NaiveDateTime,
+    edited_at: Option<NaiveDateTime>,
+    deleted_at: Option<NaiveDateTime>,
+    parent_id: Option<i64>,
+    parent_from: Option<String>,
+    parent_body: Option<String>,
+    parent_deleted_at: Option<NaiveDateTime>,
+    reply_count: i32,
+    last_reply_at: Option<NaiveDateTime>,
+    last_reply_from: Option<String>,
+}
+
+impl From<MessageRow> for ChatMessage {
+    fn from(row: MessageRow) -> Self {
+        let reply_to = row.parent_id.map(|message_id| ReplyPreview {
+            message_id,
+            from: row.parent_from,
+            text: quote(&row.parent_body.unwrap_or_default()),
+            deleted: row.parent_deleted_at.is_some(),
+        });
+        let thread = row.last_reply_at.map(|last_reply_at| ThreadSummary {
+            reply_count: row.reply_count as i64,
+            last_reply_at,
+            last_reply_from: row.last_reply_from,
+        });
+
+        ChatMessage {
+            message_id: row.message_id,
+            room_id: row.room_id,
+            seq: row.seq,
+            kind: row.kind.parse().unwrap_or(MessageType::Text),
+            user_id: row.user_id,
+            from: row.from,
+            text: row.body,
+            created_at: row.created_at,
+            edited_at: row.edited_at,
+            deleted: row.deleted_at.is_some(),
+            parent_id: row.parent_id,
+            reply_to,
+            thread,
+            reactions: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+}
+
+/// The first line of a message, cut down to fit above a reply.
+fn quote(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > QUOTE_LEN || line.len() < text.len() {
+        let mut short = line.chars().take(QUOTE_LEN).collect::<String>();
+        short.push('…');
+        short
+    } else {
+        line.to_string()
+    }
+}
+
+/// A single message as history would show it.
+pub async fn load_message(
+    db: &PgPool,
+    message_id: i64,
+) -> Result<Option<ChatMessage>, sqlx::Error> {
+    Ok(load_messages(db, &[message_id]).await?.pop())
+}
+
+/// Messages as history would show them, in the order of `ids`. Ids that
+/// don't exist are skipped.
+pub async fn load_messages(db: &PgPool, ids: &[i64]) -> Result<Vec<ChatMessage>, sqlx::Error> {
+    let mut messages = sqlx::query_as!(
+        MessageRow,
+        // language=PostgreSQL
+        r#"
+            select m.message_id, m.room_id, m.seq, m.kind, m.user_id,
+                   coalesce(m.display_name, u.username) as "from?", m.body, m.created_at,
+                   m.edited_at, m.deleted_at, m.parent_id,
+                   coalesce(p.display_name, pu.username) as "parent_from?",
+                   p.body as "parent_body?",
+                   p.deleted_at as "parent_deleted_at?", m.reply_count, m.last_reply_at,
+                   lu.username as "last_reply_from?"
+            from "messages" m
+            left join "users" u on u.user_id = m.user_id
+            left join "messages" p on p.message_id = m.parent_id
+            left join "users" pu on pu.user_id = p.user_id
+            left join "users" lu on lu.user_id = m.last_reply_by
+            where m.message_id = any($1)
+            order by array_position($1, m.message_id)
+        "#,
+        ids
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(ChatMessage::from)
+    .collect::<Vec<ChatMessage>>();
+
+    reactions::attach_reactions(db, &mut messages).await?;
+    uploads::attach_attachments(db, &mut messages).await?;
+    Ok(messages)
+}
+
+/// Find the thread a reply to `message_id` belongs in. Threads are one
+/// level deep, so replying to a reply joins its parent's thread.
+pub async fn reply_target(
+    db: &PgPool,
+    room_id: Uuid,
+    message_id: i64,
+) -> Result<ReplyPreview, MessagesError> {
+    let root = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select r.message_id, coalesce(r.display_name, u.username) as "from?", r.body
+            from "messages" m
+            join "messages" r on r.message_id = coalesce(m.parent_id, m.message_id)
+            left join "users" u on u.user_id = r.user_id
+            where m.message_id = $1 and m.room_id = $2 and r.deleted_at is null
+        "#,
+        message_id,
+        room_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(MessagesError::NotFound)?;
+
+    Ok(ReplyPreview {
+        message_id: root.message_id,
+        from: root.from,
+        text: quote(&root.body),
+        deleted: false,
+    })
+}
+
+/// Persist a message, assigning it the next sequence number in its room.
+/// Replies bump the reply count on their thread root. A `from` other than
+/// the author's username, as webhooks may set, is kept with the message.
+pub async fn insert_message(
+    db: impl Acquire<'_, Database = Postgres>,
+    room_id: Uuid,
+    user_id: Option<Uuid>,
+    from: Option<String>,
+    kind: MessageType,
+    text: String,
+    reply_to: Option<ReplyPreview>,
+) -> Result<ChatMessage, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let parent_id = reply_to.as_ref().map(|parent| parent.message_id);
+
+    let seq = sqlx::query_scalar!(
+        r#"update "rooms" set last_seq = last_seq + 1 where room_id = $1 returning last_seq"#,
+        room_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let created_at = chrono::Utc::now().naive_utc();
+
+    let message_id = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            insert into "messages"(room_id, user_id, seq, kind, body, created_at, parent_id,
+                                   display_name)
+            values ($1, $2, $3, $4, $5, $6, $7,
+                    nullif($8, (select username from "users" where user_id = $2)))
+            returning message_id
+        "#,
+        room_id,
+        user_id,
+        seq,
+        kind.as_str(),
+        text,
+        created_at,
+        parent_id,
+        from
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if let Some(parent_id) = parent_id {
+        sqlx::query!(
+            // language=PostgreSQL
+            r#"
+                update "messages"
+                set reply_count = reply_count + 1, last_reply_at = $1, last_reply_by = $2
+                where message_id = $3
+            "#,
+            created_at,
+            user_id,
+            parent_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(ChatMessage {
+        message_id,
+        room_id,
+        seq,
+        kind,
+        user_id,
+        from,
+        text,
+        created_at,
+        edited_at: None,
+        deleted: false,
+        parent_id,
+        reply_to,
+        thread: None,
+        reactions: Vec::new(),
+        attachments: Vec::new(),
+    })
+}
+
+/// Post a message as a user, after checking they may, and tell the room.
+/// However the message arrived, this is the one way in. `from` is the name
+/// shown on it when that isn't `username`, as webhooks may ask for.
+pub async fn post_message(
+    state: &AppState,
+    user_id: Uuid,
+    username: &str,
+    from: Option<String>,
+    room_id: Uuid,
+    message: NewMessage,
+) -> Result<ChatMessage, MessagesError> {
+    let NewMessage {
+        text,
+        parent_id,
+        attachments,
+    } = message;
+    if text.trim().is_empty() && attachments.is_empty() {
+        return Err(MessagesError::Invalid);
+    }
+    if text.chars().count() > MAX_MESSAGE_LEN {
+        return Err(MessagesError::TooLong);
+    }
+    roles::authorize(&state.db, room_id, user_id, Permissions::POST)
+        .await
+        .map_err(MessagesError::Roles)?;
+    moderation::check_post(&state.db, room_id, user_id).await?;
+
+    let reply_to = match parent_id {
+        Some(parent_id) => Some(reply_target(&state.db, room_id, parent_id).await?),
+        None => None,
+    };
+    let attachments = if attachments.is_empty() {
+        Vec::new()
+    } else {
+        uploads::owned_attachments(&state.db, user_id, &attachments).await?
+    };
+
+    // A message is only posted with all of its attachments.
+    let mut tx = state.db.begin().await?;
+    let mut message = insert_message(
+        &mut *tx,
+        room_id,
+        Some(user_id),
+        Some(from.unwrap_or_else(|| username.to_string())),
+        MessageType::Text,
+        text,
+        reply_to,
+    )
+    .await?;
+    if !attachments.is_empty() {
+        uploads::link_attachments(&mut *tx, message.message_id, &attachments).await?;
+        message.attachments = attachments;
+    }
+    tx.commit().await?;
+
+    // Sending a message ends typing, no need to wait for the client.
+    if state.typing.stop(room_id, user_id) {
+        state.publish(
+            room_id,
+            ServerEvent::Typing {
+                room_id,
+                user_id,
+                username: username.to_string(),
+                typing: false,
+            },
+        );
+    }
+    if let Err(e) = mentions::notify(state, &message).await {
+        tracing::error!("failed to record mentions: {e}");
+    }
+    state.publish(room_id, ServerEvent::Message(message.clone()));
+
+    // Anything you posted after, you've read. Every device of ours follows.
+    match rooms::mark_read(&state.db, room_id, user_id, message.seq).await {
+        Ok(Some(read)) => state
+            .clients
+            .send_to_user(user_id, ServerEvent::ReadMarker { room_id, read }),
+        Ok(None) => {}
+        Err(e) => tracing::error!("failed to mark read: {e}"),
+    }
+
+    Ok(message)
+}
+
+/// Replace the text of a message, keeping the old text in `message_edits`.
+/// Only the author can edit, only within the configured edit window, and
+/// not while banned or muted.
+pub async fn edit_message(
+    state: &AppState,
+    user_id: Uuid,
+    message_id: i64,
+    text: String,
+) -> Result<(), MessagesError> {
+    if text.trim().is_empty() || text.chars().count() > MAX_MESSAGE_LEN {
+        return Err(MessagesError::Invalid);
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let message = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select room_id, user_id, kind, body, created_at, deleted_at
+            from "messages"
+            where message_id = $1
+            for update
+        "#,
+        message_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .filter(|message| message.deleted_at.is_none())
+    .ok_or(MessagesError::NotFound)?;
+
+    if message.user_id != Some(user_id) {
+        return Err(MessagesError::NotAuthor);
+    }
+    match message.kind.parse() {
+        Ok(MessageType::Text) | Ok(MessageType::Action) => {}
+        _ => return Err(MessagesError::NotAuthor),
+    }
+    moderation::check_edit(&state.db, message.room_id, user_id).await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let window = state.config.edit_window_secs;
+    if window > 0 && (now - message.created_at).num_seconds() > window as i64 {
+        return Err(MessagesError::EditWindowClosed);
+    }
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "message_edits"(message_id, previous_body, edited_by, edited_at)
+            values ($1, $2, $3, $4)
+        "#,
+        message_id,
+        message.body,
+        user_id,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"update "messages" set body = $1, edited_at = $2 where message_id = $3"#,
+        text,
+        now,
+        message_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    state.publish(
+        message.room_id,
+        ServerEvent::MessageEdited {
+            room_id: message.room_id,
+            message_id,
+            text,
+            edited_at: now,
+        },
+    );
+
+    // Anyone newly mentioned by the edit gets pinged.
+    if let Some(message) = load_message(&state.db, message_id).await? {
+        mentions::notify(state, &message).await?;
+    }
+
+    Ok(())
+}
+
+/// Replace a message with a tombstone. The author or anyone who can delete
+/// others' messages may delete; the text is kept in `message_edits` for
+/// them to review.
+pub async fn delete_message(
+    state: &AppState,
+    user_id: Uuid,
+    message_id: i64,
+) -> Result<(), MessagesError> {
+    let mut tx = state.db.begin().await?;
+
+    let message = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select room_id, user_id, body, deleted_at
+            from "messages"
+            where message_id = $1
+            for update
+        "#,
+        message_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .filter(|message| message.deleted_at.is_none())
+    .ok_or(MessagesError::NotFound)?;
+
+    if message.user_id != Some(user_id) {
+        roles::authorize(
+            &mut *tx,
+            message.room_id,
+            user_id,
+            Permissions::DELETE_OTHERS,
+        )
+        .await?;
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "message_edits"(message_id, previous_body, edited_by, edited_at)
+            values ($1, $2, $3, $4)
+        "#,
+        message_id,
+        message.body,
+        user_id,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "messages"
+            set body = '', deleted_at = $1, deleted_by = $2
+            where message_id = $3
+        "#,
+        now,
+        user_id,
+        message_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    state.publish(
+        message.room_id,
+        ServerEvent::MessageDeleted {
+            room_id: message.room_id,
+            message_id,
+            deleted_by: user_id,
+        },
+    );
+
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+async fn edit_message_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+    Json(req): Json<EditRequest>,
+) -> Result<StatusCode, MessagesError> {
+    edit_message(&state, user.user_id, message_id, req.text).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn delete_message_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<StatusCode, MessagesError> {
+    delete_message(&state, user.user_id, message_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Previous versions of a message, oldest first. Once a message is deleted
+/// only those who can delete others' messages can see what it said.
+#[axum_macros::debug_handler]
+async fn fetch_edit_history(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<(StatusCode, Json<Vec<MessageEdit>>), MessagesError> {
+    let message = sqlx::query!(
+        r#"select room_id, deleted_at from "messages" where message_id = $1"#,
+        message_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(MessagesError::NotFound)?;
+
+    if !rooms::is_member(&state.db, message.room_id, user.user_id).await? {
+        return Err(MessagesError::Forbidden);
+    }
+    if message.deleted_at.is_some()
+        && !roles::access(&state.db, message.room_id, user.user_id)
+            .await?
+            .can(Permissions::DELETE_OTHERS)
+    {
+        return Err(MessagesError::NotFound);
+    }
+
+    let edits = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select e.previous_body, u.username as "edited_by?", e.edited_at
+            from "message_edits" e
+            left join "users" u on u.user_id = e.edited_by
+            where e.message_id = $1
+            order by e.edited_at
+        "#,
+        message_id
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|record| MessageEdit {
+        previous_text: record.previous_body,
+        edited_by: record.edited_by,
+        edited_at: record.edited_at,
+    })
+    .collect::<Vec<MessageEdit>>();
+
+    Ok((StatusCode::OK, Json(edits)))
+}
+
+#[axum_macros::debug_handler]
+async fn post_message_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Json(req): Json<NewMessage>,
+) -> Result<(StatusCode, Json<ChatMessage>), MessagesError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(MessagesError::NotFound)?;
+    let message = post_message(&state, user.user_id, &username, None, room_id, req).await?;
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_messages(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<(StatusCode, Json<Vec<ChatMessage>>), MessagesError> {
+    if !rooms::is_member(&state.db, room_id, user.user_id).await? {
+        return Err(MessagesError::Forbidden);
+    }
+
+    let before = query.before.unwrap_or(i64::MAX);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut messages = sqlx::query_as!(
+        MessageRow,
+        // language=PostgreSQL
+        r#"
+            select m.message_id, m.room_id, m.seq, m.kind, m.user_id,
+                   coalesce(m.display_name, u.username) as "from?", m.body, m.created_at,
+                   m.edited_at, m.deleted_at, m.parent_id,
+                   coalesce(p.display_name, pu.username) as "parent_from?",
+                   p.body as "parent_body?",
+                   p.deleted_at as "parent_deleted_at?", m.reply_count, m.last_reply_at,
+                   lu.username as "last_reply_from?"
+            from "messages" m
+            left join "users" u on u.user_id = m.user_id
+            left join "messages" p on p.message_id = m.parent_id
+            left join "users" pu on pu.user_id = p.user_id
+            left join "users" lu on lu.user_id = m.last_reply_by
+            where m.room_id = $1 and m.seq < $2
+            order by m.seq desc
+            limit $3
+        "#,
+        room_id,
+        before,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(ChatMessage::from)
+    .collect::<Vec<ChatMessage>>();
+    messages.reverse();
+    reactions::attach_reactions(&state.db, &mut messages).await?;
+    uploads::attach_attachments(&state.db, &mut messages).await?;
+
+    Ok((StatusCode::OK, Json(messages)))
+}
+
+/// A thread root and every reply to it, oldest first. Asking for a reply
+/// returns the thread it's in.
+#[axum_macros::debug_handler]
+async fn fetch_thread(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<(StatusCode, Json<Thread>), MessagesError> {
+    let mut messages = sqlx::query_as!(
+        MessageRow,
+        // language=PostgreSQL
+        r#"
+            select m.message_id, m.room_id, m.seq, m.kind, m.user_id,
+                   coalesce(m.display_name, u.username) as "from?", m.body, m.created_at,
+                   m.edited_at, m.deleted_at, m.parent_id,
+                   coalesce(p.display_name, pu.username) as "parent_from?",
+                   p.body as "parent_body?",
+                   p.deleted_at as "parent_deleted_at?", m.reply_count, m.last_reply_at,
+                   lu.username as "last_reply_from?"
+            from "messages" m
+            left join "users" u on u.user_id = m.user_id
+            left join "messages" p on p.message_id = m.parent_id
+            left join "users" pu on pu.user_id = p.user_id
+            left join "users" lu on lu.user_id = m.last_reply_by
+            where m.message_id = (
+                select coalesce(parent_id, message_id) from "messages" where message_id = $1
+            ) or m.parent_id = (
+                select coalesce(parent_id, message_id) from "messages" where message_id = $1
+            )
+            order by m.seq
+        "#,
+        message_id
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(ChatMessage::from)
+    .collect::<Vec<ChatMessage>>();
+
+    if messages.is_empty() {
+        return Err(MessagesError::NotFound);
+    }
+    reactions::attach_reactions(&state.db, &mut messages).await?;
+    uploads::attach_attachments(&state.db, &mut messages).await?;
+    let root = messages.remove(0);
+    if !rooms::is_member(&state.db, root.room_id, user.user_id).await? {
+        return Err(MessagesError::Forbidden);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(Thread {
+            root,
+            replies: messages,
+        }),
+    ))
+}
@@ -0,0 +1,257 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        moderation,
+        roles::{self, Permissions},
+        rooms, users, AppState,
+    },
+    message::{ChatMessage, Reaction, ServerEvent},
+    middleware::AuthUser,
+};
+
+use super::error::MessagesError;
+
+/// Different emoji one message can collect.
+const MAX_EMOJI_PER_MESSAGE: i64 = 20;
+const MAX_SHORTCODE_LEN: usize = 32;
+const MAX_EMOJI_LEN: usize = 16;
+
+/// Shortcodes stored as the emoji they stand for, so `:+1:` and 👍 count
+/// together. Anything else is kept as typed.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("heart", "❤️"),
+    ("smile", "😄"),
+    ("laughing", "😆"),
+    ("joy", "😂"),
+    ("tada", "🎉"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("thinking", "🤔"),
+    ("pray", "🙏"),
+    ("white_check_mark", "✅"),
+];
+
+/// Turn what a client sent into the stored form, or `None` if it's neither
+/// a `:shortcode:` nor a short run of emoji.
+pub fn normalize_emoji(emoji: &str) -> Option<String> {
+    let emoji = emoji.trim();
+    if let Some(code) = emoji.strip_prefix(':').and_then(|e| e.strip_suffix(':')) {
+        let valid = !code.is_empty()
+            && code.len() <= MAX_SHORTCODE_LEN
+            && code
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'));
+        if !valid {
+            return None;
+        }
+        let code = code.to_lowercase();
+        return Some(match SHORTCODES.iter().find(|(name, _)| *name == code) {
+            Some((_, emoji)) => emoji.to_string(),
+            None => format!(":{}:", code),
+        });
+    }
+
+    let valid = !emoji.is_empty()
+        && emoji.chars().count() <= MAX_EMOJI_LEN
+        && emoji
+            .chars()
+            .all(|c| !c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace());
+    valid.then(|| emoji.to_string())
+}
+
+/// The room a message is in, checking the user may react to it.
+async fn reactable(db: &PgPool, message_id: i64, user_id: Uuid) -> Result<Uuid, MessagesError> {
+    let message = sqlx::query!(
+        r#"select room_id, deleted_at from "messages" where message_id = $1"#,
+        message_id
+    )
+    .fetch_optional(db)
+    .await?
+    .filter(|message| message.deleted_at.is_none())
+    .ok_or(MessagesError::NotFound)?;
+
+    if !rooms::is_member(db, message.room_id, user_id).await? {
+        return Err(MessagesError::Forbidden);
+    }
+    Ok(message.room_id)
+}
+
+pub async fn add_reaction(
+    state: &AppState,
+    user_id: Uuid,
+    username: String,
+    message_id: i64,
+    emoji: &str,
+) -> Result<(), MessagesError> {
+    let emoji = normalize_emoji(emoji).ok_or(MessagesError::Invalid)?;
+    let room_id = reactable(&state.db, message_id, user_id).await?;
+    // Reacting is a kind of posting, so guests can't.
+    roles::authorize(&state.db, room_id, user_id, Permissions::POST)
+        .await
+        .map_err(MessagesError::Roles)?;
+    moderation::check_mute(&state.db, room_id, user_id).await?;
+
+    let distinct = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            select count(distinct emoji) as "count!"
+            from "reactions"
+            where message_id = $1 and emoji <> $2
+        "#,
+        message_id,
+        emoji
+    )
+    .fetch_one(&state.db)
+    .await?;
+    if distinct >= MAX_EMOJI_PER_MESSAGE {
+        return Err(MessagesError::TooManyReactions);
+    }
+
+    let added = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "reactions"(message_id, user_id, emoji)
+            values ($1, $2, $3)
+            on conflict do nothing
+        "#,
+        message_id,
+        user_id,
+        emoji
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if added {
+        state.publish(
+            room_id,
+            ServerEvent::ReactionAdded {
+                room_id,
+                message_id,
+                emoji,
+                user_id,
+                username,
+            },
+        );
+    }
+    Ok(())
+}
+
+pub async fn remove_reaction(
+    state: &AppState,
+    user_id: Uuid,
+    username: String,
+    message_id: i64,
+    emoji: &str,
+) -> Result<(), MessagesError> {
+    let emoji = normalize_emoji(emoji).ok_or(MessagesError::Invalid)?;
+    let room_id = reactable(&state.db, message_id, user_id).await?;
+
+    let removed = sqlx::query!(
+        r#"delete from "reactions" where message_id = $1 and user_id = $2 and emoji = $3"#,
+        message_id,
+        user_id,
+        emoji
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if removed {
+        state.publish(
+            room_id,
+            ServerEvent::ReactionRemoved {
+                room_id,
+                message_id,
+                emoji,
+                user_id,
+                username,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Fill in the reaction counts on a page of messages, in the order each
+/// emoji was first used.
+pub async fn attach_reactions(
+    db: &PgPool,
+    messages: &mut [ChatMessage],
+) -> Result<(), sqlx::Error> {
+    let ids = messages
+        .iter()
+        .map(|message| message.message_id)
+        .collect::<Vec<i64>>();
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select message_id, emoji, array_agg(user_id order by created_at) as "user_ids!"
+            from "reactions"
+            where message_id = any($1)
+            group by message_id, emoji
+            order by message_id, min(created_at)
+        "#,
+        &ids
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut reactions: HashMap<i64, Vec<Reaction>> = HashMap::new();
+    for record in records {
+        reactions
+            .entry(record.message_id)
+            .or_default()
+            .push(Reaction {
+                emoji: record.emoji,
+                count: record.user_ids.len() as i64,
+                user_ids: record.user_ids,
+            });
+    }
+    for message in messages.iter_mut() {
+        message.reactions = reactions.remove(&message.message_id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+pub(super) async fn add_reaction_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((message_id, emoji)): Path<(i64, String)>,
+) -> Result<StatusCode, MessagesError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(MessagesError::NotFound)?;
+    add_reaction(&state, user.user_id, username, message_id, &emoji).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+pub(super) async fn remove_reaction_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((message_id, emoji)): Path<(i64, String)>,
+) -> Result<StatusCode, MessagesError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(MessagesError::NotFound)?;
+    remove_reaction(&state, user.user_id, username, message_id, &emoji).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
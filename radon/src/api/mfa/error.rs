@@ -0,0 +1,51 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MfaError {
+    #[error("Two-factor authentication is already on")]
+    AlreadyEnabled,
+    #[error("Two-factor authentication is not on")]
+    NotEnabled,
+    #[error("Run setup first")]
+    NotSetUp,
+    #[error("Invalid code")]
+    BadCode,
+    #[error("Invalid password")]
+    BadPassword,
+    #[error("Internal error")]
+    Internal,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for MfaError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            MfaError::AlreadyEnabled => (
+                StatusCode::CONFLICT,
+                "Two-factor authentication is already on".to_string(),
+            ),
+            MfaError::NotEnabled => (
+                StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not on".to_string(),
+            ),
+            MfaError::NotSetUp => (StatusCode::BAD_REQUEST, "Run setup first".to_string()),
+            MfaError::BadCode => (StatusCode::UNAUTHORIZED, "Invalid code".to_string()),
+            MfaError::BadPassword => (StatusCode::UNAUTHORIZED, "Invalid password".to_string()),
+            MfaError::Internal => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal error".to_string(),
+            ),
+            MfaError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,507 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::post, Extension, Json, Router};
+use chrono::NaiveDateTime;
+use error::MfaError;
+use rand::seq::SliceRandom;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::middleware::{requires_auth, AuthUser};
+
+use super::{auth::utils, AppState};
+
+const ISSUER: &str = "radon";
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+const RECOVERY_CODES: usize = 10;
+/// Letters and digits that can't be mistaken for each other.
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/2fa/setup", post(setup_handler))
+        .route("/me/2fa/confirm", post(confirm_handler))
+        .route("/me/2fa/disable", post(disable_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetupResponse {
+    /// The base32 secret, for authenticator apps that can't scan codes.
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmResponse {
+    /// Single use codes to log in with if the authenticator is lost. They
+    /// are only ever shown here.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisableRequest {
+    pub password: String,
+    /// A current code or an unused recovery code.
+    pub code: String,
+}
+
+fn totp(secret: &str, username: &str) -> Result<TOTP, MfaError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| MfaError::Internal)?;
+    // Checked one step at a time by `matching_step`, hence no skew.
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP,
+        secret,
+        Some(ISSUER.to_string()),
+        username.to_string(),
+    ))
+}
+
+/// The time step `code` belongs to, allowing a step of clock drift either
+/// way. Only steps after `last_step` count, so each code works once.
+fn matching_step(totp: &TOTP, code: &str, last_step: Option<i64>) -> Option<i64> {
+    matching_step_at(totp, code, last_step, chrono::Utc::now().timestamp() as u64)
+}
+
+fn matching_step_at(totp: &TOTP, code: &str, last_step: Option<i64>, now: u64) -> Option<i64> {
+    let current = (now / STEP) as i64;
+    (current - 1..=current + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| totp.check(code, *step as u64 * STEP))
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Recovery codes are compared without case, spaces or dashes.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let mut code: String = (0..10)
+        .map(|_| *RECOVERY_ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect();
+    code.insert(5, '-');
+    code
+}
+
+pub async fn is_enabled(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let enabled = sqlx::query_scalar!(
+        r#"select totp_enabled from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(enabled.unwrap_or(false))
+}
+
+/// Remember a challenge `/login` handed out, so it can be used once.
+pub async fn store_challenge(
+    db: &PgPool,
+    user_id: Uuid,
+    challenge_token: &str,
+    expires_at: NaiveDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"delete from "mfa_challenges" where expires_at <= $1"#,
+        chrono::Utc::now().naive_utc()
+    )
+    .execute(db)
+    .await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "mfa_challenges"(token_hash, user_id, expires_at)
+            values ($1, $2, $3)
+        "#,
+        utils::hash_token(challenge_token),
+        user_id,
+        expires_at
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Whether a challenge is still waiting to be used.
+pub async fn challenge_pending(db: &PgPool, challenge_token: &str) -> Result<bool, sqlx::Error> {
+    let pending = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            select exists(
+                select 1 from "mfa_challenges" where token_hash = $1 and expires_at > $2
+            ) as "pending!"
+        "#,
+        utils::hash_token(challenge_token),
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(pending)
+}
+
+/// Use up a challenge. False if it already was.
+pub async fn take_challenge(db: &PgPool, challenge_token: &str) -> Result<bool, sqlx::Error> {
+    let taken = sqlx::query!(
+        r#"delete from "mfa_challenges" where token_hash = $1"#,
+        utils::hash_token(challenge_token)
+    )
+    .execute(db)
+    .await?;
+    Ok(taken.rows_affected() == 1)
+}
+
+/// Check a second factor for a user with two-factor authentication on:
+/// either a code from their authenticator or one of their recovery codes,
+/// which is used up.
+pub async fn check_code(db: &PgPool, user_id: Uuid, code: &str) -> Result<bool, MfaError> {
+    let code = code.trim();
+    if is_totp_code(code) {
+        check_totp(db, user_id, code).await
+    } else {
+        use_recovery_code(db, user_id, code).await
+    }
+}
+
+async fn check_totp(db: &PgPool, user_id: Uuid, code: &str) -> Result<bool, MfaError> {
+    let mut tx = db.begin().await?;
+
+    let user = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select username, totp_secret, totp_last_step
+            from "users"
+            where user_id = $1 and totp_enabled
+            for update
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MfaError::NotEnabled)?;
+    let secret = user.totp_secret.ok_or(MfaError::NotEnabled)?;
+
+    let totp = totp(&secret, &user.username)?;
+    let Some(step) = matching_step(&totp, code, user.totp_last_step) else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"update "users" set totp_last_step = $1 where user_id = $2"#,
+        step,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+async fn use_recovery_code(db: &PgPool, user_id: Uuid, code: &str) -> Result<bool, MfaError> {
+    let code = normalize(code);
+    if code.is_empty() {
+        return Ok(false);
+    }
+
+    // Marking it used in the same statement means a code raced through
+    // twice only works once.
+    let used = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "recovery_codes" set used_at = $1
+            where user_id = $2 and code_hash = $3 and used_at is null
+        "#,
+        chrono::Utc::now().naive_utc(),
+        user_id,
+        utils::hash_token(&code)
+    )
+    .execute(db)
+    .await?;
+    Ok(used.rows_affected() == 1)
+}
+
+/// Start enrolling: store a fresh secret, which only takes effect once a
+/// code from it is confirmed. Running setup again replaces the secret.
+pub async fn setup(db: &PgPool, user_id: Uuid) -> Result<SetupResponse, MfaError> {
+    let user = sqlx::query!(
+        r#"select username, totp_enabled from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?;
+    if user.totp_enabled {
+        return Err(MfaError::AlreadyEnabled);
+    }
+
+    let secret = match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => return Err(MfaError::Internal),
+    };
+    let otpauth_uri = totp(&secret, &user.username)?.get_url();
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "users" set totp_secret = $1, totp_last_step = null
+            where user_id = $2 and not totp_enabled
+        "#,
+        secret,
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(SetupResponse {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// Finish enrolling with a code from the new secret, turning two-factor
+/// authentication on and issuing recovery codes.
+pub async fn confirm(db: &PgPool, user_id: Uuid, code: &str) -> Result<Vec<String>, MfaError> {
+    let recovery_codes = (0..RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<String>>();
+    // Ten random characters are plenty for a plain hash, and checking one
+    // then costs a single lookup rather than an Argon2 run per code.
+    let hashes = recovery_codes
+        .iter()
+        .map(|code| utils::hash_token(&normalize(code)))
+        .collect::<Vec<String>>();
+
+    let mut tx = db.begin().await?;
+
+    let user = sqlx::query!(
+        r#"select username, totp_secret, totp_enabled from "users" where user_id = $1 for update"#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if user.totp_enabled {
+        return Err(MfaError::AlreadyEnabled);
+    }
+    let secret = user.totp_secret.ok_or(MfaError::NotSetUp)?;
+
+    let totp = totp(&secret, &user.username)?;
+    let step = matching_step(&totp, code.trim(), None).ok_or(MfaError::BadCode)?;
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "users" set totp_enabled = true, totp_last_step = $1
+            where user_id = $2
+        "#,
+        step,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"delete from "recovery_codes" where user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "recovery_codes"(user_id, code_hash)
+            select $1, unnest($2::text[])
+        "#,
+        user_id,
+        &hashes
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(recovery_codes)
+}
+
+/// Turn two-factor authentication off. Takes the password and a second
+/// factor, so a stolen session alone can't do it.
+pub async fn disable(
+    db: &PgPool,
+    user_id: Uuid,
+    password: String,
+    code: &str,
+) -> Result<(), MfaError> {
+    let password_hash = sqlx::query_scalar!(
+        r#"select password_hash from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?;
+    let verified = utils::verify(password, password_hash)
+        .await
+        .map_err(|_| MfaError::Internal)?;
+    if !verified {
+        return Err(MfaError::BadPassword);
+    }
+
+    if !is_enabled(db, user_id).await? {
+        return Err(MfaError::NotEnabled);
+    }
+    if !check_code(db, user_id, code).await? {
+        return Err(MfaError::BadCode);
+    }
+
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "users"
+            set totp_enabled = false, totp_secret = null, totp_last_step = null
+            where user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"delete from "recovery_codes" where user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+async fn setup_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<SetupResponse>), MfaError> {
+    let setup = setup(&state.db, user.user_id).await?;
+    Ok((StatusCode::OK, Json(setup)))
+}
+
+#[axum_macros::debug_handler]
+async fn confirm_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<ConfirmRequest>,
+) -> Result<(StatusCode, Json<ConfirmResponse>), MfaError> {
+    let recovery_codes = confirm(&state.db, user.user_id, &req.code).await?;
+    Ok((StatusCode::OK, Json(ConfirmResponse { recovery_codes })))
+}
+
+#[axum_macros::debug_handler]
+async fn disable_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<DisableRequest>,
+) -> Result<StatusCode, MfaError> {
+    disable(&state.db, user.user_id, req.password, &req.code).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_at(totp: &TOTP, step: i64) -> String {
+        totp.generate(step as u64 * STEP)
+    }
+
+    fn fixture() -> (TOTP, u64, i64) {
+        let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+            unreachable!()
+        };
+        let totp = totp(&secret, "alice").unwrap();
+        // Partway through a step, so nothing hinges on the boundary.
+        let now = 1_700_000_010;
+        (totp, now, (now / STEP) as i64)
+    }
+
+    #[test]
+    fn current_code_matches() {
+        let (totp, now, step) = fixture();
+        let code = code_at(&totp, step);
+        assert_eq!(matching_step_at(&totp, &code, None, now), Some(step));
+    }
+
+    #[test]
+    fn one_step_of_skew_either_way() {
+        let (totp, now, step) = fixture();
+        for skew in [-1, 1] {
+            let code = code_at(&totp, step + skew);
+            assert_eq!(matching_step_at(&totp, &code, None, now), Some(step + skew));
+        }
+        for skew in [-2, 2] {
+            let code = code_at(&totp, step + skew);
+            assert_eq!(matching_step_at(&totp, &code, None, now), None);
+        }
+    }
+
+    #[test]
+    fn codes_work_once() {
+        let (totp, now, step) = fixture();
+        let code = code_at(&totp, step);
+        assert_eq!(matching_step_at(&totp, &code, Some(step), now), None);
+        // Nor can an older code be used once a newer one has been.
+        let previous = code_at(&totp, step - 1);
+        assert_eq!(matching_step_at(&totp, &previous, Some(step), now), None);
+        assert_eq!(
+            matching_step_at(&totp, &code, Some(step - 1), now),
+            Some(step)
+        );
+    }
+
+    #[test]
+    fn wrong_codes_fail() {
+        let (totp, now, step) = fixture();
+        let code = code_at(&totp, step);
+        let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+        assert_eq!(matching_step_at(&totp, &wrong, None, now), None);
+        assert_eq!(matching_step_at(&totp, "", None, now), None);
+    }
+
+    #[test]
+    fn normalize_ignores_case_spaces_and_dashes() {
+        assert_eq!(normalize("ABCDE-fghjk"), "abcdefghjk");
+        assert_eq!(normalize(" abcde fghjk "), "abcdefghjk");
+        assert_eq!(normalize("ab-cd-e fg--hjk"), "abcdefghjk");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn recovery_codes_survive_normalizing() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_eq!(normalize(&code), code.replace('-', ""));
+        assert!(!is_totp_code(&code));
+        assert!(is_totp_code("012345"));
+        assert!(!is_totp_code("01234a"));
+    }
+}
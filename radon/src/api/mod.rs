@@ -1,9 +1,30 @@
 pub mod auth;
+pub mod bookmarks;
+pub mod bots;
 pub mod error;
+pub mod events;
+pub mod logins;
+pub mod mentions;
+pub mod messages;
+pub mod mfa;
+pub mod moderation;
+pub mod passwords;
+pub mod pins;
+pub mod roles;
+pub mod rooms;
+pub mod search;
+pub mod ssh_keys;
+pub mod tokens;
+pub mod uploads;
 pub mod users;
+pub mod webhooks;
 pub mod ws;
 
-use std::sync::Arc;
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
 
 use axum::{
     extract::State,
@@ -11,27 +32,95 @@ use axum::{
     Extension, Json, Router, Server,
 };
 use sqlx::PgPool;
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::Mutex;
 use tower_http::{
     cors::{Any, CorsLayer},
     trace::TraceLayer,
 };
+use uuid::Uuid;
 
-use crate::config::ServerConfig;
+use crate::{
+    api::{
+        auth::{keys::JwtKeys, sessions::Revocations},
+        webhooks::outgoing::OutgoingQueue,
+    },
+    client::{registry::ConnectionRegistry, typing::TypingTracker},
+    commands::CommandRegistry,
+    config::ServerConfig,
+    message::ServerEvent,
+    middleware::rate_limit,
+    ratelimit::RateLimiter,
+};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub config: ServerConfig,
     pub db: PgPool,
-    pub tx: broadcast::Sender<String>,
+    pub clients: Arc<ConnectionRegistry>,
+    pub typing: Arc<TypingTracker>,
+    pub commands: Arc<CommandRegistry>,
+    pub limiter: Arc<RateLimiter>,
+    pub revocations: Arc<Revocations>,
+    pub keys: Arc<JwtKeys>,
+    pub outgoing: Arc<OutgoingQueue>,
+}
+
+impl AppState {
+    pub fn new(config: ServerConfig, db: PgPool) -> anyhow::Result<Self> {
+        let typing = TypingTracker::new(Duration::from_secs(config.typing_timeout_secs));
+        let keys = JwtKeys::load(&config)?;
+        Ok(Self {
+            config,
+            db,
+            clients: Arc::new(ConnectionRegistry::new()),
+            typing: Arc::new(typing),
+            commands: Arc::new(CommandRegistry::builtin()),
+            limiter: Arc::new(RateLimiter::new()),
+            revocations: Arc::new(Revocations::new()),
+            keys: Arc::new(keys),
+            outgoing: Arc::new(OutgoingQueue::new()),
+        })
+    }
+
+    /// Send an event to everyone connected to a room, and to the room's
+    /// outgoing webhooks.
+    pub fn publish(&self, room_id: Uuid, event: ServerEvent) {
+        self.outgoing.push(room_id, &event);
+        self.clients.publish(room_id, event);
+    }
 }
 
 pub async fn run(state: Arc<AppState>) {
-    let app = routes(state.clone());
-    let addr = format!("127.0.0.1:{}", state.config.port).parse().unwrap();
+    ws::spawn_typing_sweeper(state.clone());
+    moderation::spawn_expiry_sweeper(state.clone());
+    spawn_limiter_sweeper(state.clone());
+    webhooks::outgoing::spawn_delivery(state.clone());
+
+    let addr = format!("127.0.0.1:{}", state.config.port);
+    let listener = TcpListener::bind(&addr).unwrap();
     println!("Listening on {}", addr);
-    let server = Server::bind(&addr).serve(app.into_make_service());
-    server.await.unwrap();
+    serve(state, listener).await.unwrap();
+}
+
+/// Serve the API on a listener that's already bound, as tests do on a port
+/// picked by the OS. Background tasks are left to the caller, see [`run`].
+pub async fn serve(state: Arc<AppState>, listener: TcpListener) -> anyhow::Result<()> {
+    let app = routes(state);
+    Server::from_tcp(listener)?
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+/// Drop rate limit buckets that have been idle for an hour.
+fn spawn_limiter_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5 * 60));
+        loop {
+            interval.tick().await;
+            state.limiter.sweep(Duration::from_secs(60 * 60));
+        }
+    });
 }
 
 pub fn routes(state: Arc<AppState>) -> Router {
@@ -39,6 +128,29 @@ pub fn routes(state: Arc<AppState>) -> Router {
 
     Router::new()
         .merge(users::router(state.clone()))
+        .merge(auth::router(state.clone()))
+        .merge(logins::router(state.clone()))
+        .merge(mfa::router(state.clone()))
+        .merge(ssh_keys::router(state.clone()))
+        .merge(passwords::router(state.clone()))
+        .merge(bots::router(state.clone()))
+        .merge(tokens::router(state.clone()))
+        .merge(rooms::router(state.clone()))
+        .merge(roles::router(state.clone()))
+        .merge(moderation::router(state.clone()))
+        .merge(messages::router(state.clone()))
+        .merge(events::router(state.clone()))
+        .merge(mentions::router(state.clone()))
+        .merge(search::router(state.clone()))
+        .merge(pins::router(state.clone()))
+        .merge(bookmarks::router(state.clone()))
+        .merge(uploads::router(state.clone()))
+        .merge(webhooks::router(state.clone()))
+        .merge(ws::router(state.clone()))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit,
+        ))
         .layer(cors)
 }
 
@@ -0,0 +1,79 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::NaiveDateTime;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::api::roles::error::RolesError;
+
+#[derive(Error, Debug)]
+pub enum ModerationError {
+    #[error("Invalid request")]
+    Invalid,
+    #[error("User not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    NotMember,
+    #[error("You do not have permission to do that")]
+    Forbidden,
+    #[error("You are banned from this room")]
+    Banned,
+    #[error("{}", muted_message(.0))]
+    Muted(Option<NaiveDateTime>),
+    #[error("Slow mode is on, wait {0}s before posting again")]
+    SlowMode(i64),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+fn muted_message(until: &Option<NaiveDateTime>) -> String {
+    match until {
+        Some(until) => format!("You are muted in this room until {}", until.format("%F %R")),
+        None => "You are muted in this room".to_string(),
+    }
+}
+
+impl ModerationError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ModerationError::Invalid => "invalid",
+            ModerationError::NotFound => "not_found",
+            ModerationError::NotMember | ModerationError::Forbidden => "forbidden",
+            ModerationError::Banned => "banned",
+            ModerationError::Muted(_) => "muted",
+            ModerationError::SlowMode(_) => "slow_mode",
+            ModerationError::Database(_) => "internal",
+        }
+    }
+}
+
+impl From<RolesError> for ModerationError {
+    fn from(e: RolesError) -> Self {
+        match e {
+            RolesError::Invalid => ModerationError::Invalid,
+            RolesError::NotFound => ModerationError::NotFound,
+            RolesError::NotMember => ModerationError::NotMember,
+            RolesError::Forbidden => ModerationError::Forbidden,
+            RolesError::Database(e) => ModerationError::Database(e),
+        }
+    }
+}
+
+impl IntoResponse for ModerationError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            ModerationError::Invalid => StatusCode::BAD_REQUEST,
+            ModerationError::NotFound => StatusCode::NOT_FOUND,
+            ModerationError::NotMember
+            | ModerationError::Forbidden
+            | ModerationError::Banned
+            | ModerationError::Muted(_) => StatusCode::FORBIDDEN,
+            ModerationError::SlowMode(_) => StatusCode::TOO_MANY_REQUESTS,
+            ModerationError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = Json(json!({ "error": self.to_string() }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,911 @@
+pub mod error;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, put},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::ModerationError;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    message::{MessageType, ServerEvent},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{
+    messages,
+    roles::{self, Permissions},
+    users, AppState,
+};
+
+/// Longest slow mode interval, six hours.
+const MAX_SLOW_MODE_SECS: i32 = 6 * 60 * 60;
+/// Longest timed ban or mute. Anything longer should be permanent.
+const MAX_SANCTION_SECS: i64 = 365 * 24 * 60 * 60;
+/// How often expired bans and mutes are lifted.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/rooms/:room_id/members/:user_id", delete(kick_handler))
+        .route("/rooms/:room_id/bans", get(fetch_bans))
+        .route(
+            "/rooms/:room_id/bans/:user_id",
+            put(ban_handler).delete(unban_handler),
+        )
+        .route("/rooms/:room_id/mutes", get(fetch_mutes))
+        .route(
+            "/rooms/:room_id/mutes/:user_id",
+            put(mute_handler).delete(unmute_handler),
+        )
+        .route("/rooms/:room_id/slow_mode", put(slow_mode_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+/// A ban or mute, as listed to moderators.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sanction {
+    pub user_id: Uuid,
+    pub username: String,
+    pub by: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Parse a duration like `30s`, `10m`, `2h`, `7d` or a bare number of
+/// seconds.
+pub fn parse_duration(s: &str) -> Option<chrono::Duration> {
+    let s = s.trim().to_lowercase();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s.as_str(), "s"),
+    };
+    let number = number.parse::<i64>().ok()?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number.checked_mul(60)?,
+        "h" => number.checked_mul(60 * 60)?,
+        "d" => number.checked_mul(24 * 60 * 60)?,
+        "w" => number.checked_mul(7 * 24 * 60 * 60)?,
+        _ => return None,
+    };
+    sanction_duration(seconds)
+}
+
+fn sanction_duration(seconds: i64) -> Option<chrono::Duration> {
+    if (1..=MAX_SANCTION_SECS).contains(&seconds) {
+        Some(chrono::Duration::seconds(seconds))
+    } else {
+        None
+    }
+}
+
+/// Check the moderator holds `permission` and outranks the user they're
+/// acting on. Returns the target's username.
+async fn check_target(
+    db: &PgPool,
+    room_id: Uuid,
+    user_id: Uuid,
+    target_id: Uuid,
+    permission: Permissions,
+) -> Result<String, ModerationError> {
+    let access = roles::authorize(db, room_id, user_id, permission).await?;
+    if target_id == user_id {
+        return Err(ModerationError::Invalid);
+    }
+    let target = roles::access(db, room_id, target_id).await?;
+    if target.server_admin && !access.server_admin {
+        return Err(ModerationError::Forbidden);
+    }
+    if let Some(role) = target.role {
+        if !access.outranks(role) {
+            return Err(ModerationError::Forbidden);
+        }
+    }
+    users::username(db, target_id)
+        .await?
+        .ok_or(ModerationError::NotFound)
+}
+
+async fn log(
+    db: &PgPool,
+    room_id: Uuid,
+    action: &str,
+    target_id: Option<Uuid>,
+    actor_id: Option<Uuid>,
+    reason: Option<&str>,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "moderation_log"(room_id, action, target_id, actor_id, reason, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        room_id,
+        action,
+        target_id,
+        actor_id,
+        reason,
+        expires_at,
+        chrono::Utc::now().naive_utc()
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Post a system message about a moderation action to the room.
+async fn announce(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    text: String,
+) -> Result<(), sqlx::Error> {
+    let message = messages::insert_message(
+        &state.db,
+        room_id,
+        Some(user_id),
+        Some(username.to_string()),
+        MessageType::System,
+        text,
+        None,
+    )
+    .await?;
+    state.publish(room_id, ServerEvent::Message(message));
+    Ok(())
+}
+
+fn with_reason(text: String, reason: &Option<String>) -> String {
+    match reason {
+        Some(reason) => format!("{} ({})", text, reason),
+        None => text,
+    }
+}
+
+fn until(expires_at: Option<NaiveDateTime>) -> String {
+    match expires_at {
+        Some(expires_at) => format!(" until {}", expires_at.format("%F %R")),
+        None => String::new(),
+    }
+}
+
+/// Whether a user is banned from a room right now.
+pub async fn is_banned(db: &PgPool, room_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            select exists(
+                select 1 from "room_bans"
+                where room_id = $1 and user_id = $2
+                  and (expires_at is null or expires_at > $3)
+            ) as "exists!"
+        "#,
+        room_id,
+        user_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_one(db)
+    .await
+}
+
+/// Check a member isn't muted in a room. Reacting needs only this; posting
+/// goes through `check_post` and editing through `check_edit`.
+pub async fn check_mute(db: &PgPool, room_id: Uuid, user_id: Uuid) -> Result<(), ModerationError> {
+    let now = chrono::Utc::now().naive_utc();
+    let mute = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select expires_at from "room_mutes"
+            where room_id = $1 and user_id = $2 and (expires_at is null or expires_at > $3)
+        "#,
+        room_id,
+        user_id,
+        now
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match mute {
+        Some(mute) => Err(ModerationError::Muted(mute.expires_at)),
+        None => Ok(()),
+    }
+}
+
+/// Check a user may edit their messages in a room: they aren't banned or
+/// muted. Slow mode doesn't apply, as an edit adds nothing to the room.
+pub async fn check_edit(db: &PgPool, room_id: Uuid, user_id: Uuid) -> Result<(), ModerationError> {
+    if is_banned(db, room_id, user_id).await? {
+        return Err(ModerationError::Banned);
+    }
+    check_mute(db, room_id, user_id).await
+}
+
+/// Check a member may post in a room right now: they aren't muted and, in
+/// slow mode, have waited long enough since their last post. Moderators are
+/// exempt from slow mode.
+pub async fn check_post(db: &PgPool, room_id: Uuid, user_id: Uuid) -> Result<(), ModerationError> {
+    check_mute(db, room_id, user_id).await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let record = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select r.slow_mode_secs,
+                   (select max(m.created_at) from "messages" m
+                    where m.room_id = r.room_id and m.user_id = $2) as last_post
+            from "rooms" r
+            where r.room_id = $1
+        "#,
+        room_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(ModerationError::NotMember)?;
+
+    if record.slow_mode_secs > 0 {
+        if let Some(last_post) = record.last_post {
+            let next = last_post + chrono::Duration::seconds(record.slow_mode_secs as i64);
+            if next > now
+                && !roles::access(db, room_id, user_id)
+                    .await?
+                    .can(Permissions::SLOW_MODE)
+            {
+                let wait = (next - now).num_seconds().max(1);
+                return Err(ModerationError::SlowMode(wait));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove a member from a room. They can come straight back.
+pub async fn kick(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+    reason: Option<String>,
+) -> Result<(), ModerationError> {
+    let target = check_target(&state.db, room_id, user_id, target_id, Permissions::KICK).await?;
+
+    let removed = sqlx::query!(
+        r#"delete from "room_members" where room_id = $1 and user_id = $2"#,
+        room_id,
+        target_id
+    )
+    .execute(&state.db)
+    .await?;
+    if removed.rows_affected() == 0 {
+        return Err(ModerationError::NotFound);
+    }
+
+    log(
+        &state.db,
+        room_id,
+        "kick",
+        Some(target_id),
+        Some(user_id),
+        reason.as_deref(),
+        None,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::MemberKicked {
+            room_id,
+            user_id: target_id,
+            username: target.clone(),
+            by: username.to_string(),
+            reason: reason.clone(),
+        },
+    );
+    state.clients.leave_room(target_id, room_id);
+
+    let text = with_reason(format!("{} was kicked by {}", target, username), &reason);
+    announce(state, room_id, user_id, username, text).await?;
+    Ok(())
+}
+
+/// Remove a user from a room and keep them out, for `duration` or until
+/// unbanned. Banning someone again replaces their ban.
+pub async fn ban(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+    reason: Option<String>,
+    duration: Option<chrono::Duration>,
+) -> Result<(), ModerationError> {
+    let target = check_target(&state.db, room_id, user_id, target_id, Permissions::BAN).await?;
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = duration.map(|duration| now + duration);
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "room_bans"(room_id, user_id, banned_by, reason, created_at, expires_at)
+            values ($1, $2, $3, $4, $5, $6)
+            on conflict (room_id, user_id) do update
+            set banned_by = $3, reason = $4, created_at = $5, expires_at = $6
+        "#,
+        room_id,
+        target_id,
+        user_id,
+        reason,
+        now,
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"delete from "room_members" where room_id = $1 and user_id = $2"#,
+        room_id,
+        target_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    log(
+        &state.db,
+        room_id,
+        "ban",
+        Some(target_id),
+        Some(user_id),
+        reason.as_deref(),
+        expires_at,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::MemberBanned {
+            room_id,
+            user_id: target_id,
+            username: target.clone(),
+            by: username.to_string(),
+            reason: reason.clone(),
+            expires_at,
+        },
+    );
+    state.clients.leave_room(target_id, room_id);
+
+    let text = format!("{} was banned by {}{}", target, username, until(expires_at));
+    announce(
+        state,
+        room_id,
+        user_id,
+        username,
+        with_reason(text, &reason),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn unban(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+) -> Result<(), ModerationError> {
+    roles::authorize(&state.db, room_id, user_id, Permissions::BAN).await?;
+    let target = users::username(&state.db, target_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+
+    let lifted = sqlx::query!(
+        r#"delete from "room_bans" where room_id = $1 and user_id = $2"#,
+        room_id,
+        target_id
+    )
+    .execute(&state.db)
+    .await?;
+    if lifted.rows_affected() == 0 {
+        return Err(ModerationError::NotFound);
+    }
+
+    log(
+        &state.db,
+        room_id,
+        "unban",
+        Some(target_id),
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::MemberUnbanned {
+            room_id,
+            user_id: target_id,
+            username: target.clone(),
+            by: Some(username.to_string()),
+        },
+    );
+
+    let text = format!("{} was unbanned by {}", target, username);
+    announce(state, room_id, user_id, username, text).await?;
+    Ok(())
+}
+
+/// Make a member read-only, for `duration` or until unmuted.
+pub async fn mute(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+    reason: Option<String>,
+    duration: Option<chrono::Duration>,
+) -> Result<(), ModerationError> {
+    let target = check_target(&state.db, room_id, user_id, target_id, Permissions::MUTE).await?;
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = duration.map(|duration| now + duration);
+
+    let muted = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "room_mutes"(room_id, user_id, muted_by, reason, created_at, expires_at)
+            select $1, $2, $3, $4, $5, $6
+            where exists(select 1 from "room_members" where room_id = $1 and user_id = $2)
+            on conflict (room_id, user_id) do update
+            set muted_by = $3, reason = $4, created_at = $5, expires_at = $6
+        "#,
+        room_id,
+        target_id,
+        user_id,
+        reason,
+        now,
+        expires_at
+    )
+    .execute(&state.db)
+    .await?;
+    if muted.rows_affected() == 0 {
+        return Err(ModerationError::NotFound);
+    }
+
+    log(
+        &state.db,
+        room_id,
+        "mute",
+        Some(target_id),
+        Some(user_id),
+        reason.as_deref(),
+        expires_at,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::MemberMuted {
+            room_id,
+            user_id: target_id,
+            username: target.clone(),
+            by: username.to_string(),
+            reason: reason.clone(),
+            expires_at,
+        },
+    );
+
+    let text = format!("{} was muted by {}{}", target, username, until(expires_at));
+    announce(
+        state,
+        room_id,
+        user_id,
+        username,
+        with_reason(text, &reason),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn unmute(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+) -> Result<(), ModerationError> {
+    roles::authorize(&state.db, room_id, user_id, Permissions::MUTE).await?;
+    let target = users::username(&state.db, target_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+
+    let lifted = sqlx::query!(
+        r#"delete from "room_mutes" where room_id = $1 and user_id = $2"#,
+        room_id,
+        target_id
+    )
+    .execute(&state.db)
+    .await?;
+    if lifted.rows_affected() == 0 {
+        return Err(ModerationError::NotFound);
+    }
+
+    log(
+        &state.db,
+        room_id,
+        "unmute",
+        Some(target_id),
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::MemberUnmuted {
+            room_id,
+            user_id: target_id,
+            username: target.clone(),
+            by: Some(username.to_string()),
+        },
+    );
+
+    let text = format!("{} was unmuted by {}", target, username);
+    announce(state, room_id, user_id, username, text).await?;
+    Ok(())
+}
+
+/// Set the minimum time between posts by one member. 0 turns slow mode off.
+pub async fn set_slow_mode(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    seconds: i32,
+) -> Result<(), ModerationError> {
+    if !(0..=MAX_SLOW_MODE_SECS).contains(&seconds) {
+        return Err(ModerationError::Invalid);
+    }
+    roles::authorize(&state.db, room_id, user_id, Permissions::SLOW_MODE).await?;
+
+    sqlx::query!(
+        r#"update "rooms" set slow_mode_secs = $1 where room_id = $2"#,
+        seconds,
+        room_id
+    )
+    .execute(&state.db)
+    .await?;
+
+    let reason = format!("{}s", seconds);
+    log(
+        &state.db,
+        room_id,
+        "slow_mode",
+        None,
+        Some(user_id),
+        Some(&reason),
+        None,
+    )
+    .await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::SlowMode {
+            room_id,
+            seconds,
+            by: username.to_string(),
+        },
+    );
+
+    let text = if seconds == 0 {
+        format!("{} turned slow mode off", username)
+    } else {
+        format!(
+            "{} turned on slow mode: one message every {}s",
+            username, seconds
+        )
+    };
+    announce(state, room_id, user_id, username, text).await?;
+    Ok(())
+}
+
+/// Lift bans and mutes that have run out.
+pub fn spawn_expiry_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = expire(&state).await {
+                tracing::error!("failed to expire bans and mutes: {e}");
+            }
+        }
+    });
+}
+
+async fn expire(state: &AppState) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let bans = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            delete from "room_bans" b
+            using "users" u
+            where u.user_id = b.user_id and b.expires_at <= $1
+            returning b.room_id, b.user_id, u.username
+        "#,
+        now
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for ban in bans {
+        log(
+            &state.db,
+            ban.room_id,
+            "unban",
+            Some(ban.user_id),
+            None,
+            Some("expired"),
+            None,
+        )
+        .await?;
+        state.publish(
+            ban.room_id,
+            ServerEvent::MemberUnbanned {
+                room_id: ban.room_id,
+                user_id: ban.user_id,
+                username: ban.username,
+                by: None,
+            },
+        );
+    }
+
+    let mutes = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            delete from "room_mutes" m
+            using "users" u
+            where u.user_id = m.user_id and m.expires_at <= $1
+            returning m.room_id, m.user_id, u.username
+        "#,
+        now
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for mute in mutes {
+        log(
+            &state.db,
+            mute.room_id,
+            "unmute",
+            Some(mute.user_id),
+            None,
+            Some("expired"),
+            None,
+        )
+        .await?;
+        state.publish(
+            mute.room_id,
+            ServerEvent::MemberUnmuted {
+                room_id: mute.room_id,
+                user_id: mute.user_id,
+                username: mute.username,
+                by: None,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct KickQuery {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SanctionRequest {
+    reason: Option<String>,
+    /// Omit for a sanction that lasts until it's lifted.
+    duration_secs: Option<i64>,
+}
+
+impl SanctionRequest {
+    fn duration(&self) -> Result<Option<chrono::Duration>, ModerationError> {
+        match self.duration_secs {
+            Some(secs) => sanction_duration(secs)
+                .map(Some)
+                .ok_or(ModerationError::Invalid),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SlowModeRequest {
+    seconds: i32,
+}
+
+#[axum_macros::debug_handler]
+async fn kick_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<KickQuery>,
+) -> Result<StatusCode, ModerationError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    kick(
+        &state,
+        room_id,
+        user.user_id,
+        &username,
+        target_id,
+        query.reason,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn ban_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<SanctionRequest>,
+) -> Result<StatusCode, ModerationError> {
+    let duration = req.duration()?;
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    ban(
+        &state,
+        room_id,
+        user.user_id,
+        &username,
+        target_id,
+        req.reason,
+        duration,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn unban_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ModerationError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    unban(&state, room_id, user.user_id, &username, target_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn mute_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<SanctionRequest>,
+) -> Result<StatusCode, ModerationError> {
+    let duration = req.duration()?;
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    mute(
+        &state,
+        room_id,
+        user.user_id,
+        &username,
+        target_id,
+        req.reason,
+        duration,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn unmute_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ModerationError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    unmute(&state, room_id, user.user_id, &username, target_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn slow_mode_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Json(req): Json<SlowModeRequest>,
+) -> Result<StatusCode, ModerationError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(ModerationError::NotFound)?;
+    set_slow_mode(&state, room_id, user.user_id, &username, req.seconds).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Active bans in a room, newest first.
+#[axum_macros::debug_handler]
+async fn fetch_bans(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Vec<Sanction>>), ModerationError> {
+    roles::authorize(&state.db, room_id, user.user_id, Permissions::BAN).await?;
+
+    let bans = sqlx::query_as!(
+        Sanction,
+        // language=PostgreSQL
+        r#"
+            select b.user_id, u.username, a.username as "by?", b.reason, b.created_at,
+                   b.expires_at
+            from "room_bans" b
+            join "users" u on u.user_id = b.user_id
+            left join "users" a on a.user_id = b.banned_by
+            where b.room_id = $1 and (b.expires_at is null or b.expires_at > $2)
+            order by b.created_at desc
+        "#,
+        room_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok((StatusCode::OK, Json(bans)))
+}
+
+/// Active mutes in a room, newest first.
+#[axum_macros::debug_handler]
+async fn fetch_mutes(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Vec<Sanction>>), ModerationError> {
+    roles::authorize(&state.db, room_id, user.user_id, Permissions::MUTE).await?;
+
+    let mutes = sqlx::query_as!(
+        Sanction,
+        // language=PostgreSQL
+        r#"
+            select m.user_id, u.username, a.username as "by?", m.reason, m.created_at,
+                   m.expires_at
+            from "room_mutes" m
+            join "users" u on u.user_id = m.user_id
+            left join "users" a on a.user_id = m.muted_by
+            where m.room_id = $1 and (m.expires_at is null or m.expires_at > $2)
+            order by m.created_at desc
+        "#,
+        room_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok((StatusCode::OK, Json(mutes)))
+}
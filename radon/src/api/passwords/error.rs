@@ -0,0 +1,65 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PasswordError {
+    #[error("Password must be at least {0} characters")]
+    TooShort(usize),
+    #[error("Password must be at most {0} bytes")]
+    TooLong(usize),
+    #[error("Password is too common")]
+    Common,
+    #[error("Password can't be your username")]
+    MatchesUsername,
+    #[error("Current password is wrong")]
+    WrongPassword,
+    #[error("Invalid or expired reset token")]
+    InvalidToken,
+    #[error("User not found")]
+    NotFound,
+    #[error("You do not have permission to do that")]
+    Forbidden,
+    #[error("Internal error")]
+    Internal,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl PasswordError {
+    /// Stable, machine readable code, so clients can say what to fix.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordError::TooShort(_) => "password_too_short",
+            PasswordError::TooLong(_) => "password_too_long",
+            PasswordError::Common => "password_common",
+            PasswordError::MatchesUsername => "password_matches_username",
+            PasswordError::WrongPassword => "wrong_password",
+            PasswordError::InvalidToken => "invalid_token",
+            PasswordError::NotFound => "not_found",
+            PasswordError::Forbidden => "forbidden",
+            PasswordError::Internal | PasswordError::Database(_) => "internal",
+        }
+    }
+}
+
+impl IntoResponse for PasswordError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            PasswordError::TooShort(_)
+            | PasswordError::TooLong(_)
+            | PasswordError::Common
+            | PasswordError::MatchesUsername => StatusCode::UNPROCESSABLE_ENTITY,
+            PasswordError::WrongPassword | PasswordError::InvalidToken => StatusCode::UNAUTHORIZED,
+            PasswordError::NotFound => StatusCode::NOT_FOUND,
+            PasswordError::Forbidden => StatusCode::FORBIDDEN,
+            PasswordError::Internal | PasswordError::Database(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        let body = Json(json!({ "error": self.to_string(), "code": self.code() }));
+
+        (status, body).into_response()
+    }
+}
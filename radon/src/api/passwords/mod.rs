@@ -0,0 +1,367 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::post,
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::PasswordError;
+use rand::RngCore;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::PasswordPolicy,
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{
+    auth::{self, utils, LoginResponse},
+    logins, roles, ws, AppState,
+};
+
+/// Refused whatever the configured blacklist says.
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456789",
+    "1234567890",
+    "12345678910",
+    "password",
+    "password1",
+    "password123",
+    "passw0rd",
+    "qwerty123",
+    "qwertyuiop",
+    "1q2w3e4r5t",
+    "iloveyou",
+    "princess",
+    "sunshine",
+    "football",
+    "baseball",
+    "superman",
+    "trustno1",
+    "letmein123",
+    "welcome123",
+    "changeme",
+    "administrator",
+    "abc123456",
+    "11111111",
+    "00000000",
+    "aaaaaaaaaa",
+];
+
+pub fn router(state: Arc<AppState>) -> Router {
+    let authed = Router::new()
+        .route("/me/password", post(change_password_handler))
+        .route(
+            "/admin/users/:user_id/password_reset",
+            post(issue_reset_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ));
+
+    Router::new()
+        .route("/password_reset", post(reset_password_handler))
+        .merge(authed)
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetToken {
+    /// Hand this to the user. It works once.
+    pub token: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Check a new password against the policy.
+pub fn check(policy: &PasswordPolicy, username: &str, password: &str) -> Result<(), PasswordError> {
+    if password.len() > policy.max_length {
+        return Err(PasswordError::TooLong(policy.max_length));
+    }
+    if password.chars().count() < policy.min_length {
+        return Err(PasswordError::TooShort(policy.min_length));
+    }
+    if password.eq_ignore_ascii_case(username) {
+        return Err(PasswordError::MatchesUsername);
+    }
+    let mut common = COMMON_PASSWORDS
+        .iter()
+        .copied()
+        .chain(policy.blacklist.iter().map(String::as_str));
+    if common.any(|common| password.eq_ignore_ascii_case(common)) {
+        return Err(PasswordError::Common);
+    }
+    Ok(())
+}
+
+async fn set_password(
+    state: &AppState,
+    user_id: Uuid,
+    password: String,
+) -> Result<(), PasswordError> {
+    let password_hash = utils::hash(password, state.config.argon2)
+        .await
+        .map_err(|_| PasswordError::Internal)?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "users" set password_hash = $1, updated_at = $2
+            where user_id = $3
+        "#,
+        password_hash,
+        chrono::Utc::now().naive_utc(),
+        user_id
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+/// Change our own password. Every session is revoked and its connections
+/// closed, and the caller gets a fresh one so only they stay logged in.
+pub async fn change_password(
+    state: &AppState,
+    user_id: Uuid,
+    current_password: String,
+    new_password: String,
+) -> Result<LoginResponse, PasswordError> {
+    let user = sqlx::query!(
+        r#"select username, password_hash from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    let verified = utils::verify(current_password, user.password_hash)
+        .await
+        .map_err(|_| PasswordError::Internal)?;
+    if !verified {
+        return Err(PasswordError::WrongPassword);
+    }
+    check(&state.config.password_policy, &user.username, &new_password)?;
+
+    set_password(state, user_id, new_password).await?;
+    let now = chrono::Utc::now().naive_utc();
+    state.revocations.revoke(&state.db, user_id, now).await?;
+    ws::disconnect_user(state, user_id).await;
+
+    Ok(auth::issue_session(state, user_id, user.username, 0))
+}
+
+/// Issue a reset token for a user who can't log in. Only server admins may.
+pub async fn issue_reset(
+    state: &AppState,
+    admin_id: Uuid,
+    user_id: Uuid,
+) -> Result<ResetToken, PasswordError> {
+    if !roles::is_server_admin(&state.db, admin_id).await? {
+        return Err(PasswordError::Forbidden);
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = now + chrono::Duration::seconds(state.config.password_reset_secs as i64);
+
+    let mut tx = state.db.begin().await?;
+    // Only the newest token works.
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "password_resets" set used_at = $1
+            where user_id = $2 and used_at is null
+        "#,
+        now,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    let res = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "password_resets"(token_hash, user_id, created_by, created_at, expires_at)
+            values ($1, $2, $3, $4, $5)
+        "#,
+        utils::hash_token(&token),
+        user_id,
+        admin_id,
+        now,
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await;
+    match res {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(dbe)) if dbe.is_foreign_key_violation() => {
+            return Err(PasswordError::NotFound)
+        }
+        Err(e) => return Err(PasswordError::Database(e)),
+    }
+    tx.commit().await?;
+
+    tracing::info!("{} issued a password reset for {}", admin_id, user_id);
+    Ok(ResetToken { token, expires_at })
+}
+
+/// Set a new password with a reset token. This logs out every session,
+/// closing its connections, and lifts any lockout, since whoever forgot their
+/// password has probably been failing to log in.
+pub async fn reset_password(
+    state: &AppState,
+    token: &str,
+    new_password: String,
+) -> Result<(), PasswordError> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = state.db.begin().await?;
+
+    let reset = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select r.user_id, u.username
+            from "password_resets" r
+            join "users" u on u.user_id = r.user_id
+            where r.token_hash = $1 and r.used_at is null and r.expires_at > $2
+            for update of r
+        "#,
+        utils::hash_token(token.trim()),
+        now
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(PasswordError::InvalidToken)?;
+
+    check(
+        &state.config.password_policy,
+        &reset.username,
+        &new_password,
+    )?;
+
+    sqlx::query!(
+        r#"update "password_resets" set used_at = $1 where token_hash = $2"#,
+        now,
+        utils::hash_token(token.trim())
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    set_password(state, reset.user_id, new_password).await?;
+    state
+        .revocations
+        .revoke(&state.db, reset.user_id, now)
+        .await?;
+    ws::disconnect_user(state, reset.user_id).await;
+    logins::clear_lockout(&state.db, reset.user_id).await?;
+
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+async fn change_password_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<ChangePasswordRequest>,
+) -> Result<(StatusCode, Json<LoginResponse>), PasswordError> {
+    let session =
+        change_password(&state, user.user_id, req.current_password, req.new_password).await?;
+    Ok((StatusCode::OK, Json(session)))
+}
+
+#[axum_macros::debug_handler]
+async fn issue_reset_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResetToken>), PasswordError> {
+    let reset = issue_reset(&state, user.user_id, user_id).await?;
+    Ok((StatusCode::CREATED, Json(reset)))
+}
+
+#[axum_macros::debug_handler]
+async fn reset_password_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, PasswordError> {
+    reset_password(&state, &req.token, req.new_password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checked(password: &str) -> Result<(), PasswordError> {
+        check(&PasswordPolicy::default(), "alice", password)
+    }
+
+    #[test]
+    fn accepts_a_decent_password() {
+        assert!(checked("correct horse battery staple").is_ok());
+    }
+
+    #[test]
+    fn length_limits() {
+        assert!(matches!(checked("short"), Err(PasswordError::TooShort(10))));
+        assert!(checked("ten chars!").is_ok());
+        // Characters count towards the minimum, bytes towards the maximum.
+        assert!(matches!(
+            checked("ééééééééé"),
+            Err(PasswordError::TooShort(10))
+        ));
+        assert!(checked(&"x".repeat(128)).is_ok());
+        assert!(matches!(
+            checked(&"x".repeat(129)),
+            Err(PasswordError::TooLong(128))
+        ));
+        assert!(matches!(
+            checked(&"é".repeat(65)),
+            Err(PasswordError::TooLong(128))
+        ));
+    }
+
+    #[test]
+    fn refuses_the_username() {
+        let policy = PasswordPolicy {
+            min_length: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            check(&policy, "Alice", "aLICE"),
+            Err(PasswordError::MatchesUsername)
+        ));
+    }
+
+    #[test]
+    fn refuses_common_passwords() {
+        assert!(matches!(checked("PASSWORD123"), Err(PasswordError::Common)));
+        assert!(matches!(checked("1234567890"), Err(PasswordError::Common)));
+
+        let policy = PasswordPolicy {
+            blacklist: vec!["Radon Chat 2024".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            check(&policy, "alice", "radon chat 2024"),
+            Err(PasswordError::Common)
+        ));
+        assert!(check(&policy, "alice", "radon chat 2025").is_ok());
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::api::roles::error::RolesError;
+
+#[derive(Error, Debug)]
+pub enum PinsError {
+    #[error("Message not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    Forbidden,
+    #[error("Only moderators can pin messages")]
+    NotModerator,
+    #[error("This room has too many pinned messages")]
+    TooMany,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl PinsError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PinsError::NotFound => "not_found",
+            PinsError::Forbidden | PinsError::NotModerator => "forbidden",
+            PinsError::TooMany => "too_many_pins",
+            PinsError::Database(_) => "internal",
+        }
+    }
+}
+
+impl From<RolesError> for PinsError {
+    fn from(e: RolesError) -> Self {
+        match e {
+            RolesError::NotMember => PinsError::Forbidden,
+            RolesError::Forbidden => PinsError::NotModerator,
+            RolesError::Invalid | RolesError::NotFound => PinsError::NotFound,
+            RolesError::Database(e) => PinsError::Database(e),
+        }
+    }
+}
+
+impl IntoResponse for PinsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            PinsError::NotFound => (StatusCode::NOT_FOUND, "Message not found".to_string()),
+            PinsError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            PinsError::NotModerator => (
+                StatusCode::FORBIDDEN,
+                "Only moderators can pin messages".to_string(),
+            ),
+            PinsError::TooMany => (
+                StatusCode::CONFLICT,
+                "This room has too many pinned messages".to_string(),
+            ),
+            PinsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,210 @@
+pub mod error;
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use error::PinsError;
+use uuid::Uuid;
+
+use crate::{
+    message::{Pin, ServerEvent},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{
+    messages,
+    roles::{self, Permissions},
+    rooms, users, AppState,
+};
+
+/// Pins one room can hold.
+const MAX_PINS_PER_ROOM: i64 = 50;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/rooms/:room_id/pins", get(fetch_pins))
+        .route(
+            "/messages/:message_id/pin",
+            put(pin_handler).delete(unpin_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+/// The room of a message that `user_id` may pin or unpin.
+async fn pinnable(state: &AppState, message_id: i64, user_id: Uuid) -> Result<Uuid, PinsError> {
+    let message = sqlx::query!(
+        r#"select room_id, deleted_at from "messages" where message_id = $1"#,
+        message_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .filter(|message| message.deleted_at.is_none())
+    .ok_or(PinsError::NotFound)?;
+
+    roles::authorize(&state.db, message.room_id, user_id, Permissions::PIN).await?;
+    Ok(message.room_id)
+}
+
+pub async fn pin_message(
+    state: &AppState,
+    user_id: Uuid,
+    username: String,
+    message_id: i64,
+) -> Result<(), PinsError> {
+    let room_id = pinnable(state, message_id, user_id).await?;
+
+    let pinned = sqlx::query_scalar!(
+        r#"select count(*) as "count!" from "pins" where room_id = $1"#,
+        room_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+    if pinned >= MAX_PINS_PER_ROOM {
+        return Err(PinsError::TooMany);
+    }
+
+    let pinned_at = chrono::Utc::now().naive_utc();
+    let added = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "pins"(message_id, room_id, pinned_by, pinned_at)
+            values ($1, $2, $3, $4)
+            on conflict do nothing
+        "#,
+        message_id,
+        room_id,
+        user_id,
+        pinned_at
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if added {
+        let message = messages::load_message(&state.db, message_id)
+            .await?
+            .ok_or(PinsError::NotFound)?;
+        state.publish(
+            room_id,
+            ServerEvent::MessagePinned(Pin {
+                message,
+                pinned_by: Some(username),
+                pinned_at,
+            }),
+        );
+    }
+    Ok(())
+}
+
+pub async fn unpin_message(
+    state: &AppState,
+    user_id: Uuid,
+    username: String,
+    message_id: i64,
+) -> Result<(), PinsError> {
+    let room_id = pinnable(state, message_id, user_id).await?;
+
+    let removed = sqlx::query!(r#"delete from "pins" where message_id = $1"#, message_id)
+        .execute(&state.db)
+        .await?
+        .rows_affected()
+        > 0;
+
+    if removed {
+        state.publish(
+            room_id,
+            ServerEvent::MessageUnpinned {
+                room_id,
+                message_id,
+                unpinned_by: username,
+            },
+        );
+    }
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+async fn pin_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<StatusCode, PinsError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(PinsError::NotFound)?;
+    pin_message(&state, user.user_id, username, message_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum_macros::debug_handler]
+async fn unpin_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(message_id): Path<i64>,
+) -> Result<StatusCode, PinsError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(PinsError::NotFound)?;
+    unpin_message(&state, user.user_id, username, message_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A room's pinned messages, most recently pinned first.
+#[axum_macros::debug_handler]
+async fn fetch_pins(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Vec<Pin>>), PinsError> {
+    if !rooms::is_member(&state.db, room_id, user.user_id).await? {
+        return Err(PinsError::Forbidden);
+    }
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select p.message_id, u.username as "pinned_by?", p.pinned_at
+            from "pins" p
+            join "messages" m on m.message_id = p.message_id
+            left join "users" u on u.user_id = p.pinned_by
+            where p.room_id = $1 and m.deleted_at is null
+            order by p.pinned_at desc
+        "#,
+        room_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let ids = records
+        .iter()
+        .map(|record| record.message_id)
+        .collect::<Vec<i64>>();
+    let mut messages = messages::load_messages(&state.db, &ids)
+        .await?
+        .into_iter()
+        .map(|message| (message.message_id, message))
+        .collect::<HashMap<i64, _>>();
+
+    let pins = records
+        .into_iter()
+        .filter_map(|record| {
+            Some(Pin {
+                message: messages.remove(&record.message_id)?,
+                pinned_by: record.pinned_by,
+                pinned_at: record.pinned_at,
+            })
+        })
+        .collect::<Vec<Pin>>();
+
+    Ok((StatusCode::OK, Json(pins)))
+}
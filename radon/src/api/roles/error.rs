@@ -0,0 +1,54 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RolesError {
+    #[error("Invalid role")]
+    Invalid,
+    #[error("Member not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    NotMember,
+    #[error("You do not have permission to do that")]
+    Forbidden,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl RolesError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RolesError::Invalid => "invalid",
+            RolesError::NotFound => "not_found",
+            RolesError::NotMember | RolesError::Forbidden => "forbidden",
+            RolesError::Database(_) => "internal",
+        }
+    }
+}
+
+impl IntoResponse for RolesError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            RolesError::Invalid => (StatusCode::BAD_REQUEST, "Invalid role".to_string()),
+            RolesError::NotFound => (StatusCode::NOT_FOUND, "Member not found".to_string()),
+            RolesError::NotMember => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            RolesError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "You do not have permission to do that".to_string(),
+            ),
+            RolesError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,320 @@
+pub mod error;
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::put,
+    Extension, Json, Router,
+};
+use bitflags::bitflags;
+use error::RolesError;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    message::ServerEvent,
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{users, AppState};
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/rooms/:room_id/members/:user_id/role",
+            put(set_role_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+bitflags! {
+    /// What a user may do in a room.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Permissions: u32 {
+        const POST = 1 << 0;
+        const INVITE = 1 << 1;
+        const KICK = 1 << 2;
+        const BAN = 1 << 3;
+        const PIN = 1 << 4;
+        const EDIT_TOPIC = 1 << 5;
+        const DELETE_OTHERS = 1 << 6;
+        const MANAGE_ROLES = 1 << 7;
+        const MUTE = 1 << 8;
+        const SLOW_MODE = 1 << 9;
+        const MANAGE_WEBHOOKS = 1 << 10;
+        const MANAGE_ROOM = 1 << 11;
+    }
+}
+
+/// A member's standing in a room, lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can read along but not post.
+    Guest,
+    Member,
+    Moderator,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub fn permissions(self) -> Permissions {
+        let member = Permissions::POST | Permissions::INVITE;
+        let moderator = member
+            | Permissions::KICK
+            | Permissions::BAN
+            | Permissions::PIN
+            | Permissions::DELETE_OTHERS
+            | Permissions::MUTE
+            | Permissions::SLOW_MODE;
+        match self {
+            Role::Guest => Permissions::empty(),
+            Role::Member => member,
+            Role::Moderator => moderator,
+            Role::Admin => {
+                moderator
+                    | Permissions::EDIT_TOPIC
+                    | Permissions::MANAGE_ROLES
+                    | Permissions::MANAGE_WEBHOOKS
+                    | Permissions::MANAGE_ROOM
+            }
+            Role::Owner => Permissions::all(),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = RolesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "guest" => Ok(Role::Guest),
+            "member" => Ok(Role::Member),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            "owner" => Ok(Role::Owner),
+            _ => Err(RolesError::Invalid),
+        }
+    }
+}
+
+/// Who a user is in a room: their role if they're a member, and whether
+/// they run the server.
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub role: Option<Role>,
+    pub server_admin: bool,
+}
+
+impl Access {
+    pub fn permissions(&self) -> Permissions {
+        let mut permissions = self
+            .role
+            .map(Role::permissions)
+            .unwrap_or(Permissions::empty());
+        // Server admins can moderate any room, but only post where they've
+        // joined.
+        if self.server_admin {
+            permissions |= Permissions::all() - Permissions::POST - Permissions::INVITE;
+        }
+        permissions
+    }
+
+    pub fn can(&self, permissions: Permissions) -> bool {
+        self.permissions().contains(permissions)
+    }
+
+    /// Whether this user sits above `role`, and so may act on its holders.
+    pub fn outranks(&self, role: Role) -> bool {
+        self.server_admin || self.role.is_some_and(|own| own > role)
+    }
+}
+
+pub async fn access(
+    db: impl PgExecutor<'_>,
+    room_id: Uuid,
+    user_id: Uuid,
+) -> Result<Access, sqlx::Error> {
+    let record = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select u.is_admin, m.role as "role?"
+            from "users" u
+            left join "room_members" m on m.room_id = $1 and m.user_id = u.user_id
+            where u.user_id = $2
+        "#,
+        room_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(match record {
+        Some(record) => Access {
+            role: record.role.and_then(|role| role.parse().ok()),
+            server_admin: record.is_admin,
+        },
+        None => Access {
+            role: None,
+            server_admin: false,
+        },
+    })
+}
+
+/// Check that a user holds `permissions` in a room. Every permission check,
+/// REST or websocket, goes through here.
+pub async fn authorize(
+    db: impl PgExecutor<'_>,
+    room_id: Uuid,
+    user_id: Uuid,
+    permissions: Permissions,
+) -> Result<Access, RolesError> {
+    let access = access(db, room_id, user_id).await?;
+    if access.can(permissions) {
+        Ok(access)
+    } else if access.role.is_none() && !access.server_admin {
+        Err(RolesError::NotMember)
+    } else {
+        Err(RolesError::Forbidden)
+    }
+}
+
+pub async fn is_server_admin(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let is_admin = sqlx::query_scalar!(
+        r#"select is_admin from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(is_admin.unwrap_or(false))
+}
+
+/// Mark the users named in `admins` as server admins.
+pub async fn grant_server_admin(db: &PgPool, admins: &[String]) -> Result<(), sqlx::Error> {
+    if admins.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"update "users" set is_admin = true where username = any($1)"#,
+        admins
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Change a member's role. You can only promote people to below your own
+/// role, and only change the role of members below you, so owners are
+/// appointed by server admins.
+pub async fn set_role(
+    state: &AppState,
+    room_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    target_id: Uuid,
+    role: Role,
+) -> Result<(), RolesError> {
+    let mut tx = state.db.begin().await?;
+
+    let access = authorize(&mut *tx, room_id, user_id, Permissions::MANAGE_ROLES).await?;
+
+    let target = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select m.role, u.username
+            from "room_members" m
+            join "users" u on u.user_id = m.user_id
+            where m.room_id = $1 and m.user_id = $2
+            for update of m
+        "#,
+        room_id,
+        target_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(RolesError::NotFound)?;
+    let current = target.role.parse::<Role>()?;
+
+    if current == role {
+        return Ok(());
+    }
+    if !access.outranks(current) || !access.outranks(role) {
+        return Err(RolesError::Forbidden);
+    }
+
+    sqlx::query!(
+        r#"update "room_members" set role = $1 where room_id = $2 and user_id = $3"#,
+        role.as_str(),
+        room_id,
+        target_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    state.publish(
+        room_id,
+        ServerEvent::RoleChanged {
+            room_id,
+            user_id: target_id,
+            username: target.username,
+            role,
+            by: username.to_string(),
+        },
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRoleRequest {
+    role: Role,
+}
+
+#[axum_macros::debug_handler]
+async fn set_role_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, target_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<SetRoleRequest>,
+) -> Result<StatusCode, RolesError> {
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(RolesError::NotFound)?;
+
+    set_role(
+        &state,
+        room_id,
+        user.user_id,
+        &username,
+        target_id,
+        req.role,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,45 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RoomsError {
+    #[error("Invalid request")]
+    Invalid,
+    #[error("Room name is taken")]
+    NameTaken,
+    #[error("Room not found")]
+    NotFound,
+    #[error("Not a member of this room")]
+    Forbidden,
+    #[error("You are banned from this room")]
+    Banned,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for RoomsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            RoomsError::Invalid => (StatusCode::BAD_REQUEST, "Invalid request".to_string()),
+            RoomsError::NameTaken => (StatusCode::CONFLICT, "Room name is taken".to_string()),
+            RoomsError::NotFound => (StatusCode::NOT_FOUND, "Room not found".to_string()),
+            RoomsError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            RoomsError::Banned => (
+                StatusCode::FORBIDDEN,
+                "You are banned from this room".to_string(),
+            ),
+            RoomsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
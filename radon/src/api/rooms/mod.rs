@@ -0,0 +1,403 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::RoomsError;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    client::Presence,
+    message::ServerEvent,
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{moderation, roles::Role, users, AppState};
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/rooms", get(fetch_rooms).post(create_room))
+        .route("/rooms/:room_id/join", post(join_room))
+        .route("/rooms/:room_id/members", get(fetch_members))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Room {
+    pub room_id: String,
+    pub name: String,
+    pub topic: String,
+    /// Minimum seconds between one member's posts. 0 when slow mode is off.
+    pub slow_mode_secs: i32,
+    /// Whether users can only join when a member invites them.
+    pub invite_only: bool,
+    #[serde(flatten)]
+    pub read: ReadState,
+}
+
+/// Where a member is up to in a room.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ReadState {
+    pub last_read_seq: i64,
+    /// Messages after `last_read_seq`.
+    pub unread: i64,
+    /// Unread messages that mention the member.
+    pub mentions: i64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateRoomRequest {
+    #[validate(length(min = 1, max = 64))]
+    name: String,
+    #[serde(default)]
+    topic: String,
+    #[serde(default)]
+    invite_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MembersQuery {
+    /// Only return members that are online or away.
+    #[serde(default)]
+    online: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Member {
+    pub user_id: String,
+    pub username: String,
+    pub role: Role,
+    pub muted: bool,
+    /// When a timed mute ends.
+    pub muted_until: Option<NaiveDateTime>,
+    pub presence: Presence,
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+pub async fn is_member(
+    db: impl PgExecutor<'_>,
+    room_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"select exists(select 1 from "room_members" where room_id = $1 and user_id = $2) as "exists!""#,
+        room_id,
+        user_id
+    )
+    .fetch_one(db)
+    .await
+}
+
+/// Move a member's read marker forward (never back, never past the end of
+/// the room) and return their read state afterwards. `None` if they aren't a
+/// member.
+pub async fn mark_read(
+    db: &PgPool,
+    room_id: Uuid,
+    user_id: Uuid,
+    seq: i64,
+) -> Result<Option<ReadState>, sqlx::Error> {
+    let updated = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "room_members" m
+            set last_read_seq = least(greatest(m.last_read_seq, $3), r.last_seq)
+            from "rooms" r
+            where r.room_id = m.room_id and m.room_id = $1 and m.user_id = $2
+        "#,
+        room_id,
+        user_id,
+        seq
+    )
+    .execute(db)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    read_state(db, room_id, user_id).await
+}
+
+pub async fn read_state(
+    db: &PgPool,
+    room_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<ReadState>, sqlx::Error> {
+    let record = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select m.last_read_seq,
+                   r.last_seq - m.last_read_seq as "unread!",
+                   (select count(*) from "mentions" n
+                    where n.room_id = m.room_id
+                      and n.user_id = m.user_id
+                      and n.seq > m.last_read_seq) as "mentions!"
+            from "room_members" m
+            join "rooms" r on r.room_id = m.room_id
+            where m.room_id = $1 and m.user_id = $2
+        "#,
+        room_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(record.map(|record| ReadState {
+        last_read_seq: record.last_read_seq,
+        unread: record.unread,
+        mentions: record.mentions,
+    }))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_rooms(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Vec<Room>>), RoomsError> {
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select r.room_id, r.name, r.topic, r.slow_mode_secs, r.invite_only, m.last_read_seq,
+                   r.last_seq - m.last_read_seq as "unread!",
+                   (select count(*) from "mentions" n
+                    where n.room_id = m.room_id
+                      and n.user_id = m.user_id
+                      and n.seq > m.last_read_seq) as "mentions!"
+            from "rooms" r
+            join "room_members" m on m.room_id = r.room_id
+            where m.user_id = $1
+            order by r.name
+        "#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let rooms = records
+        .into_iter()
+        .map(|record| Room {
+            room_id: record.room_id.to_string(),
+            name: record.name,
+            topic: record.topic,
+            slow_mode_secs: record.slow_mode_secs,
+            invite_only: record.invite_only,
+            read: ReadState {
+                last_read_seq: record.last_read_seq,
+                unread: record.unread,
+                mentions: record.mentions,
+            },
+        })
+        .collect::<Vec<Room>>();
+
+    Ok((StatusCode::OK, Json(rooms)))
+}
+
+#[axum_macros::debug_handler]
+async fn create_room(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<CreateRoomRequest>,
+) -> Result<(StatusCode, Json<Room>), RoomsError> {
+    req.validate().map_err(|_| RoomsError::Invalid)?;
+
+    let CreateRoomRequest {
+        name,
+        topic,
+        invite_only,
+    } = req;
+    let time = chrono::Utc::now().naive_utc();
+
+    let mut tx = state.db.begin().await?;
+
+    let res = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            insert into "rooms"(name, topic, invite_only, created_by, created_at)
+            values ($1, $2, $3, $4, $5)
+            returning room_id
+        "#,
+        name,
+        topic,
+        invite_only,
+        user.user_id,
+        time
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let room_id = match res {
+        Ok(room_id) => room_id,
+        Err(sqlx::Error::Database(dbe)) if dbe.constraint() == Some("rooms_name_key") => {
+            return Err(RoomsError::NameTaken)
+        }
+        Err(e) => return Err(RoomsError::Database(e)),
+    };
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "room_members"(room_id, user_id, joined_at, role)
+            values ($1, $2, $3, $4)
+        "#,
+        room_id,
+        user.user_id,
+        time,
+        Role::Owner.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    state.clients.join_room(user.user_id, room_id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Room {
+            room_id: room_id.to_string(),
+            name,
+            topic,
+            slow_mode_secs: 0,
+            invite_only,
+            read: ReadState::default(),
+        }),
+    ))
+}
+
+/// Join a room. Invite-only rooms can only be joined by being added with
+/// `/invite`.
+#[axum_macros::debug_handler]
+async fn join_room(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<StatusCode, RoomsError> {
+    if moderation::is_banned(&state.db, room_id, user.user_id).await? {
+        return Err(RoomsError::Banned);
+    }
+    let invite_only = sqlx::query_scalar!(
+        r#"select invite_only from "rooms" where room_id = $1"#,
+        room_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(RoomsError::NotFound)?;
+    if invite_only && !is_member(&state.db, room_id, user.user_id).await? {
+        return Err(RoomsError::Forbidden);
+    }
+
+    let time = chrono::Utc::now().naive_utc();
+
+    let res = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "room_members"(room_id, user_id, joined_at)
+            values ($1, $2, $3)
+            on conflict do nothing
+        "#,
+        room_id,
+        user.user_id,
+        time
+    )
+    .execute(&state.db)
+    .await;
+
+    match res {
+        Ok(done) if done.rows_affected() == 0 => return Ok(StatusCode::OK),
+        Ok(_) => {}
+        Err(sqlx::Error::Database(dbe)) if dbe.is_foreign_key_violation() => {
+            return Err(RoomsError::NotFound)
+        }
+        Err(e) => return Err(RoomsError::Database(e)),
+    }
+
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(RoomsError::NotFound)?;
+
+    state.clients.join_room(user.user_id, room_id);
+    state.publish(
+        room_id,
+        ServerEvent::MemberJoined {
+            room_id,
+            user_id: user.user_id,
+            username,
+        },
+    );
+
+    Ok(StatusCode::CREATED)
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_members(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Query(query): Query<MembersQuery>,
+) -> Result<(StatusCode, Json<Vec<Member>>), RoomsError> {
+    if !is_member(&state.db, room_id, user.user_id).await? {
+        return Err(RoomsError::Forbidden);
+    }
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select u.user_id, u.username, u.last_seen_at, m.role,
+                   mu.created_at as "muted_at?", mu.expires_at as muted_until
+            from "room_members" m
+            join "users" u on u.user_id = m.user_id
+            left join "room_mutes" mu on mu.room_id = m.room_id and mu.user_id = m.user_id
+                and (mu.expires_at is null or mu.expires_at > $2)
+            where m.room_id = $1
+            order by u.username
+        "#,
+        room_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let members = records
+        .into_iter()
+        .map(|record| {
+            let role = record.role.parse().unwrap_or(Role::Member);
+            match state.clients.presence(record.user_id) {
+                Some(info) => Member {
+                    user_id: record.user_id.to_string(),
+                    username: record.username,
+                    role,
+                    muted: record.muted_at.is_some(),
+                    muted_until: record.muted_until,
+                    presence: info.presence,
+                    last_seen: info.last_seen,
+                },
+                None => Member {
+                    user_id: record.user_id.to_string(),
+                    username: record.username,
+                    role,
+                    muted: record.muted_at.is_some(),
+                    muted_until: record.muted_until,
+                    presence: Presence::Offline,
+                    last_seen: record.last_seen_at,
+                },
+            }
+        })
+        .filter(|member| !query.online || member.presence != Presence::Offline)
+        .collect::<Vec<Member>>();
+
+    Ok((StatusCode::OK, Json(members)))
+}
@@ -0,0 +1,35 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error("Search query is empty")]
+    EmptyQuery,
+    #[error("Not a member of this room")]
+    Forbidden,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for SearchError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            SearchError::EmptyQuery => {
+                (StatusCode::BAD_REQUEST, "Search query is empty".to_string())
+            }
+            SearchError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Not a member of this room".to_string(),
+            ),
+            SearchError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
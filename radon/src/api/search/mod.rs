@@ -0,0 +1,171 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::SearchError;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    message::{ChatMessage, MessageType},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{rooms, AppState};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Markers around matched words in snippets.
+pub const HIGHLIGHT_START: &str = "«";
+pub const HIGHLIGHT_END: &str = "»";
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/search", get(search))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// Words to look for. Quoted phrases and `-word` work as in a web search.
+    q: String,
+    room: Option<Uuid>,
+    /// Username of the author.
+    from: Option<String>,
+    before: Option<NaiveDateTime>,
+    after: Option<NaiveDateTime>,
+    /// `next_cursor` from the previous page.
+    cursor: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub room_name: String,
+    /// The matching part of the message, with matches between
+    /// `HIGHLIGHT_START` and `HIGHLIGHT_END`.
+    pub snippet: String,
+    pub message: ChatMessage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchHit>,
+    /// Pass back as `cursor` for older results, if there are any.
+    pub next_cursor: Option<i64>,
+}
+
+/// Messages matching a query in rooms the caller is a member of, newest first.
+#[axum_macros::debug_handler]
+async fn search(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Query(query): Query<SearchQuery>,
+) -> Result<(StatusCode, Json<SearchPage>), SearchError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(SearchError::EmptyQuery);
+    }
+    if let Some(room_id) = query.room {
+        if !rooms::is_member(&state.db, room_id, user.user_id).await? {
+            return Err(SearchError::Forbidden);
+        }
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let options = format!(
+        "StartSel={}, StopSel={}, MaxWords=20, MinWords=8, MaxFragments=2",
+        HIGHLIGHT_START, HIGHLIGHT_END
+    );
+
+    // One extra row tells us whether there's another page.
+    let mut records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select m.message_id, m.room_id, r.name as room_name, m.seq, m.kind, m.user_id,
+                   coalesce(m.display_name, u.username) as "from?", m.body, m.created_at,
+                   m.edited_at, m.parent_id,
+                   ts_headline('english', m.body, websearch_to_tsquery('english', $2), $3)
+                       as "snippet!"
+            from "messages" m
+            join "room_members" rm on rm.room_id = m.room_id and rm.user_id = $1
+            join "rooms" r on r.room_id = m.room_id
+            left join "users" u on u.user_id = m.user_id
+            where m.search @@ websearch_to_tsquery('english', $2)
+              and m.deleted_at is null
+              and ($4::uuid is null or m.room_id = $4)
+              and ($5::text is null or lower(u.username) = lower($5))
+              and ($6::timestamp is null or m.created_at < $6)
+              and ($7::timestamp is null or m.created_at > $7)
+              and m.message_id < $8
+            order by m.message_id desc
+            limit $9
+        "#,
+        user.user_id,
+        q,
+        options,
+        query.room,
+        query.from,
+        query.before,
+        query.after,
+        query.cursor.unwrap_or(i64::MAX),
+        limit + 1
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let next_cursor = if records.len() as i64 > limit {
+        records.truncate(limit as usize);
+        records.last().map(|record| record.message_id)
+    } else {
+        None
+    };
+
+    let results = records
+        .into_iter()
+        .map(|record| SearchHit {
+            room_name: record.room_name,
+            snippet: record.snippet,
+            message: ChatMessage {
+                message_id: record.message_id,
+                room_id: record.room_id,
+                seq: record.seq,
+                kind: record.kind.parse().unwrap_or(MessageType::Text),
+                user_id: record.user_id,
+                from: record.from,
+                text: record.body,
+                created_at: record.created_at,
+                edited_at: record.edited_at,
+                deleted: false,
+                parent_id: record.parent_id,
+                reply_to: None,
+                thread: None,
+                reactions: Vec::new(),
+                attachments: Vec::new(),
+            },
+        })
+        .collect::<Vec<SearchHit>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(SearchPage {
+            results,
+            next_cursor,
+        }),
+    ))
+}
@@ -0,0 +1,48 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SshKeysError {
+    #[error("Invalid public key")]
+    InvalidKey,
+    #[error("Only ed25519 keys are supported")]
+    UnsupportedKey,
+    #[error("That key is already registered")]
+    KeyTaken,
+    #[error("Key not found")]
+    NotFound,
+    #[error("Login expired, start again")]
+    ChallengeExpired,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for SshKeysError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            SshKeysError::InvalidKey => (StatusCode::BAD_REQUEST, "Invalid public key".to_string()),
+            SshKeysError::UnsupportedKey => (
+                StatusCode::BAD_REQUEST,
+                "Only ed25519 keys are supported".to_string(),
+            ),
+            SshKeysError::KeyTaken => (
+                StatusCode::CONFLICT,
+                "That key is already registered".to_string(),
+            ),
+            SshKeysError::NotFound => (StatusCode::NOT_FOUND, "Key not found".to_string()),
+            SshKeysError::ChallengeExpired => (
+                StatusCode::UNAUTHORIZED,
+                "Login expired, start again".to_string(),
+            ),
+            SshKeysError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
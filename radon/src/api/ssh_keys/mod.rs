@@ -0,0 +1,276 @@
+pub mod error;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::SshKeysError;
+use rand::RngCore;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use ssh_key::{Algorithm, HashAlg, PublicKey, SshSig};
+use uuid::Uuid;
+
+use crate::middleware::{requires_auth, AuthUser};
+
+use super::AppState;
+
+/// The SSHSIG namespace login signatures are made in, so a signature made for
+/// radon can't be passed off as one for anything else, or the reverse.
+pub const NAMESPACE: &str = "radon-login";
+/// How long a client has to sign a challenge.
+const CHALLENGE_TTL_SECS: i64 = 60;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/ssh_keys", get(fetch_keys).post(add_key_handler))
+        .route("/me/ssh_keys/:key_id", delete(delete_key_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshKey {
+    pub key_id: Uuid,
+    pub fingerprint: String,
+    pub public_key: String,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddKeyRequest {
+    /// A public key as it appears in `~/.ssh/id_ed25519.pub`.
+    pub public_key: String,
+    /// Defaults to the key's comment.
+    pub name: Option<String>,
+}
+
+/// A nonce to sign with a registered key, in the [`NAMESPACE`] namespace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    pub challenge_id: Uuid,
+    pub nonce: String,
+    pub namespace: String,
+}
+
+fn parse_key(public_key: &str) -> Result<PublicKey, SshKeysError> {
+    let key = PublicKey::from_openssh(public_key.trim()).map_err(|_| SshKeysError::InvalidKey)?;
+    if key.algorithm() != Algorithm::Ed25519 {
+        return Err(SshKeysError::UnsupportedKey);
+    }
+    Ok(key)
+}
+
+pub async fn add_key(
+    db: impl PgExecutor<'_>,
+    user_id: Uuid,
+    public_key: &str,
+    name: Option<String>,
+) -> Result<SshKey, SshKeysError> {
+    let mut key = parse_key(public_key)?;
+    let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| Some(key.comment().to_string()).filter(|comment| !comment.is_empty()))
+        .unwrap_or_else(|| fingerprint.clone());
+    key.set_comment("");
+    let public_key = key.to_openssh().map_err(|_| SshKeysError::InvalidKey)?;
+
+    let key_id = Uuid::new_v4();
+    let created_at = chrono::Utc::now().naive_utc();
+    let res = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "ssh_keys"(key_id, user_id, fingerprint, public_key, name, created_at)
+            values ($1, $2, $3, $4, $5, $6)
+        "#,
+        key_id,
+        user_id,
+        fingerprint,
+        public_key,
+        name,
+        created_at
+    )
+    .execute(db)
+    .await;
+    match res {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(dbe)) if dbe.constraint() == Some("ssh_keys_fingerprint_key") => {
+            return Err(SshKeysError::KeyTaken)
+        }
+        Err(e) => return Err(SshKeysError::Database(e)),
+    }
+
+    Ok(SshKey {
+        key_id,
+        fingerprint,
+        public_key,
+        name,
+        created_at,
+        last_used_at: None,
+    })
+}
+
+/// Start a key login. Unknown usernames get a challenge too, stored without
+/// a user, which expires and is used up like any other but never verifies,
+/// so neither step reveals who has an account.
+pub async fn challenge(db: &PgPool, username: &str) -> Result<Challenge, SshKeysError> {
+    let mut nonce = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let challenge = Challenge {
+        challenge_id: Uuid::new_v4(),
+        nonce: hex::encode(nonce),
+        namespace: NAMESPACE.to_string(),
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        r#"delete from "ssh_challenges" where expires_at <= $1"#,
+        now
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "ssh_challenges"(challenge_id, user_id, nonce, expires_at)
+            values ($1, (select user_id from "users" where username = $4), $2, $3)
+        "#,
+        challenge.challenge_id,
+        challenge.nonce,
+        now + chrono::Duration::seconds(CHALLENGE_TTL_SECS),
+        username
+    )
+    .execute(db)
+    .await?;
+
+    Ok(challenge)
+}
+
+/// Use up a challenge, returning who it was issued to, if anyone, and its
+/// nonce.
+pub async fn take_challenge(
+    db: &PgPool,
+    challenge_id: Uuid,
+) -> Result<(Option<Uuid>, String), SshKeysError> {
+    let now = chrono::Utc::now().naive_utc();
+    let challenge = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            delete from "ssh_challenges"
+            where challenge_id = $1
+            returning user_id, nonce, expires_at
+        "#,
+        challenge_id
+    )
+    .fetch_optional(db)
+    .await?
+    .filter(|challenge| challenge.expires_at > now)
+    .ok_or(SshKeysError::ChallengeExpired)?;
+    Ok((challenge.user_id, challenge.nonce))
+}
+
+/// Check that `signature`, an armored SSHSIG, is `nonce` signed by one of
+/// the user's keys.
+pub async fn verify_signature(
+    db: &PgPool,
+    user_id: Uuid,
+    nonce: &str,
+    public_key: &str,
+    signature: &str,
+) -> Result<bool, SshKeysError> {
+    let Ok(key) = parse_key(public_key) else {
+        return Ok(false);
+    };
+    let Ok(signature) = SshSig::from_pem(signature) else {
+        return Ok(false);
+    };
+
+    let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+    let key_id = sqlx::query_scalar!(
+        r#"select key_id from "ssh_keys" where user_id = $1 and fingerprint = $2"#,
+        user_id,
+        fingerprint
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(key_id) = key_id else {
+        return Ok(false);
+    };
+
+    if key.verify(NAMESPACE, nonce.as_bytes(), &signature).is_err() {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"update "ssh_keys" set last_used_at = $1 where key_id = $2"#,
+        chrono::Utc::now().naive_utc(),
+        key_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(true)
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_keys(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Vec<SshKey>>), SshKeysError> {
+    let keys = sqlx::query_as!(
+        SshKey,
+        // language=PostgreSQL
+        r#"
+            select key_id, fingerprint, public_key, name, created_at, last_used_at
+            from "ssh_keys"
+            where user_id = $1
+            order by created_at
+        "#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(keys)))
+}
+
+#[axum_macros::debug_handler]
+async fn add_key_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<AddKeyRequest>,
+) -> Result<(StatusCode, Json<SshKey>), SshKeysError> {
+    let key = add_key(&state.db, user.user_id, &req.public_key, req.name).await?;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+#[axum_macros::debug_handler]
+async fn delete_key_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(key_id): Path<Uuid>,
+) -> Result<StatusCode, SshKeysError> {
+    let deleted = sqlx::query!(
+        r#"delete from "ssh_keys" where key_id = $1 and user_id = $2"#,
+        key_id,
+        user.user_id
+    )
+    .execute(&state.db)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(SshKeysError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
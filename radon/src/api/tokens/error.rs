@@ -0,0 +1,50 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TokensError {
+    #[error("Token name must be 1 to 64 characters")]
+    InvalidName,
+    #[error("Unknown scope: {0}")]
+    UnknownScope(String),
+    #[error("Name the rooms a posting token may post in")]
+    NoRooms,
+    #[error("You can only create tokens for yourself and your bots")]
+    NotYourBot,
+    #[error("Token not found")]
+    NotFound,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for TokensError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            TokensError::InvalidName => (
+                StatusCode::BAD_REQUEST,
+                "Token name must be 1 to 64 characters".to_string(),
+            ),
+            TokensError::UnknownScope(scope) => {
+                (StatusCode::BAD_REQUEST, format!("Unknown scope: {}", scope))
+            }
+            TokensError::NoRooms => (
+                StatusCode::BAD_REQUEST,
+                "Name the rooms a posting token may post in".to_string(),
+            ),
+            TokensError::NotYourBot => (
+                StatusCode::FORBIDDEN,
+                "You can only create tokens for yourself and your bots".to_string(),
+            ),
+            TokensError::NotFound => (StatusCode::NOT_FOUND, "Token not found".to_string()),
+            TokensError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
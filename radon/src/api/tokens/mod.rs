@@ -0,0 +1,350 @@
+pub mod error;
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::{Method, StatusCode},
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::{Duration, NaiveDateTime};
+use error::TokensError;
+use rand::{distributions::Alphanumeric, Rng};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::middleware::{requires_auth, AuthUser};
+
+use super::{auth::utils::hash_token, bots, ws, AppState};
+
+/// What every API token starts with, so it can be told from a session token
+/// and spotted by secret scanners.
+pub const TOKEN_PREFIX: &str = "radon_";
+/// How much of a token is kept in the clear, prefix included.
+const SHOWN_LEN: usize = 12;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/me/tokens", get(fetch_tokens).post(create_token))
+        .route("/me/tokens/:token_id", delete(revoke_token))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+/// Something an API token is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenScope {
+    /// Read rooms, messages and members, and receive events.
+    #[serde(rename = "rooms:read")]
+    ReadRooms,
+    /// Join and post in the rooms the token names.
+    #[serde(rename = "rooms:post")]
+    PostRooms,
+    #[serde(rename = "webhooks:manage")]
+    ManageWebhooks,
+}
+
+impl TokenScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::ReadRooms => "rooms:read",
+            TokenScope::PostRooms => "rooms:post",
+            TokenScope::ManageWebhooks => "webhooks:manage",
+        }
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = TokensError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rooms:read" => Ok(TokenScope::ReadRooms),
+            "rooms:post" => Ok(TokenScope::PostRooms),
+            "webhooks:manage" => Ok(TokenScope::ManageWebhooks),
+            _ => Err(TokensError::UnknownScope(s.to_string())),
+        }
+    }
+}
+
+/// What the API token a request came with allows. Callers with a session
+/// token have no grant and may do anything their user can.
+#[derive(Debug, Clone)]
+pub struct TokenGrant {
+    pub token_id: Uuid,
+    pub scopes: Vec<TokenScope>,
+    pub rooms: Vec<Uuid>,
+}
+
+impl TokenGrant {
+    pub fn has(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn can_post(&self, room_id: Uuid) -> bool {
+        self.has(TokenScope::PostRooms) && self.rooms.contains(&room_id)
+    }
+
+    /// Whether a REST call may be made with this token. `route` is the
+    /// matched route and `path` the actual one. Anything not listed, such as
+    /// managing tokens, passwords or keys, needs a session.
+    pub fn allows(&self, method: &Method, route: &str, path: &str) -> bool {
+        let room_id = || path.split('/').nth(2).and_then(|id| id.parse().ok());
+        match (method, route) {
+            (&Method::GET, "/me") => true,
+            (
+                &Method::GET,
+                "/rooms"
+                | "/rooms/:room_id/messages"
+                | "/rooms/:room_id/members"
+                | "/rooms/:room_id/pins"
+                | "/messages/:message_id/history"
+                | "/messages/:message_id/thread"
+                | "/search"
+                | "/uploads/:upload_id",
+            ) => self.has(TokenScope::ReadRooms),
+            (&Method::POST, "/rooms/:room_id/join" | "/rooms/:room_id/messages") => {
+                room_id().is_some_and(|id| self.can_post(id))
+            }
+            (&Method::POST, "/uploads") => self.has(TokenScope::PostRooms),
+            (&Method::GET | &Method::POST | &Method::DELETE, route)
+                if route.starts_with("/rooms/:room_id/webhooks")
+                    || route.starts_with("/rooms/:room_id/outgoing_webhooks") =>
+            {
+                self.has(TokenScope::ManageWebhooks)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A token as listed back to its creator. The secret itself is gone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub token_id: Uuid,
+    /// The user the token acts as.
+    pub user_id: Uuid,
+    pub username: String,
+    pub name: String,
+    /// The first few characters of the token.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub rooms: Vec<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    /// One of the caller's bots to act as. Defaults to the caller.
+    bot_id: Option<Uuid>,
+    scopes: Vec<String>,
+    /// Rooms a token with `rooms:post` may post in.
+    #[serde(default)]
+    rooms: Vec<Uuid>,
+    /// Never expires if not given.
+    expires_in_days: Option<u32>,
+}
+
+/// A freshly made token. This is the only time `token` is shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiToken,
+}
+
+/// The key a token is known by when rate limiting, so the token itself isn't
+/// kept in memory.
+pub fn limiter_key(token: &str) -> String {
+    hash_token(token)
+}
+
+/// Look up a bearer token that looks like an API token, recording its use.
+/// Revoked and expired tokens, and scopes we no longer know, grant nothing.
+pub async fn authenticate(
+    db: impl PgExecutor<'_>,
+    token: &str,
+) -> Result<Option<(Uuid, TokenGrant)>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let record = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "api_tokens" set last_used_at = $2
+            where token_hash = $1
+              and revoked_at is null
+              and (expires_at is null or expires_at > $2)
+            returning token_id, user_id, scopes, rooms
+        "#,
+        hash_token(token),
+        now
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(record.map(|record| {
+        let grant = TokenGrant {
+            token_id: record.token_id,
+            scopes: record
+                .scopes
+                .iter()
+                .filter_map(|scope| scope.parse().ok())
+                .collect(),
+            rooms: record.rooms,
+        };
+        (record.user_id, grant)
+    }))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_tokens(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Vec<ApiToken>>), TokensError> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        // language=PostgreSQL
+        r#"
+            select t.token_id, t.user_id, u.username, t.name, t.prefix, t.scopes, t.rooms,
+                   t.created_at, t.expires_at, t.last_used_at
+            from "api_tokens" t
+            join "users" u on u.user_id = t.user_id
+            where t.created_by = $1 and t.revoked_at is null
+            order by t.created_at
+        "#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+#[axum_macros::debug_handler]
+async fn create_token(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Json(req): Json<CreateTokenRequest>,
+) -> Result<(StatusCode, Json<CreatedToken>), TokensError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(TokensError::InvalidName);
+    }
+    let scopes = req
+        .scopes
+        .iter()
+        .map(|scope| scope.parse::<TokenScope>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if scopes.contains(&TokenScope::PostRooms) && req.rooms.is_empty() {
+        return Err(TokensError::NoRooms);
+    }
+
+    let user_id = match req.bot_id {
+        Some(bot_id) if bot_id != user.user_id => {
+            if !bots::owns(&state.db, user.user_id, bot_id).await? {
+                return Err(TokensError::NotYourBot);
+            }
+            bot_id
+        }
+        _ => user.user_id,
+    };
+
+    let token = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect::<String>()
+    );
+    let token_id = Uuid::new_v4();
+    let prefix = token[..SHOWN_LEN].to_string();
+    let scopes = scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect::<Vec<_>>();
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = req
+        .expires_in_days
+        .map(|days| now + Duration::days(days.into()));
+
+    let username = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            insert into "api_tokens"(token_id, user_id, created_by, name, token_hash, prefix,
+                                     scopes, rooms, created_at, expires_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            returning (select username from "users" where user_id = $2) as "username!"
+        "#,
+        token_id,
+        user_id,
+        user.user_id,
+        name,
+        hash_token(&token),
+        prefix,
+        &scopes,
+        &req.rooms,
+        now,
+        expires_at
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedToken {
+            token,
+            info: ApiToken {
+                token_id,
+                user_id,
+                username,
+                name,
+                prefix,
+                scopes,
+                rooms: req.rooms,
+                created_at: now,
+                expires_at,
+                last_used_at: None,
+            },
+        }),
+    ))
+}
+
+#[axum_macros::debug_handler]
+async fn revoke_token(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, TokensError> {
+    let owner = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            update "api_tokens" set revoked_at = $1
+            where token_id = $2 and created_by = $3 and revoked_at is null
+            returning user_id
+        "#,
+        chrono::Utc::now().naive_utc(),
+        token_id,
+        user.user_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(TokensError::NotFound)?;
+
+    // Sockets and streams opened with it would otherwise keep going.
+    ws::disconnect_token(&state, owner, token_id).await;
+    Ok(StatusCode::NO_CONTENT)
+}
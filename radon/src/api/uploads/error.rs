@@ -0,0 +1,61 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum UploadsError {
+    #[error("Invalid upload")]
+    Invalid,
+    #[error("Upload not found")]
+    NotFound,
+    #[error("File is too large")]
+    TooLarge,
+    #[error("Upload quota exceeded")]
+    QuotaExceeded,
+    #[error("Storage error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl UploadsError {
+    /// Stable, machine readable code for websocket clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UploadsError::Invalid => "invalid",
+            UploadsError::NotFound => "not_found",
+            UploadsError::TooLarge => "too_large",
+            UploadsError::QuotaExceeded => "quota_exceeded",
+            UploadsError::Io(_) | UploadsError::Database(_) => "internal",
+        }
+    }
+}
+
+impl IntoResponse for UploadsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            UploadsError::Invalid => (StatusCode::BAD_REQUEST, "Invalid upload".to_string()),
+            UploadsError::NotFound => (StatusCode::NOT_FOUND, "Upload not found".to_string()),
+            UploadsError::TooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "File is too large".to_string(),
+            ),
+            UploadsError::QuotaExceeded => (
+                StatusCode::INSUFFICIENT_STORAGE,
+                "Upload quota exceeded".to_string(),
+            ),
+            UploadsError::Io(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Storage error: {}", e),
+            ),
+            UploadsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
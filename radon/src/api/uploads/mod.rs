@@ -0,0 +1,422 @@
+pub mod error;
+
+use std::{
+    collections::HashMap,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    body::StreamBody,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::UploadsError;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    config::ServerConfig,
+    message::{Attachment, ChatMessage},
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::AppState;
+
+/// Bytes kept from the start of an upload to sniff its type.
+const SNIFF_LEN: usize = 8192;
+const MAX_FILENAME_LEN: usize = 255;
+/// Files one message can carry.
+pub const MAX_ATTACHMENTS: usize = 10;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    // Leave room for the multipart framing around the file itself.
+    let body_limit = state.config.max_upload_bytes as usize + 64 * 1024;
+
+    Router::new()
+        .route("/uploads", post(upload))
+        .route("/uploads/:upload_id", get(download))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ))
+        .with_state(state)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Upload {
+    pub upload_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub mime: String,
+    pub sha256: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Where the blob with this hash lives: `<upload_dir>/ab/cd/abcd…`.
+fn blob_path(upload_dir: &str, sha256: &str) -> PathBuf {
+    FsPath::new(upload_dir)
+        .join(&sha256[0..2])
+        .join(&sha256[2..4])
+        .join(sha256)
+}
+
+/// Keep the last path component and drop anything that would upset a
+/// `Content-Disposition` header.
+fn clean_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_LEN)
+        .collect::<String>();
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Work out a file's type from its first bytes. We don't trust what the
+/// client says it is.
+fn sniff_mime(head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    // A multi-byte character may be cut off at the end of the sample.
+    let text = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if text {
+        "text/plain; charset=utf-8".to_string()
+    } else {
+        "application/octet-stream".to_string()
+    }
+}
+
+/// Whether a file fits in both the user's quota and the server's. A file
+/// the user already uploaded doesn't count against them again, nor one the
+/// server already stores against the server.
+async fn fits_quota(
+    db: &mut PgConnection,
+    config: &ServerConfig,
+    user_id: Uuid,
+    sha256: &str,
+    size: u64,
+) -> Result<bool, sqlx::Error> {
+    let used = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select (select coalesce(sum(size), 0)
+                    from (select distinct sha256, size from "uploads" where user_id = $1) own
+                   )::bigint as "user!",
+                   exists(select 1 from "uploads" where user_id = $1 and sha256 = $2)
+                       as "user_has!",
+                   (select coalesce(sum(size), 0) from "blobs")::bigint as "total!",
+                   exists(select 1 from "blobs" where sha256 = $2) as "stored!"
+        "#,
+        user_id,
+        sha256
+    )
+    .fetch_one(db)
+    .await?;
+
+    let user_size = if used.user_has { 0 } else { size };
+    let total_size = if used.stored { 0 } else { size };
+    Ok(
+        used.user as u64 + user_size <= config.user_upload_quota_bytes
+            && used.total as u64 + total_size <= config.upload_quota_bytes,
+    )
+}
+
+/// Store a file sent as the `file` field of a multipart form. The file is
+/// streamed to a temporary path while it's hashed, then, if it fits the
+/// quotas, moved into place unless we already have a blob with the same
+/// hash.
+#[axum_macros::debug_handler]
+async fn upload(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Upload>), UploadsError> {
+    let max_upload = state.config.max_upload_bytes;
+
+    let mut field = loop {
+        match multipart
+            .next_field()
+            .await
+            .map_err(|_| UploadsError::Invalid)?
+        {
+            Some(field) if field.name() == Some("file") => break field,
+            Some(_) => continue,
+            None => return Err(UploadsError::Invalid),
+        }
+    };
+    let filename = field
+        .file_name()
+        .and_then(clean_filename)
+        .ok_or(UploadsError::Invalid)?;
+
+    let tmp_dir = FsPath::new(&state.config.upload_dir).join("tmp");
+    fs::create_dir_all(&tmp_dir).await?;
+    let tmp_path = tmp_dir.join(Uuid::new_v4().to_string());
+
+    let written = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        let mut hasher = Sha256::new();
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        let mut size: u64 = 0;
+
+        while let Some(chunk) = field.chunk().await.map_err(|_| UploadsError::Invalid)? {
+            size += chunk.len() as u64;
+            if size > max_upload {
+                return Err(UploadsError::TooLarge);
+            }
+            if head.len() < SNIFF_LEN {
+                let take = (SNIFF_LEN - head.len()).min(chunk.len());
+                head.extend_from_slice(&chunk[..take]);
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok::<_, UploadsError>((hex::encode(hasher.finalize()), size, sniff_mime(&head)))
+    }
+    .await;
+
+    let (sha256, size, mime) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(e);
+        }
+    };
+
+    let mut tx = state.db.begin().await?;
+    // One upload per user at a time, so two can't both fit in what's left.
+    sqlx::query!(
+        r#"select user_id from "users" where user_id = $1 for update"#,
+        user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !fits_quota(&mut tx, &state.config, user.user_id, &sha256, size).await? {
+        fs::remove_file(&tmp_path).await?;
+        return Err(UploadsError::QuotaExceeded);
+    }
+
+    let path = blob_path(&state.config.upload_dir, &sha256);
+    if fs::try_exists(&path).await? {
+        fs::remove_file(&tmp_path).await?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&tmp_path, &path).await?;
+    }
+
+    let upload = Upload {
+        upload_id: Uuid::new_v4(),
+        filename,
+        size: size as i64,
+        mime,
+        sha256,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "blobs"(sha256, size, mime, created_at)
+            values ($1, $2, $3, $4)
+            on conflict do nothing
+        "#,
+        upload.sha256,
+        upload.size,
+        upload.mime,
+        upload.created_at
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "uploads"(upload_id, user_id, sha256, filename, size, mime, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        upload.upload_id,
+        user.user_id,
+        upload.sha256,
+        upload.filename,
+        upload.size,
+        upload.mime,
+        upload.created_at
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(upload)))
+}
+
+/// Stream a file back. The uploader can always fetch it; anyone else needs
+/// to be in a room where it was posted.
+#[axum_macros::debug_handler]
+async fn download(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(upload_id): Path<Uuid>,
+) -> Result<impl IntoResponse, UploadsError> {
+    let upload = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select u.sha256, u.filename, u.size, u.mime
+            from "uploads" u
+            where u.upload_id = $1
+              and (u.user_id = $2 or exists(
+                  select 1
+                  from "message_attachments" a
+                  join "messages" m on m.message_id = a.message_id
+                  join "room_members" rm on rm.room_id = m.room_id and rm.user_id = $2
+                  where a.upload_id = u.upload_id and m.deleted_at is null
+              ))
+        "#,
+        upload_id,
+        user.user_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(UploadsError::NotFound)?;
+
+    let file = fs::File::open(blob_path(&state.config.upload_dir, &upload.sha256)).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&upload.mime)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(upload.size));
+    if let Ok(disposition) =
+        HeaderValue::from_str(&format!("attachment; filename=\"{}\"", upload.filename))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+
+    Ok((headers, StreamBody::new(ReaderStream::new(file))))
+}
+
+/// The uploads behind `upload_ids`, checking they all belong to `user_id`.
+pub async fn owned_attachments(
+    db: &PgPool,
+    user_id: Uuid,
+    upload_ids: &[Uuid],
+) -> Result<Vec<Attachment>, UploadsError> {
+    if upload_ids.len() > MAX_ATTACHMENTS {
+        return Err(UploadsError::Invalid);
+    }
+    let attachments = sqlx::query_as!(
+        Attachment,
+        // language=PostgreSQL
+        r#"
+            select upload_id, filename, size, mime
+            from "uploads"
+            where upload_id = any($1) and user_id = $2
+            order by array_position($1, upload_id)
+        "#,
+        upload_ids,
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    if attachments.len() != upload_ids.len() {
+        return Err(UploadsError::NotFound);
+    }
+    Ok(attachments)
+}
+
+/// Link uploads to a message as it's posted.
+pub async fn link_attachments(
+    db: impl PgExecutor<'_>,
+    message_id: i64,
+    attachments: &[Attachment],
+) -> Result<(), sqlx::Error> {
+    let ids = attachments
+        .iter()
+        .map(|attachment| attachment.upload_id)
+        .collect::<Vec<Uuid>>();
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "message_attachments"(message_id, upload_id)
+            select $1, unnest($2::uuid[])
+            on conflict do nothing
+        "#,
+        message_id,
+        &ids
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Fill in the attachments on a page of messages.
+pub async fn attach_attachments(
+    db: &PgPool,
+    messages: &mut [ChatMessage],
+) -> Result<(), sqlx::Error> {
+    let ids = messages
+        .iter()
+        .map(|message| message.message_id)
+        .collect::<Vec<i64>>();
+
+    let records = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select a.message_id, u.upload_id, u.filename, u.size, u.mime
+            from "message_attachments" a
+            join "uploads" u on u.upload_id = a.upload_id
+            where a.message_id = any($1)
+            order by a.message_id, u.created_at
+        "#,
+        &ids
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut attachments: HashMap<i64, Vec<Attachment>> = HashMap::new();
+    for record in records {
+        attachments
+            .entry(record.message_id)
+            .or_default()
+            .push(Attachment {
+                upload_id: record.upload_id,
+                filename: record.filename,
+                size: record.size,
+                mime: record.mime,
+            });
+    }
+    for message in messages.iter_mut() {
+        if !message.deleted {
+            message.attachments = attachments.remove(&message.message_id).unwrap_or_default();
+        }
+    }
+    Ok(())
+}
@@ -2,6 +2,8 @@ use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 use thiserror::Error;
 
+use crate::api::{passwords::error::PasswordError, ssh_keys::error::SshKeysError};
+
 #[derive(Error, Debug)]
 pub enum UsersError {
     #[error("Invalid username or password")]
@@ -12,6 +14,10 @@ pub enum UsersError {
     Database(#[from] sqlx::Error),
     #[error("Invalid password")]
     BadPassword,
+    #[error(transparent)]
+    SshKey(#[from] SshKeysError),
+    #[error(transparent)]
+    Password(#[from] PasswordError),
 }
 
 // should I implement into response or just respond_with_json
@@ -25,6 +31,8 @@ impl IntoResponse for UsersError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Database error: {}", e),
             ),
+            UsersError::SshKey(e) => return e.into_response(),
+            UsersError::Password(e) => return e.into_response(),
             // handle other variants
         };
 
@@ -6,7 +6,7 @@ use axum::{
     extract::State,
     http::StatusCode,
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use error::UsersError;
 use rand::Rng;
@@ -15,13 +15,21 @@ use sqlx::PgExecutor;
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::api::auth::utils;
+use crate::{
+    api::{auth::utils, passwords, ssh_keys, tokens::TokenScope},
+    middleware::{requires_auth, AuthUser},
+};
 
 use super::AppState;
 
 pub fn router(state: Arc<AppState>) -> Router {
+    let me = Router::new().route("/me", get(fetch_me)).route_layer(
+        axum::middleware::from_fn_with_state(state.clone(), requires_auth),
+    );
+
     Router::new()
         .route("/users", get(fetch_users).post(create_user))
+        .merge(me)
         .with_state(state)
 }
 
@@ -29,12 +37,26 @@ pub fn router(state: Arc<AppState>) -> Router {
 pub struct User {
     pub user_id: String,
     pub username: String,
+    pub is_bot: bool,
+}
+
+/// Who the caller is, for clients that only hold a token.
+#[derive(Serialize, Deserialize)]
+pub struct Me {
+    pub user_id: Uuid,
+    pub username: String,
+    pub is_bot: bool,
+    /// The scopes of the API token the request was made with, if it was.
+    pub scopes: Option<Vec<TokenScope>>,
 }
 
 #[derive(Deserialize, Validate)]
 pub struct RegisterRequest {
+    #[validate(length(min = 1, max = 32))]
     username: String,
-    password: String,
+    password: Option<String>,
+    /// A public key to log in with instead of a password.
+    ssh_key: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,6 +65,19 @@ pub struct RegisterResponse {
     username: String,
 }
 
+/// A user's name, or `None` if there's no such user.
+pub async fn username(
+    db: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"select username from "users" where user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+}
+
 #[axum_macros::debug_handler]
 async fn create_user(
     State(state): State<Arc<AppState>>,
@@ -51,44 +86,66 @@ async fn create_user(
     // println!("req: {:?}", "hello");
     req.validate().map_err(|_| UsersError::Invalid)?;
 
-    let RegisterRequest { username, password } = req;
+    let RegisterRequest {
+        username,
+        password,
+        ssh_key,
+    } = req;
+
+    // Accounts that only log in with a key get a password nobody knows.
+    let password = match (password, &ssh_key) {
+        (Some(password), _) => {
+            passwords::check(&state.config.password_policy, &username, &password)?;
+            password
+        }
+        (None, Some(_)) => hex::encode(rand::thread_rng().gen::<[u8; 32]>()),
+        (None, None) => return Err(UsersError::Invalid),
+    };
 
     // It would be irresponsible to store passwords in plaintext, however.
     //
-    let password_hash = utils::hash(password)
+    let password_hash = utils::hash(password, state.config.argon2)
         .await
         .map_err(|_| UsersError::BadPassword)?;
 
     let time = chrono::Utc::now().naive_utc();
 
-    let res = sqlx::query!(
+    let mut tx = state.db.begin().await?;
+
+    let res = sqlx::query_scalar!(
         // language=PostgreSQL
         r#"
             insert into "users"(username, password_hash, created_at, updated_at)
             values ($1, $2, $3, $4)
+            returning user_id
         "#,
         username,
         password_hash,
         time.clone(),
         time
     )
-    .execute(&state.db)
+    .fetch_one(&mut *tx)
     .await;
-    match res {
-        Ok(_) => {
-            return Ok((
-                StatusCode::CREATED,
-                Json(RegisterResponse {
-                    user_id: "user_id".to_string(),
-                    username,
-                }),
-            ))
-        }
+    let user_id = match res {
+        Ok(user_id) => user_id,
         Err(sqlx::Error::Database(dbe)) if dbe.constraint() == Some("user_username_key") => {
             return Err(UsersError::UsernameTaken)
         }
         Err(e) => return Err(UsersError::Database(e)),
     };
+
+    if let Some(ssh_key) = ssh_key {
+        ssh_keys::add_key(&mut *tx, user_id, &ssh_key, None).await?;
+    }
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisterResponse {
+            user_id: user_id.to_string(),
+            username,
+        }),
+    ))
 }
 
 #[axum_macros::debug_handler]
@@ -111,6 +168,7 @@ async fn fetch_users(
                 .map(|record| User {
                     user_id: record.user_id.to_string(),
                     username: record.username,
+                    is_bot: record.is_bot,
                 })
                 .collect::<Vec<User>>();
             return Ok((StatusCode::OK, Json(users)));
@@ -118,3 +176,26 @@ async fn fetch_users(
         Err(e) => return Err(UsersError::Database(e)),
     };
 }
+
+#[axum_macros::debug_handler]
+async fn fetch_me(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+) -> Result<(StatusCode, Json<Me>), UsersError> {
+    let record = sqlx::query!(
+        r#"select username, is_bot from "users" where user_id = $1"#,
+        user.user_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(Me {
+            user_id: user.user_id,
+            username: record.username,
+            is_bot: record.is_bot,
+            scopes: user.token.map(|grant| grant.scopes.clone()),
+        }),
+    ))
+}
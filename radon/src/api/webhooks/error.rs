@@ -0,0 +1,90 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::api::{
+    bots::error::BotsError, messages::error::MessagesError, moderation::error::ModerationError,
+    roles::error::RolesError,
+};
+
+#[derive(Error, Debug)]
+pub enum WebhooksError {
+    #[error("Invalid webhook request")]
+    Invalid,
+    #[error("Webhook not found")]
+    NotFound,
+    #[error("Delivery not found")]
+    DeliveryNotFound,
+    #[error("Webhook URLs must be http or https")]
+    InvalidUrl,
+    #[error("Webhook URL's host can't be resolved")]
+    UnresolvableUrl,
+    #[error("Webhook URLs can't point at private addresses")]
+    PrivateUrl,
+    #[error("Unknown event: {0}")]
+    UnknownEvent(String),
+    #[error("You can only post as your own bots")]
+    NotYourBot,
+    #[error("That name belongs to a user")]
+    NameTaken,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Roles(#[from] RolesError),
+    #[error(transparent)]
+    Moderation(#[from] ModerationError),
+    #[error(transparent)]
+    Messages(#[from] MessagesError),
+    #[error(transparent)]
+    Bots(#[from] BotsError),
+}
+
+impl IntoResponse for WebhooksError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            WebhooksError::Invalid => (
+                StatusCode::BAD_REQUEST,
+                "Invalid webhook request".to_string(),
+            ),
+            WebhooksError::NotFound => (StatusCode::NOT_FOUND, "Webhook not found".to_string()),
+            WebhooksError::DeliveryNotFound => {
+                (StatusCode::NOT_FOUND, "Delivery not found".to_string())
+            }
+            WebhooksError::InvalidUrl => (
+                StatusCode::BAD_REQUEST,
+                "Webhook URLs must be http or https".to_string(),
+            ),
+            WebhooksError::UnresolvableUrl => (
+                StatusCode::BAD_REQUEST,
+                "Webhook URL's host can't be resolved".to_string(),
+            ),
+            WebhooksError::PrivateUrl => (
+                StatusCode::BAD_REQUEST,
+                "Webhook URLs can't point at private addresses".to_string(),
+            ),
+            WebhooksError::UnknownEvent(event) => {
+                (StatusCode::BAD_REQUEST, format!("Unknown event: {}", event))
+            }
+            WebhooksError::NotYourBot => (
+                StatusCode::FORBIDDEN,
+                "You can only post as your own bots".to_string(),
+            ),
+            WebhooksError::NameTaken => (
+                StatusCode::FORBIDDEN,
+                "That name belongs to a user".to_string(),
+            ),
+            WebhooksError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+            WebhooksError::Roles(e) => return e.into_response(),
+            WebhooksError::Moderation(e) => return e.into_response(),
+            WebhooksError::Messages(e) => return e.into_response(),
+            WebhooksError::Bots(e) => return e.into_response(),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
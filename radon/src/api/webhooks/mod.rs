@@ -0,0 +1,431 @@
+pub mod error;
+pub mod outgoing;
+
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use error::WebhooksError;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    message::ServerEvent,
+    middleware::{requires_auth, AuthUser},
+};
+
+use super::{
+    auth::utils::hash_token,
+    bots,
+    messages::{self, NewMessage},
+    moderation::{self, error::ModerationError},
+    roles::{self, Permissions},
+    users, AppState,
+};
+
+/// Longest name a webhook may post under.
+const MAX_USERNAME_LEN: usize = 32;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    let manage = Router::new()
+        .route(
+            "/rooms/:room_id/webhooks",
+            get(fetch_hooks).post(create_hook),
+        )
+        .route("/rooms/:room_id/webhooks/:hook_id", delete(delete_hook))
+        .merge(outgoing::routes())
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            requires_auth,
+        ));
+
+    // Posting is authorized by the hook's own secret.
+    Router::new()
+        .route("/hooks/:hook_id", post(post_hook))
+        .merge(manage)
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub hook_id: Uuid,
+    pub room_id: Uuid,
+    pub name: String,
+    /// The bot the hook posts as.
+    pub bot_id: Uuid,
+    pub bot_username: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateHookRequest {
+    name: String,
+    /// One of the caller's bots to post as. A new bot named after the hook is
+    /// made if not given.
+    bot_id: Option<Uuid>,
+}
+
+/// A freshly made hook. This is the only time its secret is shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedHook {
+    pub token: String,
+    /// Where to post, secret included, for tools that only take a URL.
+    pub path: String,
+    #[serde(flatten)]
+    pub info: Webhook,
+}
+
+/// What a webhook post carries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookMessage {
+    #[serde(default)]
+    pub text: String,
+    /// Post under this name instead of the bot's. It can't be another user's.
+    pub username_override: Option<String>,
+    /// Uploads made by the hook's bot.
+    #[serde(default)]
+    pub attachments: Vec<Uuid>,
+}
+
+/// A form encoded post: the fields themselves, or the JSON body in
+/// `payload`, as Slack style integrations send it.
+#[derive(Debug, Deserialize)]
+struct HookForm {
+    payload: Option<String>,
+    text: Option<String>,
+    username_override: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookQuery {
+    token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostedMessage {
+    pub message_id: i64,
+    pub seq: i64,
+}
+
+/// Read a webhook post, JSON or form encoded, going by its content type.
+fn parse_body(headers: &HeaderMap, body: &[u8]) -> Result<HookMessage, WebhooksError> {
+    let form = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !form {
+        return serde_json::from_slice(body).map_err(|_| WebhooksError::Invalid);
+    }
+
+    let form: HookForm = serde_urlencoded::from_bytes(body).map_err(|_| WebhooksError::Invalid)?;
+    match form.payload {
+        Some(payload) => serde_json::from_str(&payload).map_err(|_| WebhooksError::Invalid),
+        None => Ok(HookMessage {
+            text: form.text.unwrap_or_default(),
+            username_override: form.username_override,
+            attachments: Vec::new(),
+        }),
+    }
+}
+
+/// Post a message through a webhook. Hooks post as their bot, so kicking,
+/// banning or muting the bot silences the hook.
+pub async fn post_hook_message(
+    state: &AppState,
+    hook_id: Uuid,
+    token: &str,
+    message: HookMessage,
+) -> Result<PostedMessage, WebhooksError> {
+    let hook = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select h.room_id, h.bot_id, u.username
+            from "incoming_webhooks" h
+            join "users" u on u.user_id = h.bot_id
+            where h.hook_id = $1 and h.token_hash = $2
+        "#,
+        hook_id,
+        hash_token(token)
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(WebhooksError::NotFound)?;
+
+    let HookMessage {
+        text,
+        username_override,
+        attachments,
+    } = message;
+    let from = match username_override.map(|name| name.trim().to_string()) {
+        Some(name) if name.is_empty() || name.chars().count() > MAX_USERNAME_LEN => {
+            return Err(WebhooksError::Invalid)
+        }
+        name => name,
+    };
+    // Hooks can't pass themselves off as someone else. Their own bot's name
+    // is fine, and is stored as no override at all.
+    if let Some(name) = &from {
+        let taken = sqlx::query_scalar!(
+            // language=PostgreSQL
+            r#"
+                select exists(
+                    select 1 from "users" where lower(username) = lower($1) and user_id <> $2
+                ) as "taken!"
+            "#,
+            name,
+            hook.bot_id
+        )
+        .fetch_one(&state.db)
+        .await?;
+        if taken {
+            return Err(WebhooksError::NameTaken);
+        }
+    }
+
+    let message = messages::post_message(
+        state,
+        hook.bot_id,
+        &hook.username,
+        from,
+        hook.room_id,
+        NewMessage {
+            text,
+            parent_id: None,
+            attachments,
+        },
+    )
+    .await?;
+
+    sqlx::query!(
+        r#"update "incoming_webhooks" set last_used_at = $1 where hook_id = $2"#,
+        message.created_at,
+        hook_id
+    )
+    .execute(&state.db)
+    .await?;
+
+    Ok(PostedMessage {
+        message_id: message.message_id,
+        seq: message.seq,
+    })
+}
+
+#[axum_macros::debug_handler]
+async fn post_hook(
+    State(state): State<Arc<AppState>>,
+    Path(hook_id): Path<Uuid>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<PostedMessage>), WebhooksError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or(query.token)
+        .ok_or(WebhooksError::NotFound)?;
+    let message = parse_body(&headers, &body)?;
+
+    let posted = post_hook_message(&state, hook_id, &token, message).await?;
+    Ok((StatusCode::CREATED, Json(posted)))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_hooks(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Vec<Webhook>>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let hooks = sqlx::query_as!(
+        Webhook,
+        // language=PostgreSQL
+        r#"
+            select h.hook_id, h.room_id, h.name, h.bot_id, u.username as bot_username,
+                   h.created_at, h.last_used_at
+            from "incoming_webhooks" h
+            join "users" u on u.user_id = h.bot_id
+            where h.room_id = $1
+            order by h.created_at
+        "#,
+        room_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(hooks)))
+}
+
+/// Make a webhook, joining its bot to the room.
+#[axum_macros::debug_handler]
+async fn create_hook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Json(req): Json<CreateHookRequest>,
+) -> Result<(StatusCode, Json<CreatedHook>), WebhooksError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(WebhooksError::Invalid);
+    }
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let (bot_id, bot_username) = match req.bot_id {
+        Some(bot_id) => {
+            if !bots::owns(&state.db, user.user_id, bot_id).await? {
+                return Err(WebhooksError::NotYourBot);
+            }
+            let username = users::username(&state.db, bot_id)
+                .await?
+                .ok_or(WebhooksError::NotYourBot)?;
+            (bot_id, username)
+        }
+        None => {
+            let bot = bots::create(&state, user.user_id, name.clone()).await?;
+            (bot.user_id, bot.username)
+        }
+    };
+
+    if moderation::is_banned(&state.db, room_id, bot_id).await? {
+        return Err(ModerationError::Banned.into());
+    }
+
+    let token = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    let hook_id = Uuid::new_v4();
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "incoming_webhooks"(hook_id, room_id, bot_id, created_by, name, token_hash,
+                                            created_at, made_bot)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        hook_id,
+        room_id,
+        bot_id,
+        user.user_id,
+        name,
+        hash_token(&token),
+        now,
+        req.bot_id.is_none()
+    )
+    .execute(&mut *tx)
+    .await?;
+    let joined = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "room_members"(room_id, user_id, joined_at)
+            values ($1, $2, $3)
+            on conflict do nothing
+        "#,
+        room_id,
+        bot_id,
+        now
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    tx.commit().await?;
+
+    if joined {
+        state.publish(
+            room_id,
+            ServerEvent::MemberJoined {
+                room_id,
+                user_id: bot_id,
+                username: bot_username.clone(),
+            },
+        );
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedHook {
+            path: format!("/hooks/{}?token={}", hook_id, token),
+            token,
+            info: Webhook {
+                hook_id,
+                room_id,
+                name,
+                bot_id,
+                bot_username,
+                created_at: now,
+                last_used_at: None,
+            },
+        }),
+    ))
+}
+
+/// Delete a webhook. A bot made for it goes too, unless another hook has
+/// since been set up to post as it.
+#[axum_macros::debug_handler]
+async fn delete_hook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let mut tx = state.db.begin().await?;
+    let hook = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            delete from "incoming_webhooks" where hook_id = $1 and room_id = $2
+            returning bot_id, made_bot
+        "#,
+        hook_id,
+        room_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(WebhooksError::NotFound)?;
+    if hook.made_bot {
+        // Its memberships and tokens go with it; its messages stay.
+        sqlx::query!(
+            // language=PostgreSQL
+            r#"
+                delete from "users"
+                where user_id = $1 and is_bot
+                  and not exists(select 1 from "incoming_webhooks" where bot_id = $1)
+            "#,
+            hook.bot_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
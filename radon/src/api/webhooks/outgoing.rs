@@ -0,0 +1,1108 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::NaiveDateTime;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio::sync::{mpsc, Notify};
+use uuid::Uuid;
+
+use crate::{config::OutgoingWebhooks, message::ServerEvent, middleware::AuthUser};
+
+use super::{
+    super::{
+        mentions,
+        roles::{self, Permissions},
+        AppState,
+    },
+    error::WebhooksError,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+/// Deliveries sent at once by the worker.
+const BATCH_SIZE: i64 = 20;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/rooms/:room_id/outgoing_webhooks",
+            get(fetch_hooks).post(create_hook),
+        )
+        .route(
+            "/rooms/:room_id/outgoing_webhooks/:hook_id",
+            delete(delete_hook),
+        )
+        .route(
+            "/rooms/:room_id/outgoing_webhooks/:hook_id/ping",
+            post(ping_hook),
+        )
+        .route(
+            "/rooms/:room_id/outgoing_webhooks/:hook_id/deliveries",
+            get(fetch_deliveries),
+        )
+        .route(
+            "/rooms/:room_id/outgoing_webhooks/:hook_id/deliveries/:delivery_id",
+            get(fetch_delivery),
+        )
+        .route(
+            "/rooms/:room_id/outgoing_webhooks/:hook_id/deliveries/:delivery_id/retry",
+            post(retry_delivery),
+        )
+}
+
+/// What an outgoing webhook can be told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookEvent {
+    #[serde(rename = "message.posted")]
+    MessagePosted,
+    #[serde(rename = "member.joined")]
+    MemberJoined,
+    /// A message mentioned a bot that's in the room.
+    #[serde(rename = "bot.mentioned")]
+    BotMentioned,
+    #[serde(rename = "reaction.added")]
+    ReactionAdded,
+    /// Sent on request, to check an endpoint works. Always delivered.
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+impl HookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::MessagePosted => "message.posted",
+            HookEvent::MemberJoined => "member.joined",
+            HookEvent::BotMentioned => "bot.mentioned",
+            HookEvent::ReactionAdded => "reaction.added",
+            HookEvent::Ping => "ping",
+        }
+    }
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = WebhooksError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message.posted" => Ok(HookEvent::MessagePosted),
+            "member.joined" => Ok(HookEvent::MemberJoined),
+            "bot.mentioned" => Ok(HookEvent::BotMentioned),
+            "reaction.added" => Ok(HookEvent::ReactionAdded),
+            _ => Err(WebhooksError::UnknownEvent(s.to_string())),
+        }
+    }
+}
+
+/// Room events on their way to becoming deliveries, and a nudge for the
+/// worker when there's something new to send.
+#[derive(Debug)]
+pub struct OutgoingQueue {
+    events: mpsc::UnboundedSender<(Uuid, ServerEvent)>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<(Uuid, ServerEvent)>>>,
+    wake: Notify,
+}
+
+impl OutgoingQueue {
+    pub fn new() -> Self {
+        let (events, receiver) = mpsc::unbounded_channel();
+        Self {
+            events,
+            receiver: Mutex::new(Some(receiver)),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Queue a room event, if it's one hooks can subscribe to.
+    pub fn push(&self, room_id: Uuid, event: &ServerEvent) {
+        if matches!(
+            event,
+            ServerEvent::Message(_)
+                | ServerEvent::MemberJoined { .. }
+                | ServerEvent::ReactionAdded { .. }
+        ) {
+            let _ = self.events.send((room_id, event.clone()));
+        }
+    }
+}
+
+impl Default for OutgoingQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutgoingHook {
+    pub hook_id: Uuid,
+    pub room_id: Uuid,
+    pub name: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: NaiveDateTime,
+    /// Deliveries still being tried.
+    pub pending: i64,
+    /// Deliveries given up on.
+    pub dead: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateHookRequest {
+    name: String,
+    url: String,
+    events: Vec<String>,
+}
+
+/// A freshly made hook. This is the only time its secret is shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedHook {
+    /// Key for the HMAC-SHA256 in `X-Radon-Signature`.
+    pub secret: String,
+    #[serde(flatten)]
+    pub info: OutgoingHook,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub delivery_id: i64,
+    pub event: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attempt {
+    pub attempted_at: NaiveDateTime,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// A delivery with what was sent and every attempt to send it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryLog {
+    #[serde(flatten)]
+    pub delivery: Delivery,
+    pub payload: String,
+    pub attempts_log: Vec<Attempt>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveriesQuery {
+    /// Only deliveries in this state: pending, delivered or dead.
+    status: Option<String>,
+    /// Only deliveries older than this one.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Queued {
+    pub delivery_id: i64,
+}
+
+/// `hex(HMAC-SHA256(secret, "{timestamp}.{body}"))`. The timestamp is in the
+/// signature so a captured delivery can't be replayed later.
+pub fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// How long to wait before trying a delivery again after `attempts` failures.
+fn backoff(config: &OutgoingWebhooks, attempts: i32) -> chrono::Duration {
+    let doublings = (attempts - 1).max(0) as u32;
+    let secs = config
+        .backoff_base_secs
+        .saturating_mul(2u64.saturating_pow(doublings))
+        .min(config.backoff_max_secs);
+    chrono::Duration::seconds(secs as i64)
+}
+
+/// Whether `ip` is somewhere only radon's own network should reach.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                // "This network", 0.0.0.0/8, and shared address space,
+                // 100.64.0.0/10.
+                || ip.octets()[0] == 0
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local, fc00::/7, and link local, fe80::/10.
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|ip| is_private(ip.into()))
+        }
+    }
+}
+
+/// Refuse endpoints whose host resolves to a private, loopback or link local
+/// address, unless `outgoing_webhooks.allow_private` is set. Returns the
+/// addresses checked, or none when the check is off.
+async fn check_endpoint(
+    state: &AppState,
+    url: &reqwest::Url,
+) -> Result<Vec<SocketAddr>, WebhooksError> {
+    if state.config.outgoing_webhooks.allow_private {
+        return Ok(Vec::new());
+    }
+    let host = url.host_str().ok_or(WebhooksError::InvalidUrl)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url
+        .port_or_known_default()
+        .ok_or(WebhooksError::InvalidUrl)?;
+    let addrs: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| WebhooksError::UnresolvableUrl)?
+        .collect();
+    if addrs.iter().any(|addr| is_private(addr.ip())) {
+        return Err(WebhooksError::PrivateUrl);
+    }
+    Ok(addrs)
+}
+
+/// Check `url` again and build a client that only connects to the addresses
+/// checked, so its host can't point somewhere private between the check and
+/// the request. `None` when there's nothing to pin and the shared client will
+/// do.
+async fn pinned_client(
+    state: &AppState,
+    url: &reqwest::Url,
+) -> Result<Option<reqwest::Client>, WebhooksError> {
+    let addrs = check_endpoint(state, url).await?;
+    let Some(host) = url.domain() else {
+        return Ok(None);
+    };
+    if addrs.is_empty() {
+        return Ok(None);
+    }
+    let client = client_builder(&state.config.outgoing_webhooks)
+        .resolve_to_addrs(host, &addrs)
+        .build()
+        .expect("failed to build webhook client");
+    Ok(Some(client))
+}
+
+fn client_builder(config: &OutgoingWebhooks) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("radon/", env!("CARGO_PKG_VERSION")))
+}
+
+fn payload(event: HookEvent, room_id: Uuid, data: serde_json::Value) -> String {
+    json!({
+        "event": event.as_str(),
+        "room_id": room_id,
+        "created_at": chrono::Utc::now().naive_utc(),
+        "data": data,
+    })
+    .to_string()
+}
+
+/// Store a delivery of `payload` for every hook in the room that wants
+/// `event`.
+async fn enqueue(
+    db: &PgPool,
+    room_id: Uuid,
+    event: HookEvent,
+    payload: String,
+) -> Result<u64, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let queued = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "webhook_deliveries"(hook_id, event, payload, next_attempt_at, created_at)
+            select hook_id, $2, $3, $4, $4
+            from "outgoing_webhooks"
+            where room_id = $1 and $2 = any(events)
+        "#,
+        room_id,
+        event.as_str(),
+        payload,
+        now
+    )
+    .execute(db)
+    .await?;
+    Ok(queued.rows_affected())
+}
+
+/// Turn a room event into deliveries.
+async fn dispatch(state: &AppState, room_id: Uuid, event: ServerEvent) -> anyhow::Result<()> {
+    let data = serde_json::to_value(&event)?;
+    let mut queued = 0;
+
+    match &event {
+        ServerEvent::Message(message) => {
+            let body = payload(HookEvent::MessagePosted, room_id, data);
+            queued += enqueue(&state.db, room_id, HookEvent::MessagePosted, body).await?;
+
+            let usernames = mentions::parse(&message.text).usernames;
+            if !usernames.is_empty() {
+                let bots = sqlx::query!(
+                    // language=PostgreSQL
+                    r#"
+                        select u.user_id, u.username
+                        from "users" u
+                        join "room_members" m on m.user_id = u.user_id and m.room_id = $1
+                        where u.is_bot and lower(u.username) = any($2)
+                          and u.user_id is distinct from $3
+                    "#,
+                    room_id,
+                    &usernames,
+                    message.user_id
+                )
+                .fetch_all(&state.db)
+                .await?;
+                for bot in bots {
+                    let data = json!({
+                        "bot_id": bot.user_id,
+                        "bot_username": bot.username,
+                        "message": message,
+                    });
+                    let body = payload(HookEvent::BotMentioned, room_id, data);
+                    queued += enqueue(&state.db, room_id, HookEvent::BotMentioned, body).await?;
+                }
+            }
+        }
+        ServerEvent::MemberJoined { .. } => {
+            let body = payload(HookEvent::MemberJoined, room_id, data);
+            queued += enqueue(&state.db, room_id, HookEvent::MemberJoined, body).await?;
+        }
+        ServerEvent::ReactionAdded { .. } => {
+            let body = payload(HookEvent::ReactionAdded, room_id, data);
+            queued += enqueue(&state.db, room_id, HookEvent::ReactionAdded, body).await?;
+        }
+        _ => {}
+    }
+
+    if queued > 0 {
+        state.outgoing.wake.notify_one();
+    }
+    Ok(())
+}
+
+/// A delivery the worker is about to try.
+struct Due {
+    delivery_id: i64,
+    event: String,
+    payload: String,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+/// POST a signed delivery to `url`. Returns the status code the endpoint
+/// responded with, and what went wrong unless it was a 2xx.
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    event: &str,
+    delivery_id: i64,
+    secret: &str,
+    payload: String,
+) -> (Option<i32>, Option<String>) {
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature = sign(secret, timestamp, &payload);
+    let res = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header("X-Radon-Event", event)
+        .header("X-Radon-Delivery", delivery_id.to_string())
+        .header("X-Radon-Timestamp", timestamp.to_string())
+        .header("X-Radon-Signature", format!("sha256={}", signature))
+        .body(payload)
+        .send()
+        .await;
+
+    match res {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i32), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            Some(format!("Endpoint responded {}", response.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Where a delivery stands after its `attempts`th try.
+fn status_after(config: &OutgoingWebhooks, attempts: i32, failed: bool) -> &'static str {
+    if !failed {
+        "delivered"
+    } else if attempts >= config.max_attempts {
+        "dead"
+    } else {
+        "pending"
+    }
+}
+
+/// Try one delivery, log the attempt and schedule the next one if it failed.
+async fn deliver(state: &AppState, client: &reqwest::Client, due: Due) -> Result<(), sqlx::Error> {
+    let Due {
+        delivery_id,
+        event,
+        payload,
+        attempts,
+        url,
+        secret,
+    } = due;
+
+    let start = Instant::now();
+    // Where a host points can change after its hook was made, so look again.
+    let pinned = match reqwest::Url::parse(&url) {
+        Ok(parsed) => pinned_client(state, &parsed).await,
+        Err(_) => Err(WebhooksError::InvalidUrl),
+    };
+    let (status_code, error) = match pinned {
+        Ok(pinned) => {
+            let client = pinned.as_ref().unwrap_or(client);
+            send(client, &url, &event, delivery_id, &secret, payload).await
+        }
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let duration_ms = start.elapsed().as_millis().min(i32::MAX as u128) as i32;
+
+    let now = chrono::Utc::now().naive_utc();
+    let attempts = attempts + 1;
+    let config = &state.config.outgoing_webhooks;
+    let status = status_after(config, attempts, error.is_some());
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "webhook_attempts"(delivery_id, attempted_at, status_code, error,
+                                           duration_ms)
+            values ($1, $2, $3, $4, $5)
+        "#,
+        delivery_id,
+        now,
+        status_code,
+        error,
+        duration_ms
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "webhook_deliveries"
+            set status = $1, attempts = $2, next_attempt_at = $3,
+                delivered_at = case when $1 = 'delivered' then $4::timestamp end
+            where delivery_id = $5
+        "#,
+        status,
+        attempts,
+        now + backoff(config, attempts),
+        now,
+        delivery_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    if status == "dead" {
+        tracing::warn!(
+            "webhook delivery {delivery_id} to {url} failed {attempts} times, giving up"
+        );
+    }
+    Ok(())
+}
+
+/// Send everything that's due, a batch at a time. Returns how many were
+/// tried.
+async fn deliver_due(state: &AppState, client: &reqwest::Client) -> Result<usize, sqlx::Error> {
+    let due = sqlx::query_as!(
+        Due,
+        // language=PostgreSQL
+        r#"
+            select d.delivery_id, d.event, d.payload, d.attempts, h.url, h.secret
+            from "webhook_deliveries" d
+            join "outgoing_webhooks" h on h.hook_id = d.hook_id
+            where d.status = 'pending' and d.next_attempt_at <= $1
+            order by d.next_attempt_at
+            limit $2
+        "#,
+        chrono::Utc::now().naive_utc(),
+        BATCH_SIZE
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let tried = due.len();
+    let results =
+        futures::future::join_all(due.into_iter().map(|due| deliver(state, client, due))).await;
+    for res in results {
+        res?;
+    }
+    Ok(tried)
+}
+
+/// Start turning room events into deliveries and sending them. Deliveries
+/// live in the database, so ones that were pending when radon stopped are
+/// sent once it's back. An endpoint may see a delivery twice if radon stops
+/// mid-request; `X-Radon-Delivery` tells repeats apart.
+pub fn spawn_delivery(state: Arc<AppState>) {
+    let Some(mut receiver) = state.outgoing.receiver.lock().unwrap().take() else {
+        return;
+    };
+
+    let dispatch_state = state.clone();
+    tokio::spawn(async move {
+        while let Some((room_id, event)) = receiver.recv().await {
+            if let Err(e) = dispatch(&dispatch_state, room_id, event).await {
+                tracing::error!("failed to queue webhook deliveries: {e}");
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let client = client_builder(&state.config.outgoing_webhooks)
+            .build()
+            .expect("failed to build webhook client");
+
+        loop {
+            match deliver_due(&state, &client).await {
+                // A full batch means there may be more waiting.
+                Ok(tried) if tried as i64 == BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("failed to deliver webhooks: {e}"),
+            }
+            tokio::select! {
+                _ = state.outgoing.wake.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            }
+        }
+    });
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_hooks(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Vec<OutgoingHook>>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let hooks = sqlx::query_as!(
+        OutgoingHook,
+        // language=PostgreSQL
+        r#"
+            select h.hook_id, h.room_id, h.name, h.url, h.events, h.created_at,
+                   (select count(*) from "webhook_deliveries" d
+                    where d.hook_id = h.hook_id and d.status = 'pending') as "pending!",
+                   (select count(*) from "webhook_deliveries" d
+                    where d.hook_id = h.hook_id and d.status = 'dead') as "dead!"
+            from "outgoing_webhooks" h
+            where h.room_id = $1
+            order by h.created_at
+        "#,
+        room_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(hooks)))
+}
+
+#[axum_macros::debug_handler]
+async fn create_hook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path(room_id): Path<Uuid>,
+    Json(req): Json<CreateHookRequest>,
+) -> Result<(StatusCode, Json<CreatedHook>), WebhooksError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(WebhooksError::Invalid);
+    }
+    let url = reqwest::Url::parse(&req.url).map_err(|_| WebhooksError::InvalidUrl)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(WebhooksError::InvalidUrl);
+    }
+    check_endpoint(&state, &url).await?;
+    let mut events = Vec::new();
+    for event in &req.events {
+        let event = event.parse::<HookEvent>()?.as_str().to_string();
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+    if events.is_empty() {
+        return Err(WebhooksError::Invalid);
+    }
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let secret = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    let hook_id = Uuid::new_v4();
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            insert into "outgoing_webhooks"(hook_id, room_id, created_by, name, url, secret, events,
+                                            created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        hook_id,
+        room_id,
+        user.user_id,
+        name,
+        url.as_str(),
+        secret,
+        &events,
+        now
+    )
+    .execute(&state.db)
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedHook {
+            secret,
+            info: OutgoingHook {
+                hook_id,
+                room_id,
+                name,
+                url: url.to_string(),
+                events,
+                created_at: now,
+                pending: 0,
+                dead: 0,
+            },
+        }),
+    ))
+}
+
+#[axum_macros::debug_handler]
+async fn delete_hook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let deleted = sqlx::query!(
+        r#"delete from "outgoing_webhooks" where hook_id = $1 and room_id = $2"#,
+        hook_id,
+        room_id
+    )
+    .execute(&state.db)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(WebhooksError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a `ping` event to a hook, whatever it subscribes to.
+#[axum_macros::debug_handler]
+async fn ping_hook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Json<Queued>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let body = payload(HookEvent::Ping, room_id, json!({ "hook_id": hook_id }));
+    let delivery_id = sqlx::query_scalar!(
+        // language=PostgreSQL
+        r#"
+            insert into "webhook_deliveries"(hook_id, event, payload, next_attempt_at, created_at)
+            select hook_id, $3, $4, $5, $5
+            from "outgoing_webhooks"
+            where hook_id = $1 and room_id = $2
+            returning delivery_id
+        "#,
+        hook_id,
+        room_id,
+        HookEvent::Ping.as_str(),
+        body,
+        now
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(WebhooksError::NotFound)?;
+    state.outgoing.wake.notify_one();
+
+    Ok((StatusCode::ACCEPTED, Json(Queued { delivery_id })))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_deliveries(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<(StatusCode, Json<Vec<Delivery>>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        // language=PostgreSQL
+        r#"
+            select d.delivery_id, d.event, d.status, d.attempts, d.next_attempt_at, d.created_at,
+                   d.delivered_at, a.status_code as "last_status_code?", a.error as "last_error?"
+            from "webhook_deliveries" d
+            join "outgoing_webhooks" h on h.hook_id = d.hook_id
+            left join lateral (
+                select status_code, error from "webhook_attempts"
+                where delivery_id = d.delivery_id
+                order by attempt_id desc
+                limit 1
+            ) a on true
+            where d.hook_id = $1 and h.room_id = $2
+              and ($3::text is null or d.status = $3)
+              and d.delivery_id < $4
+            order by d.delivery_id desc
+            limit $5
+        "#,
+        hook_id,
+        room_id,
+        query.status,
+        query.before.unwrap_or(i64::MAX),
+        limit
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok((StatusCode::OK, Json(deliveries)))
+}
+
+#[axum_macros::debug_handler]
+async fn fetch_delivery(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id, delivery_id)): Path<(Uuid, Uuid, i64)>,
+) -> Result<(StatusCode, Json<DeliveryLog>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let record = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            select d.delivery_id, d.event, d.status, d.attempts, d.next_attempt_at, d.created_at,
+                   d.delivered_at, d.payload
+            from "webhook_deliveries" d
+            join "outgoing_webhooks" h on h.hook_id = d.hook_id
+            where d.delivery_id = $1 and d.hook_id = $2 and h.room_id = $3
+        "#,
+        delivery_id,
+        hook_id,
+        room_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(WebhooksError::DeliveryNotFound)?;
+
+    let attempts = sqlx::query_as!(
+        Attempt,
+        // language=PostgreSQL
+        r#"
+            select attempted_at, status_code, error, duration_ms
+            from "webhook_attempts"
+            where delivery_id = $1
+            order by attempt_id
+        "#,
+        delivery_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let last = attempts.last();
+    let delivery = Delivery {
+        delivery_id: record.delivery_id,
+        event: record.event,
+        status: record.status,
+        attempts: record.attempts,
+        next_attempt_at: record.next_attempt_at,
+        created_at: record.created_at,
+        delivered_at: record.delivered_at,
+        last_status_code: last.and_then(|attempt| attempt.status_code),
+        last_error: last.and_then(|attempt| attempt.error.clone()),
+    };
+    Ok((
+        StatusCode::OK,
+        Json(DeliveryLog {
+            delivery,
+            payload: record.payload,
+            attempts_log: attempts,
+        }),
+    ))
+}
+
+/// Try a dead delivery again, with a fresh set of attempts.
+#[axum_macros::debug_handler]
+async fn retry_delivery(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<AuthUser>,
+    Path((room_id, hook_id, delivery_id)): Path<(Uuid, Uuid, i64)>,
+) -> Result<(StatusCode, Json<Queued>), WebhooksError> {
+    roles::authorize(
+        &state.db,
+        room_id,
+        user.user_id,
+        Permissions::MANAGE_WEBHOOKS,
+    )
+    .await?;
+
+    let requeued = sqlx::query!(
+        // language=PostgreSQL
+        r#"
+            update "webhook_deliveries" d
+            set status = 'pending', attempts = 0, next_attempt_at = $1
+            from "outgoing_webhooks" h
+            where h.hook_id = d.hook_id
+              and d.delivery_id = $2 and d.hook_id = $3 and h.room_id = $4
+              and d.status = 'dead'
+        "#,
+        chrono::Utc::now().naive_utc(),
+        delivery_id,
+        hook_id,
+        room_id
+    )
+    .execute(&state.db)
+    .await?;
+    if requeued.rows_affected() == 0 {
+        return Err(WebhooksError::DeliveryNotFound);
+    }
+    state.outgoing.wake.notify_one();
+
+    Ok((StatusCode::ACCEPTED, Json(Queued { delivery_id })))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use axum::{http::HeaderMap, routing::post};
+
+    use super::*;
+
+    /// An endpoint that answers every delivery with `status`, passing what it
+    /// got on to the returned receiver.
+    fn receiver(status: StatusCode) -> (String, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| async move {
+                let _ = tx.send((headers, body));
+                status
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        (format!("http://{addr}/hook"), rx)
+    }
+
+    fn config() -> OutgoingWebhooks {
+        OutgoingWebhooks {
+            max_attempts: 4,
+            backoff_base_secs: 10,
+            backoff_max_secs: 25,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed() {
+        let (url, mut received) = receiver(StatusCode::NO_CONTENT);
+        let body = payload(HookEvent::Ping, Uuid::new_v4(), json!({ "hello": "world" }));
+
+        let client = reqwest::Client::new();
+        let res = send(&client, &url, "ping", 7, "shh", body.clone()).await;
+        assert_eq!(res, (Some(204), None));
+
+        let (headers, got) = received.recv().await.unwrap();
+        assert_eq!(got, body);
+        assert_eq!(headers["x-radon-event"], "ping");
+        assert_eq!(headers["x-radon-delivery"], "7");
+        let timestamp: i64 = headers["x-radon-timestamp"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected = format!("sha256={}", sign("shh", timestamp, &got));
+        assert_eq!(headers["x-radon-signature"], expected.as_str());
+        assert_ne!(
+            headers["x-radon-signature"],
+            format!("sha256={}", sign("other", timestamp, &got)).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_deliveries_back_off_until_dead() {
+        let (url, mut received) = receiver(StatusCode::INTERNAL_SERVER_ERROR);
+        let config = config();
+        let client = reqwest::Client::new();
+
+        let mut attempts = 0;
+        let mut waits = Vec::new();
+        let status = loop {
+            let (status_code, error) = send(&client, &url, "ping", 1, "shh", "{}".into()).await;
+            assert_eq!(status_code, Some(500));
+            assert_eq!(
+                error.as_deref(),
+                Some("Endpoint responded 500 Internal Server Error")
+            );
+            attempts += 1;
+            match status_after(&config, attempts, error.is_some()) {
+                "pending" => waits.push(backoff(&config, attempts).num_seconds()),
+                status => break status,
+            }
+        };
+
+        assert_eq!(status, "dead");
+        assert_eq!(attempts, config.max_attempts);
+        assert_eq!(waits, [10, 20, 25]);
+        for _ in 0..attempts {
+            received.recv().await.unwrap();
+        }
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn delivered_wins_over_running_out() {
+        let config = config();
+        assert_eq!(status_after(&config, 1, false), "delivered");
+        assert_eq!(
+            status_after(&config, config.max_attempts, false),
+            "delivered"
+        );
+        assert_eq!(
+            status_after(&config, config.max_attempts - 1, true),
+            "pending"
+        );
+        assert_eq!(status_after(&config, config.max_attempts, true), "dead");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = OutgoingWebhooks {
+            backoff_base_secs: 10,
+            backoff_max_secs: 60 * 60,
+            ..Default::default()
+        };
+        let waits: Vec<_> = (0..=5)
+            .map(|attempts| backoff(&config, attempts).num_seconds())
+            .collect();
+        assert_eq!(waits, [10, 10, 20, 40, 80, 160]);
+        assert_eq!(backoff(&config, 10).num_seconds(), 3600);
+        // Far past where doubling would overflow.
+        assert_eq!(backoff(&config, i32::MAX).num_seconds(), 3600);
+    }
+
+    #[test]
+    fn private_addresses() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(is_private(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "1.1.1.1",
+            "100.128.0.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!is_private(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
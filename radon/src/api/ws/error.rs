@@ -1 +1,27 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
 use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WsError {
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for WsError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            WsError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            WsError::Database(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -1,89 +1,279 @@
 pub mod error;
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
 
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        ConnectInfo, Query, State, WebSocketUpgrade,
     },
+    http::StatusCode,
     response::IntoResponse,
     routing::get,
-    Router,
+    Json, Router,
 };
+use chrono::NaiveDateTime;
+use error::WsError;
 use futures::{SinkExt, StreamExt};
+use serde_derive::Deserialize;
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
 use crate::{
-    config::ServerConfig,
-    message::{MessageType, TextMessage},
+    client::{Client, ClientState, Delivery, PresenceInfo},
+    commands::{error::CommandError, CommandContext, CommandInfo, CommandOutcome},
+    message::{ClientFrame, ServerEvent},
+    middleware::{self, AuthUser},
+    ratelimit::Scope,
 };
 
-use super::AppState;
+use super::{
+    bookmarks,
+    messages::{self, error::MessagesError, reactions, NewMessage},
+    pins, rooms,
+    tokens::{TokenGrant, TokenScope},
+    users, AppState,
+};
 
 pub fn router(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/ws", get(websocket_handler))
+        .route("/commands", get(list_commands))
         .with_state(state)
 }
+
+#[derive(Debug, Deserialize)]
+pub struct WsParams {
+    /// Access token. Browsers can't set headers on websocket requests, so it
+    /// travels in the query string.
+    token: String,
+}
+
 async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| websocket(state, socket))
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<WsParams>,
+) -> Result<impl IntoResponse, WsError> {
+    let user = middleware::authenticate(&state, &params.token)
+        .await
+        .map_err(|_| WsError::Unauthorized)?;
+
+    let username = users::username(&state.db, user.user_id)
+        .await?
+        .ok_or(WsError::Unauthorized)?;
+
+    let rooms = subscriptions(&state, &user).await?.into_keys().collect();
+
+    Ok(ws.on_upgrade(move |socket| {
+        websocket(
+            state,
+            socket,
+            addr,
+            user.user_id,
+            username,
+            user.token,
+            rooms,
+        )
+    }))
 }
 
-async fn websocket(state: Arc<AppState>, stream: WebSocket) {
-    // By splitting, we can send and receive at the same time.
-    let (mut sender, mut receiver) = stream.split();
+/// The rooms a connection gets events for, with when the user joined each.
+/// API tokens that can't read rooms only get what's addressed to their user.
+pub async fn subscriptions(
+    state: &AppState,
+    user: &AuthUser,
+) -> Result<HashMap<Uuid, NaiveDateTime>, sqlx::Error> {
+    if let Some(grant) = &user.token {
+        if !grant.has(TokenScope::ReadRooms) {
+            return Ok(HashMap::new());
+        }
+    }
+    let rooms = sqlx::query!(
+        r#"select room_id, joined_at from "room_members" where user_id = $1"#,
+        user.user_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(rooms
+        .into_iter()
+        .map(|room| (room.room_id, room.joined_at))
+        .collect())
+}
 
-    // Username gets set in the receive loop, if it's valid.
-    let mut username = String::new();
-    // Loop until a text message is found.
-    while let Some(Ok(message)) = receiver.next().await {
-        if let Message::Text(name) = message {
-            // If not empty we want to quit the loop else we want to quit function.
-            if !username.is_empty() {
-                break;
-            } else {
-                // Only send our client that username is taken.
-                let _ = sender
-                    .send(Message::Text(String::from("Username already taken.")))
-                    .await;
+/// Drop a connection from the registry, recording when its user was last
+/// seen if it was their last one.
+pub async fn disconnect(state: &AppState, user_id: Uuid, client_id: Uuid) {
+    if let Some(info) = state.clients.disconnect(user_id, client_id) {
+        record_last_seen(state, user_id, info).await;
+    }
+}
 
-                return;
+/// Close every `/ws` and `/events` connection a user has, for when their
+/// sessions are revoked.
+pub async fn disconnect_user(state: &AppState, user_id: Uuid) {
+    if let Some(info) = state.clients.disconnect_user(user_id) {
+        record_last_seen(state, user_id, info).await;
+    }
+}
+
+/// Close the connections a user opened with one API token, for when it's
+/// revoked.
+pub async fn disconnect_token(state: &AppState, user_id: Uuid, token_id: Uuid) {
+    if let Some(info) = state.clients.disconnect_token(user_id, token_id) {
+        record_last_seen(state, user_id, info).await;
+    }
+}
+
+async fn record_last_seen(state: &AppState, user_id: Uuid, info: PresenceInfo) {
+    let res = sqlx::query!(
+        r#"update "users" set last_seen_at = $1 where user_id = $2"#,
+        info.last_seen,
+        user_id
+    )
+    .execute(&state.db)
+    .await;
+    if let Err(e) = res {
+        tracing::error!("failed to record last seen for {user_id}: {e}");
+    }
+}
+
+async fn list_commands(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Vec<CommandInfo>>) {
+    (StatusCode::OK, Json(state.commands.list()))
+}
+
+/// Clear typing indicators whose clients never sent a stop.
+pub fn spawn_typing_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            for expired in state.typing.expire() {
+                state.publish(
+                    expired.room_id,
+                    ServerEvent::Typing {
+                        room_id: expired.room_id,
+                        user_id: expired.user_id,
+                        username: expired.username,
+                        typing: false,
+                    },
+                );
             }
         }
-    }
+    });
+}
+
+/// One authenticated socket.
+struct Session {
+    state: Arc<AppState>,
+    client_id: Uuid,
+    user_id: Uuid,
+    username: String,
+    /// Set when the socket was opened with an API token.
+    token: Option<Arc<TokenGrant>>,
+}
+
+async fn websocket(
+    state: Arc<AppState>,
+    stream: WebSocket,
+    addr: SocketAddr,
+    user_id: Uuid,
+    username: String,
+    token: Option<Arc<TokenGrant>>,
+    rooms: HashSet<Uuid>,
+) {
+    // By splitting, we can send and receive at the same time.
+    let (mut sender, mut receiver) = stream.split();
 
-    // We subscribe *before* sending the "joined" message, so that we will also
-    // display it to our client.
-    let mut rx = state.tx.subscribe();
+    // Everything addressed to this socket, from the registry or from us.
+    let (tx, mut rx) = mpsc::unbounded_channel::<Delivery>();
 
-    // Now send the "joined" message to all subscribers.
-    let msg = format!("{username} joined.");
-    tracing::debug!("{msg}");
-    let _ = state.tx.send(msg);
+    let token_id = token.as_ref().map(|grant| grant.token_id);
+    let client = Client::new(user_id, username.clone(), addr, token_id, rooms, tx);
+    let session = Session {
+        state: state.clone(),
+        client_id: client.id,
+        user_id,
+        username,
+        token,
+    };
+    state.clients.connect(client);
 
-    // Spawn the first task that will receive broadcast messages and send text
-    // messages over the websocket to our client.
+    // Spawn the first task that will forward queued events over the websocket.
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
+        while let Some(delivery) = rx.recv().await {
+            let text = match serde_json::to_string(&delivery.event) {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::error!("failed to serialize event: {e}");
+                    continue;
+                }
+            };
+
             // In any websocket error, break loop.
-            if sender.send(Message::Text(msg)).await.is_err() {
+            if sender.send(Message::Text(text)).await.is_err() {
                 break;
             }
         }
     });
 
-    // Clone things we want to pass (move) to the receiving task.
-    let tx = state.tx.clone();
-    let name = username.clone();
-
-    // Spawn a task that takes messages from the websocket, prepends the user
-    // name, and sends them to all broadcast subscribers.
+    // Spawn a task that takes frames from the websocket and handles them.
+    let recv_state = state.clone();
+    let client_id = session.client_id;
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(text))) = receiver.next().await {
-            // Add username before message.
-            let _ = tx.send(format!("{name}: {text}"));
+        while let Some(Ok(message)) = receiver.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            recv_state.clients.touch(user_id);
+
+            let limits = &recv_state.config.rate_limits;
+            let allowed = recv_state
+                .limiter
+                .check(
+                    Scope::WsConnection,
+                    &client_id.to_string(),
+                    limits.ws_connection,
+                )
+                .map_err(|throttled| (Scope::WsConnection, throttled))
+                .and_then(|_| {
+                    recv_state
+                        .limiter
+                        .check(Scope::WsUser, &user_id.to_string(), limits.ws_user)
+                        .map_err(|throttled| (Scope::WsUser, throttled))
+                });
+            if let Err((scope, throttled)) = allowed {
+                recv_state.clients.send_to_client(
+                    user_id,
+                    client_id,
+                    ServerEvent::Throttled {
+                        scope: scope.to_string(),
+                        retry_after_ms: throttled.retry_after.as_millis() as u64,
+                    },
+                );
+                continue;
+            }
+
+            let reply = match serde_json::from_str::<ClientFrame>(&text) {
+                Ok(frame) => session.handle(frame).await,
+                Err(e) => Some(ServerEvent::Error {
+                    code: "bad_frame".to_string(),
+                    message: e.to_string(),
+                }),
+            };
+
+            if let Some(reply) = reply {
+                recv_state
+                    .clients
+                    .send_to_client(user_id, session.client_id, reply);
+            }
         }
     });
 
@@ -93,8 +283,242 @@ async fn websocket(state: Arc<AppState>, stream: WebSocket) {
         _ = (&mut recv_task) => send_task.abort(),
     };
 
-    // Send "user left" message (similar to "joined" above).
-    let msg = format!("{username} left.");
-    tracing::debug!("{msg}");
-    let _ = state.tx.send(msg);
+    disconnect(&state, user_id, client_id).await;
+}
+
+fn error_event(code: &str, message: impl Into<String>) -> ServerEvent {
+    ServerEvent::Error {
+        code: code.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Turn a failed message operation into an error event for the socket.
+fn messages_error(res: Result<(), MessagesError>) -> Option<ServerEvent> {
+    let e = res.err()?;
+    if let MessagesError::Database(db) = &e {
+        tracing::error!("message operation failed: {db}");
+    }
+    Some(error_event(e.code(), e.to_string()))
+}
+
+impl Session {
+    /// Whether the API token this socket was opened with, if any, allows a
+    /// frame. Tokens can post, read and type, but not run commands or act on
+    /// existing messages.
+    fn token_allows(&self, frame: &ClientFrame) -> bool {
+        let Some(grant) = &self.token else {
+            return true;
+        };
+        match frame {
+            ClientFrame::Send { room_id, text, .. } => {
+                grant.can_post(*room_id) && (!text.starts_with('/') || text.starts_with("//"))
+            }
+            ClientFrame::Typing { room_id, .. } => grant.can_post(*room_id),
+            ClientFrame::MarkRead { .. } => grant.has(TokenScope::ReadRooms),
+            ClientFrame::ListCommands | ClientFrame::SetPresence { .. } => true,
+            _ => false,
+        }
+    }
+
+    async fn handle(&self, frame: ClientFrame) -> Option<ServerEvent> {
+        if !self.token_allows(&frame) {
+            return Some(error_event(
+                "out_of_scope",
+                "This token's scopes don't allow that",
+            ));
+        }
+        match frame {
+            ClientFrame::Send {
+                room_id,
+                text,
+                parent_id,
+                attachments,
+            } => {
+                // `/me waves` typed into the input box is a command, `//me` is
+                // the literal text `/me`.
+                if let Some(line) = text.strip_prefix('/') {
+                    if !line.starts_with('/') && attachments.is_empty() {
+                        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+                        return Some(self.run_command(room_id, name, args).await);
+                    }
+                }
+                let text = text.strip_prefix('/').map(str::to_string).unwrap_or(text);
+                let message = NewMessage {
+                    text,
+                    parent_id,
+                    attachments,
+                };
+                self.send(room_id, message).await
+            }
+            ClientFrame::Command {
+                room_id,
+                name,
+                args,
+            } => Some(self.run_command(room_id, &name, &args).await),
+            ClientFrame::ListCommands => Some(ServerEvent::Commands {
+                commands: self.state.commands.list(),
+            }),
+            ClientFrame::SetPresence { away } => {
+                let state = if away {
+                    ClientState::Away
+                } else {
+                    ClientState::Active
+                };
+                self.state
+                    .clients
+                    .set_state(self.user_id, self.client_id, state);
+                None
+            }
+            ClientFrame::Typing { room_id, typing } => {
+                self.typing(room_id, typing);
+                None
+            }
+            ClientFrame::MarkRead { room_id, seq } => self.mark_read(room_id, seq).await,
+            ClientFrame::Edit { message_id, text } => {
+                let res = messages::edit_message(&self.state, self.user_id, message_id, text).await;
+                messages_error(res)
+            }
+            ClientFrame::Delete { message_id } => {
+                let res = messages::delete_message(&self.state, self.user_id, message_id).await;
+                messages_error(res)
+            }
+            ClientFrame::React { message_id, emoji } => {
+                let res = reactions::add_reaction(
+                    &self.state,
+                    self.user_id,
+                    self.username.clone(),
+                    message_id,
+                    &emoji,
+                )
+                .await;
+                messages_error(res)
+            }
+            ClientFrame::Unreact { message_id, emoji } => {
+                let res = reactions::remove_reaction(
+                    &self.state,
+                    self.user_id,
+                    self.username.clone(),
+                    message_id,
+                    &emoji,
+                )
+                .await;
+                messages_error(res)
+            }
+            ClientFrame::Pin { message_id } => {
+                let res =
+                    pins::pin_message(&self.state, self.user_id, self.username.clone(), message_id)
+                        .await;
+                res.err().map(|e| error_event(e.code(), e.to_string()))
+            }
+            ClientFrame::Unpin { message_id } => {
+                let res = pins::unpin_message(
+                    &self.state,
+                    self.user_id,
+                    self.username.clone(),
+                    message_id,
+                )
+                .await;
+                res.err().map(|e| error_event(e.code(), e.to_string()))
+            }
+            ClientFrame::Bookmark { message_id } => {
+                let res = bookmarks::add_bookmark(&self.state, self.user_id, message_id).await;
+                res.err().map(|e| error_event(e.code(), e.to_string()))
+            }
+            ClientFrame::Unbookmark { message_id } => {
+                let res = bookmarks::remove_bookmark(&self.state, self.user_id, message_id).await;
+                res.err().map(|e| error_event(e.code(), e.to_string()))
+            }
+        }
+    }
+
+    async fn mark_read(&self, room_id: Uuid, seq: i64) -> Option<ServerEvent> {
+        match rooms::mark_read(&self.state.db, room_id, self.user_id, seq).await {
+            Ok(Some(read)) => {
+                // Every device of ours, this one included, follows the marker.
+                self.state
+                    .clients
+                    .send_to_user(self.user_id, ServerEvent::ReadMarker { room_id, read });
+                None
+            }
+            Ok(None) => Some(error_event("forbidden", "Not a member of this room")),
+            Err(e) => {
+                tracing::error!("failed to mark read: {e}");
+                Some(error_event("internal", "Failed to mark read"))
+            }
+        }
+    }
+
+    fn typing(&self, room_id: Uuid, typing: bool) {
+        let clients = &self.state.clients;
+        if !clients.is_subscribed(self.user_id, self.client_id, room_id) {
+            return;
+        }
+
+        let changed = if typing {
+            self.state
+                .typing
+                .start(room_id, self.user_id, &self.username)
+        } else {
+            self.state.typing.stop(room_id, self.user_id)
+        };
+
+        if changed {
+            self.state.publish(
+                room_id,
+                ServerEvent::Typing {
+                    room_id,
+                    user_id: self.user_id,
+                    username: self.username.clone(),
+                    typing,
+                },
+            );
+        }
+    }
+
+    async fn send(&self, room_id: Uuid, message: NewMessage) -> Option<ServerEvent> {
+        if message.text.trim().is_empty() && message.attachments.is_empty() {
+            return None;
+        }
+        let res = messages::post_message(
+            &self.state,
+            self.user_id,
+            &self.username,
+            None,
+            room_id,
+            message,
+        )
+        .await;
+        messages_error(res.map(|_| ()))
+    }
+
+    async fn run_command(&self, room_id: Uuid, name: &str, args: &str) -> ServerEvent {
+        let ctx = CommandContext {
+            state: self.state.clone(),
+            user_id: self.user_id,
+            username: self.username.clone(),
+            room_id,
+        };
+
+        match self.state.commands.dispatch(ctx, name, args).await {
+            Ok(CommandOutcome::Reply(text)) => ServerEvent::CommandOk {
+                command: name.to_string(),
+                text: Some(text),
+            },
+            Ok(CommandOutcome::Done) => ServerEvent::CommandOk {
+                command: name.to_string(),
+                text: None,
+            },
+            Err(e) => {
+                if let CommandError::Database(db) = &e {
+                    tracing::error!("command /{name} failed: {db}");
+                }
+                ServerEvent::CommandError {
+                    command: name.to_string(),
+                    code: e.code().to_string(),
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
 }
@@ -1,29 +1,88 @@
 pub mod error;
-use tokio::sync::broadcast;
+pub mod registry;
+pub mod typing;
+
+use std::{collections::HashSet, net::SocketAddr};
+
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
-#[derive(Clone)]
+use crate::message::ServerEvent;
+
+/// What a single connection says its user is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ClientState {
-    Anonymous,
-    Registered { username: String }, // TODO: make sure this isn't too long so copying isn't
-                                     // expensive
+    Active,
+    Away,
 }
 
-#[derive(Clone)]
+/// A user's presence, derived from all of their connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    Online,
+    Away,
+    Offline,
+}
+
+/// One open websocket or event stream.
+#[derive(Debug, Clone)]
 pub struct Client {
     pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
     pub state: ClientState,
-    pub addr: std::net::SocketAddr,
-    pub tx: broadcast::Sender<(Vec<u8>, Uuid)>,
+    pub addr: SocketAddr,
+    /// The API token this connection was opened with, if any.
+    pub token_id: Option<Uuid>,
+    /// Rooms this connection receives events for.
+    pub rooms: HashSet<Uuid>,
+    pub tx: mpsc::UnboundedSender<Delivery>,
 }
 
 impl Client {
-    pub fn new(addr: std::net::SocketAddr, tx: broadcast::Sender<(Vec<u8>, Uuid)>) -> Self {
+    pub fn new(
+        user_id: Uuid,
+        username: String,
+        addr: SocketAddr,
+        token_id: Option<Uuid>,
+        rooms: HashSet<Uuid>,
+        tx: mpsc::UnboundedSender<Delivery>,
+    ) -> Self {
         Self {
             id: Uuid::new_v4(),
-            state: ClientState::Anonymous,
+            user_id,
+            username,
+            state: ClientState::Active,
             addr,
+            token_id,
+            rooms,
             tx,
         }
     }
+
+    /// Queue an event for this connection. Fails only if the socket is gone.
+    pub fn send(&self, delivery: Delivery) -> bool {
+        self.tx.send(delivery).is_ok()
+    }
+}
+
+/// An event on its way to a connection. Ids only ever go up, so clients that
+/// can't keep a socket open can say how far they got.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub id: u64,
+    pub event: ServerEvent,
+}
+
+/// A presence change as sent to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceInfo {
+    pub user_id: Uuid,
+    pub username: String,
+    pub presence: Presence,
+    pub last_seen: Option<NaiveDateTime>,
 }
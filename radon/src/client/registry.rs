@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Mutex, RwLock},
+};
+
+use chrono::NaiveDateTime;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::message::ServerEvent;
+
+use super::{Client, ClientState, Delivery, Presence, PresenceInfo};
+
+/// How many recent events are kept for clients resuming a stream.
+const JOURNAL_LEN: usize = 10_000;
+
+/// Every live connection, grouped by user.
+///
+/// This is the one place events get fanned out from: room events go to each
+/// connection subscribed to the room, user events go to each of a user's
+/// connections. Every event is numbered and the most recent are kept, so SSE
+/// and long-poll clients can pick up where they left off.
+#[derive(Debug)]
+pub struct ConnectionRegistry {
+    users: RwLock<HashMap<Uuid, UserConnections>>,
+    journal: Mutex<Journal>,
+    /// The id of the newest event, for long-polls waiting on the next one.
+    latest: watch::Sender<u64>,
+}
+
+/// Who an event went to, so a replay goes to the same people.
+#[derive(Debug)]
+enum Audience {
+    Room(Uuid),
+    /// Anyone sharing one of these rooms, as presence changes are sent.
+    Rooms(HashSet<Uuid>),
+    User(Uuid),
+    /// A reply to one connection, which no one else can resume.
+    Client,
+}
+
+#[derive(Debug)]
+struct Journal {
+    next_id: u64,
+    /// Who each event went to and when.
+    entries: VecDeque<(Audience, NaiveDateTime, Delivery)>,
+}
+
+impl Journal {
+    /// The id of the oldest event still kept.
+    fn first_id(&self) -> u64 {
+        self.next_id - self.entries.len() as u64
+    }
+}
+
+/// What a client resuming after `after` missed.
+#[derive(Debug)]
+pub struct Replay {
+    pub events: Vec<Delivery>,
+    /// False when some of what was missed has already been dropped, or
+    /// `after` is from before the server restarted. The client should refetch
+    /// whatever it shows.
+    pub complete: bool,
+    /// The newest event id when the replay was taken, for or not for them.
+    pub latest: u64,
+}
+
+#[derive(Debug)]
+struct UserConnections {
+    username: String,
+    clients: HashMap<Uuid, Client>,
+    last_seen: NaiveDateTime,
+}
+
+impl UserConnections {
+    fn presence(&self) -> Presence {
+        if self.clients.is_empty() {
+            Presence::Offline
+        } else if self
+            .clients
+            .values()
+            .any(|client| client.state == ClientState::Active)
+        {
+            Presence::Online
+        } else {
+            Presence::Away
+        }
+    }
+
+    fn rooms(&self) -> HashSet<Uuid> {
+        self.clients
+            .values()
+            .flat_map(|client| client.rooms.iter().copied())
+            .collect()
+    }
+
+    fn info(&self, user_id: Uuid) -> PresenceInfo {
+        PresenceInfo {
+            user_id,
+            username: self.username.clone(),
+            presence: self.presence(),
+            last_seen: Some(self.last_seen),
+        }
+    }
+}
+
+fn now() -> NaiveDateTime {
+    chrono::Utc::now().naive_utc()
+}
+
+impl Default for ConnectionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionRegistry {
+    pub fn new() -> Self {
+        // Ids start from the clock so ones handed out before a restart are
+        // never mistaken for new ones.
+        let next_id = now().timestamp_millis() as u64 * 1000;
+        let (latest, _) = watch::channel(next_id - 1);
+        Self {
+            users: RwLock::default(),
+            journal: Mutex::new(Journal {
+                next_id,
+                entries: VecDeque::with_capacity(JOURNAL_LEN),
+            }),
+            latest,
+        }
+    }
+
+    /// Register a connection, announcing the user if they just came online.
+    pub fn connect(&self, client: Client) {
+        let mut users = self.users.write().unwrap();
+        let user_id = client.user_id;
+        let entry = users.entry(user_id).or_insert_with(|| UserConnections {
+            username: client.username.clone(),
+            clients: HashMap::new(),
+            last_seen: now(),
+        });
+
+        let before = entry.presence();
+        entry.clients.insert(client.id, client);
+        entry.last_seen = now();
+
+        self.announce(&users, user_id, before);
+    }
+
+    /// Drop a connection. Returns the user's presence if this was their last
+    /// one, so the caller can persist when they were last seen.
+    pub fn disconnect(&self, user_id: Uuid, client_id: Uuid) -> Option<PresenceInfo> {
+        let mut users = self.users.write().unwrap();
+        let entry = users.get_mut(&user_id)?;
+        let before = entry.presence();
+        let client = entry.clients.remove(&client_id)?;
+        entry.last_seen = now();
+
+        if !entry.clients.is_empty() {
+            self.announce(&users, user_id, before);
+            return None;
+        }
+
+        let entry = users.remove(&user_id)?;
+        let info = entry.info(user_id);
+        self.fan_out(&users, client.rooms, ServerEvent::Presence(info.clone()));
+        Some(info)
+    }
+
+    /// Drop every connection a user has, which ends their streams. Returns
+    /// their presence if they had any, as `disconnect` does.
+    pub fn disconnect_user(&self, user_id: Uuid) -> Option<PresenceInfo> {
+        let mut users = self.users.write().unwrap();
+        let mut entry = users.remove(&user_id)?;
+        let rooms = entry.rooms();
+        entry.clients.clear();
+        entry.last_seen = now();
+
+        let info = entry.info(user_id);
+        self.fan_out(&users, rooms, ServerEvent::Presence(info.clone()));
+        Some(info)
+    }
+
+    /// Drop the connections a user opened with `token_id`. Returns their
+    /// presence if that left them with none, as `disconnect` does.
+    pub fn disconnect_token(&self, user_id: Uuid, token_id: Uuid) -> Option<PresenceInfo> {
+        let mut users = self.users.write().unwrap();
+        let entry = users.get_mut(&user_id)?;
+        let before = entry.presence();
+        let rooms = entry.rooms();
+        entry
+            .clients
+            .retain(|_, client| client.token_id != Some(token_id));
+        entry.last_seen = now();
+
+        if !entry.clients.is_empty() {
+            self.announce(&users, user_id, before);
+            return None;
+        }
+
+        let entry = users.remove(&user_id)?;
+        let info = entry.info(user_id);
+        self.fan_out(&users, rooms, ServerEvent::Presence(info.clone()));
+        Some(info)
+    }
+
+    pub fn set_state(&self, user_id: Uuid, client_id: Uuid, state: ClientState) {
+        let mut users = self.users.write().unwrap();
+        let Some(entry) = users.get_mut(&user_id) else {
+            return;
+        };
+        let before = entry.presence();
+        if let Some(client) = entry.clients.get_mut(&client_id) {
+            client.state = state;
+        }
+        entry.last_seen = now();
+
+        self.announce(&users, user_id, before);
+    }
+
+    /// Note activity on a connection.
+    pub fn touch(&self, user_id: Uuid) {
+        if let Some(entry) = self.users.write().unwrap().get_mut(&user_id) {
+            entry.last_seen = now();
+        }
+    }
+
+    /// The live presence of a user, or `None` if they have no connections.
+    pub fn presence(&self, user_id: Uuid) -> Option<PresenceInfo> {
+        self.users
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .map(|entry| entry.info(user_id))
+    }
+
+    /// Users with at least one active connection.
+    pub fn online_users(&self) -> Vec<Uuid> {
+        self.users
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.presence() == Presence::Online)
+            .map(|(user_id, _)| *user_id)
+            .collect()
+    }
+
+    /// Subscribe all of a user's connections to a room.
+    pub fn join_room(&self, user_id: Uuid, room_id: Uuid) {
+        if let Some(entry) = self.users.write().unwrap().get_mut(&user_id) {
+            for client in entry.clients.values_mut() {
+                client.rooms.insert(room_id);
+            }
+        }
+    }
+
+    /// Unsubscribe all of a user's connections from a room.
+    pub fn leave_room(&self, user_id: Uuid, room_id: Uuid) {
+        if let Some(entry) = self.users.write().unwrap().get_mut(&user_id) {
+            for client in entry.clients.values_mut() {
+                client.rooms.remove(&room_id);
+            }
+        }
+    }
+
+    /// Whether a connection receives events for a room.
+    pub fn is_subscribed(&self, user_id: Uuid, client_id: Uuid, room_id: Uuid) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|entry| entry.clients.get(&client_id))
+            .is_some_and(|client| client.rooms.contains(&room_id))
+    }
+
+    /// Send an event to every connection subscribed to a room.
+    pub fn publish(&self, room_id: Uuid, event: ServerEvent) {
+        let users = self.users.read().unwrap();
+        self.deliver(Audience::Room(room_id), event, |delivery| {
+            for client in users.values().flat_map(|entry| entry.clients.values()) {
+                if client.rooms.contains(&room_id) {
+                    client.send(delivery.clone());
+                }
+            }
+        });
+    }
+
+    /// Send an event to every connection of one user.
+    pub fn send_to_user(&self, user_id: Uuid, event: ServerEvent) {
+        let users = self.users.read().unwrap();
+        self.deliver(Audience::User(user_id), event, |delivery| {
+            if let Some(entry) = users.get(&user_id) {
+                for client in entry.clients.values() {
+                    client.send(delivery.clone());
+                }
+            }
+        });
+    }
+
+    /// Send an event to a single connection.
+    pub fn send_to_client(&self, user_id: Uuid, client_id: Uuid, event: ServerEvent) {
+        let users = self.users.read().unwrap();
+        let Some(client) = users
+            .get(&user_id)
+            .and_then(|entry| entry.clients.get(&client_id))
+        else {
+            return;
+        };
+        self.deliver(Audience::Client, event, |delivery| {
+            client.send(delivery.clone());
+        });
+    }
+
+    /// The events after `after` that a user was sent, given the rooms they're
+    /// in and when they joined each. Room events from before they joined are
+    /// left out.
+    pub fn replay(
+        &self,
+        user_id: Uuid,
+        rooms: &HashMap<Uuid, NaiveDateTime>,
+        after: u64,
+    ) -> Replay {
+        let journal = self.journal.lock().unwrap();
+        let complete = after >= journal.first_id().saturating_sub(1) && after < journal.next_id;
+        let skip = (after + 1).saturating_sub(journal.first_id()) as usize;
+        let events = journal
+            .entries
+            .iter()
+            .skip(skip)
+            .filter(|(audience, at, _)| {
+                let joined = |room_id: &Uuid| rooms.get(room_id).is_some_and(|joined| joined <= at);
+                match audience {
+                    Audience::Room(room_id) => joined(room_id),
+                    Audience::Rooms(shared) => shared.iter().any(joined),
+                    Audience::User(id) => *id == user_id,
+                    Audience::Client => false,
+                }
+            })
+            .map(|(_, _, delivery)| delivery.clone())
+            .collect();
+        Replay {
+            events,
+            complete,
+            latest: journal.next_id - 1,
+        }
+    }
+
+    /// Watch the id of the newest event.
+    pub fn watch(&self) -> watch::Receiver<u64> {
+        self.latest.subscribe()
+    }
+
+    /// Number an event and note who it's for, then hand it to `send`. The
+    /// journal stays locked until `send` is done so every connection gets
+    /// ids in order.
+    fn deliver(&self, audience: Audience, event: ServerEvent, send: impl FnOnce(&Delivery)) {
+        let mut journal = self.journal.lock().unwrap();
+        let delivery = Delivery {
+            id: journal.next_id,
+            event,
+        };
+        journal.next_id += 1;
+        send(&delivery);
+
+        let id = delivery.id;
+        if journal.entries.len() == JOURNAL_LEN {
+            journal.entries.pop_front();
+        }
+        journal.entries.push_back((audience, now(), delivery));
+        drop(journal);
+        self.latest.send_replace(id);
+    }
+
+    /// Broadcast a user's presence to everyone sharing a room with them, if
+    /// it changed from `before`.
+    fn announce(&self, users: &HashMap<Uuid, UserConnections>, user_id: Uuid, before: Presence) {
+        let Some(entry) = users.get(&user_id) else {
+            return;
+        };
+        if entry.presence() == before {
+            return;
+        }
+        self.fan_out(
+            users,
+            entry.rooms(),
+            ServerEvent::Presence(entry.info(user_id)),
+        );
+    }
+
+    /// Send an event once to each connection that shares any of `rooms`.
+    fn fan_out(
+        &self,
+        users: &HashMap<Uuid, UserConnections>,
+        rooms: HashSet<Uuid>,
+        event: ServerEvent,
+    ) {
+        self.deliver(Audience::Rooms(rooms.clone()), event, |delivery| {
+            for client in users.values().flat_map(|entry| entry.clients.values()) {
+                if !client.rooms.is_disjoint(&rooms) {
+                    client.send(delivery.clone());
+                }
+            }
+        });
+    }
+}
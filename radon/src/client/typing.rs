@@ -0,0 +1,91 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+/// Who is typing where. Nothing here is persisted; entries expire on their
+/// own if a client never says it stopped.
+#[derive(Debug)]
+pub struct TypingTracker {
+    timeout: Duration,
+    typing: Mutex<HashMap<(Uuid, Uuid), Typist>>,
+}
+
+#[derive(Debug)]
+struct Typist {
+    username: String,
+    expires: Instant,
+}
+
+/// A typing indicator that has run out.
+#[derive(Debug, Clone)]
+pub struct Expired {
+    pub room_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+impl TypingTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            typing: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mark a user as typing. Returns true if they weren't already, i.e. the
+    /// room needs to hear about it.
+    pub fn start(&self, room_id: Uuid, user_id: Uuid, username: &str) -> bool {
+        let expires = Instant::now() + self.timeout;
+        let mut typing = self.typing.lock().unwrap();
+        match typing.get_mut(&(room_id, user_id)) {
+            Some(typist) => {
+                typist.expires = expires;
+                false
+            }
+            None => {
+                typing.insert(
+                    (room_id, user_id),
+                    Typist {
+                        username: username.to_string(),
+                        expires,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Clear a user's indicator. Returns true if they were typing.
+    pub fn stop(&self, room_id: Uuid, user_id: Uuid) -> bool {
+        self.typing
+            .lock()
+            .unwrap()
+            .remove(&(room_id, user_id))
+            .is_some()
+    }
+
+    /// Remove and return every indicator past its deadline.
+    pub fn expire(&self) -> Vec<Expired> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.typing
+            .lock()
+            .unwrap()
+            .retain(|(room_id, user_id), typist| {
+                if typist.expires > now {
+                    return true;
+                }
+                expired.push(Expired {
+                    room_id: *room_id,
+                    user_id: *user_id,
+                    username: typist.username.clone(),
+                });
+                false
+            });
+        expired
+    }
+}
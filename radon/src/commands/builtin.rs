@@ -0,0 +1,593 @@
+use rand::Rng;
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        logins, mentions, messages, moderation, passwords,
+        roles::{self, Permissions, Role},
+    },
+    message::{MessageType, ServerEvent},
+};
+
+use super::{
+    error::CommandError, Command, CommandArgs, CommandContext, CommandOutcome, HandlerFuture,
+    Permission,
+};
+
+const SHRUG: &str = r"¯\_(ツ)_/¯";
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+const LOGINS_SHOWN: i64 = 10;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command {
+            name: "help",
+            usage: "/help [command]",
+            help: "List commands, or show help for one command",
+            permission: Permission::Anyone,
+            min_args: 0,
+            max_args: Some(1),
+            handler: help,
+        },
+        Command {
+            name: "me",
+            usage: "/me <action>",
+            help: "Describe something you are doing",
+            permission: Permission::Room(Permissions::POST),
+            min_args: 1,
+            max_args: None,
+            handler: me,
+        },
+        Command {
+            name: "shrug",
+            usage: "/shrug [message]",
+            help: "Append a shrug to your message",
+            permission: Permission::Room(Permissions::POST),
+            min_args: 0,
+            max_args: None,
+            handler: shrug,
+        },
+        Command {
+            name: "roll",
+            usage: "/roll [NdM]",
+            help: "Roll N dice with M sides (defaults to 1d6)",
+            permission: Permission::Room(Permissions::POST),
+            min_args: 0,
+            max_args: Some(1),
+            handler: roll,
+        },
+        Command {
+            name: "topic",
+            usage: "/topic <topic>",
+            help: "Set the room topic",
+            permission: Permission::Room(Permissions::EDIT_TOPIC),
+            min_args: 1,
+            max_args: None,
+            handler: topic,
+        },
+        Command {
+            name: "kick",
+            usage: "/kick <username> [reason]",
+            help: "Remove a user from the room",
+            permission: Permission::Room(Permissions::KICK),
+            min_args: 1,
+            max_args: None,
+            handler: kick,
+        },
+        Command {
+            name: "ban",
+            usage: "/ban <username> [duration] [reason]",
+            help: "Remove a user from the room and keep them out, e.g. /ban bob 1d spam",
+            permission: Permission::Room(Permissions::BAN),
+            min_args: 1,
+            max_args: None,
+            handler: ban,
+        },
+        Command {
+            name: "unban",
+            usage: "/unban <username>",
+            help: "Let a banned user back in",
+            permission: Permission::Room(Permissions::BAN),
+            min_args: 1,
+            max_args: Some(1),
+            handler: unban,
+        },
+        Command {
+            name: "mute",
+            usage: "/mute <username> [duration] [reason]",
+            help: "Stop a member posting, e.g. /mute bob 10m",
+            permission: Permission::Room(Permissions::MUTE),
+            min_args: 1,
+            max_args: None,
+            handler: mute,
+        },
+        Command {
+            name: "unmute",
+            usage: "/unmute <username>",
+            help: "Let a muted member post again",
+            permission: Permission::Room(Permissions::MUTE),
+            min_args: 1,
+            max_args: Some(1),
+            handler: unmute,
+        },
+        Command {
+            name: "slow",
+            usage: "/slow <seconds|off>",
+            help: "Limit how often each member can post",
+            permission: Permission::Room(Permissions::SLOW_MODE),
+            min_args: 1,
+            max_args: Some(1),
+            handler: slow,
+        },
+        Command {
+            name: "invite",
+            usage: "/invite <username>",
+            help: "Add a user to the room",
+            permission: Permission::Room(Permissions::INVITE),
+            min_args: 1,
+            max_args: Some(1),
+            handler: invite,
+        },
+        Command {
+            name: "inviteonly",
+            usage: "/inviteonly <on|off>",
+            help: "Only let users in that a member invites",
+            permission: Permission::Room(Permissions::MANAGE_ROOM),
+            min_args: 1,
+            max_args: Some(1),
+            handler: invite_only,
+        },
+        Command {
+            name: "role",
+            usage: "/role <username> <guest|member|moderator|admin|owner>",
+            help: "Change a member's role",
+            permission: Permission::Room(Permissions::MANAGE_ROLES),
+            min_args: 2,
+            max_args: Some(2),
+            handler: role,
+        },
+        Command {
+            name: "logins",
+            usage: "/logins",
+            help: "Show your recent login attempts",
+            permission: Permission::Anyone,
+            min_args: 0,
+            max_args: Some(0),
+            handler: logins,
+        },
+        Command {
+            name: "unlock",
+            usage: "/unlock <username>",
+            help: "Let a user locked out by failed logins try again",
+            permission: Permission::ServerAdmin,
+            min_args: 1,
+            max_args: Some(1),
+            handler: unlock,
+        },
+        Command {
+            name: "resetpassword",
+            usage: "/resetpassword <username>",
+            help: "Issue a single use token the user can set a new password with",
+            permission: Permission::ServerAdmin,
+            min_args: 1,
+            max_args: Some(1),
+            handler: reset_password,
+        },
+    ]
+}
+
+async fn post(
+    ctx: &CommandContext,
+    kind: MessageType,
+    text: String,
+) -> Result<CommandOutcome, CommandError> {
+    if text.chars().count() > messages::MAX_MESSAGE_LEN {
+        return Err(CommandError::TooLong);
+    }
+    let message = messages::insert_message(
+        &ctx.state.db,
+        ctx.room_id,
+        Some(ctx.user_id),
+        Some(ctx.username.clone()),
+        kind,
+        text,
+        None,
+    )
+    .await?;
+    mentions::notify(&ctx.state, &message).await?;
+    ctx.state
+        .publish(ctx.room_id, ServerEvent::Message(message));
+    Ok(CommandOutcome::Done)
+}
+
+async fn lookup_user(ctx: &CommandContext, username: &str) -> Result<Uuid, CommandError> {
+    sqlx::query_scalar!(
+        r#"select user_id from "users" where username = $1"#,
+        username
+    )
+    .fetch_optional(&ctx.state.db)
+    .await?
+    .ok_or_else(|| CommandError::NotFound(format!("No such user: {}", username)))
+}
+
+fn help(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let commands = &ctx.state.commands;
+        let text = match args.get(0) {
+            Some(name) => {
+                let name = name.trim_start_matches('/');
+                let command = commands
+                    .get(name)
+                    .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+                format!("{} - {}", command.usage, command.help)
+            }
+            None => commands
+                .list()
+                .into_iter()
+                .map(|info| format!("{} - {}", info.usage, info.help))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        };
+        Ok(CommandOutcome::Reply(text))
+    })
+}
+
+fn me(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move { post(&ctx, MessageType::Action, args.raw).await })
+}
+
+fn shrug(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let text = if args.raw.is_empty() {
+            SHRUG.to_string()
+        } else {
+            format!("{} {}", args.raw, SHRUG)
+        };
+        post(&ctx, MessageType::Text, text).await
+    })
+}
+
+/// Parse dice notation like `2d6` into (dice, sides).
+fn parse_dice(spec: &str) -> Result<(u32, u32), CommandError> {
+    let invalid = || CommandError::InvalidArgument(format!("expected NdM, got {}", spec));
+
+    let spec = spec.to_lowercase();
+    let (dice, sides) = spec.split_once('d').ok_or_else(invalid)?;
+    let dice = if dice.is_empty() {
+        1
+    } else {
+        dice.parse::<u32>().map_err(|_| invalid())?
+    };
+    let sides = sides.parse::<u32>().map_err(|_| invalid())?;
+
+    if !(1..=MAX_DICE).contains(&dice) || !(2..=MAX_SIDES).contains(&sides) {
+        return Err(CommandError::InvalidArgument(format!(
+            "between 1 and {} dice with 2 to {} sides",
+            MAX_DICE, MAX_SIDES
+        )));
+    }
+
+    Ok((dice, sides))
+}
+
+fn roll(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let spec = args.get(0).unwrap_or("1d6").to_string();
+        let (dice, sides) = parse_dice(&spec)?;
+
+        let rolls = {
+            let mut rng = rand::thread_rng();
+            (0..dice)
+                .map(|_| rng.gen_range(1..=sides))
+                .collect::<Vec<u32>>()
+        };
+        let total: u32 = rolls.iter().sum();
+        let rolls = rolls
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let text = format!(
+            "{} rolled {}: {} (total {})",
+            ctx.username, spec, rolls, total
+        );
+        post(&ctx, MessageType::System, text).await
+    })
+}
+
+fn topic(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let topic = args.raw;
+
+        sqlx::query!(
+            r#"update "rooms" set topic = $1 where room_id = $2"#,
+            topic,
+            ctx.room_id
+        )
+        .execute(&ctx.state.db)
+        .await?;
+
+        ctx.state.publish(
+            ctx.room_id,
+            ServerEvent::Topic {
+                room_id: ctx.room_id,
+                topic: topic.clone(),
+                set_by: ctx.username.clone(),
+            },
+        );
+
+        let text = format!("{} changed the topic to: {}", ctx.username, topic);
+        post(&ctx, MessageType::System, text).await
+    })
+}
+
+fn kick(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+        if user_id == ctx.user_id {
+            return Err(CommandError::InvalidArgument(
+                "you cannot kick yourself".to_string(),
+            ));
+        }
+
+        moderation::kick(
+            &ctx.state,
+            ctx.room_id,
+            ctx.user_id,
+            &ctx.username,
+            user_id,
+            args.rest(1),
+        )
+        .await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+/// Split `<username> [duration] [reason]` arguments.
+fn sanction_args(args: &CommandArgs) -> (String, Option<chrono::Duration>, Option<String>) {
+    let username = args.get(0).unwrap_or_default().to_string();
+    match args.get(1).and_then(moderation::parse_duration) {
+        Some(duration) => (username, Some(duration), args.rest(2)),
+        None => (username, None, args.rest(1)),
+    }
+}
+
+fn ban(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let (username, duration, reason) = sanction_args(&args);
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        moderation::ban(
+            &ctx.state,
+            ctx.room_id,
+            ctx.user_id,
+            &ctx.username,
+            user_id,
+            reason,
+            duration,
+        )
+        .await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+fn unban(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        moderation::unban(&ctx.state, ctx.room_id, ctx.user_id, &ctx.username, user_id).await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+fn mute(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let (username, duration, reason) = sanction_args(&args);
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        moderation::mute(
+            &ctx.state,
+            ctx.room_id,
+            ctx.user_id,
+            &ctx.username,
+            user_id,
+            reason,
+            duration,
+        )
+        .await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+fn unmute(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        moderation::unmute(&ctx.state, ctx.room_id, ctx.user_id, &ctx.username, user_id).await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+fn slow(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let spec = args.get(0).unwrap_or_default();
+        let seconds = if spec.eq_ignore_ascii_case("off") {
+            0
+        } else {
+            moderation::parse_duration(spec)
+                .and_then(|duration| i32::try_from(duration.num_seconds()).ok())
+                .ok_or_else(|| {
+                    CommandError::InvalidArgument(format!("expected seconds or off, got {}", spec))
+                })?
+        };
+
+        moderation::set_slow_mode(&ctx.state, ctx.room_id, ctx.user_id, &ctx.username, seconds)
+            .await?;
+        Ok(CommandOutcome::Done)
+    })
+}
+
+fn invite_only(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let spec = args.get(0).unwrap_or_default();
+        let invite_only = match spec.to_ascii_lowercase().as_str() {
+            "on" => true,
+            "off" => false,
+            _ => {
+                return Err(CommandError::InvalidArgument(format!(
+                    "expected on or off, got {}",
+                    spec
+                )))
+            }
+        };
+
+        sqlx::query!(
+            r#"update "rooms" set invite_only = $1 where room_id = $2"#,
+            invite_only,
+            ctx.room_id
+        )
+        .execute(&ctx.state.db)
+        .await?;
+
+        let text = if invite_only {
+            format!("{} made the room invite only", ctx.username)
+        } else {
+            format!("{} opened the room to everyone", ctx.username)
+        };
+        post(&ctx, MessageType::System, text).await
+    })
+}
+
+fn invite(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        if moderation::is_banned(&ctx.state.db, ctx.room_id, user_id).await? {
+            return Err(CommandError::InvalidArgument(format!(
+                "{} is banned from this room",
+                username
+            )));
+        }
+
+        let added = sqlx::query!(
+            // language=PostgreSQL
+            r#"
+                insert into "room_members"(room_id, user_id, joined_at)
+                values ($1, $2, $3)
+                on conflict do nothing
+            "#,
+            ctx.room_id,
+            user_id,
+            chrono::Utc::now().naive_utc()
+        )
+        .execute(&ctx.state.db)
+        .await?;
+
+        if added.rows_affected() == 0 {
+            return Err(CommandError::InvalidArgument(format!(
+                "{} is already in this room",
+                username
+            )));
+        }
+
+        ctx.state.clients.join_room(user_id, ctx.room_id);
+        ctx.state.publish(
+            ctx.room_id,
+            ServerEvent::MemberJoined {
+                room_id: ctx.room_id,
+                user_id,
+                username: username.clone(),
+            },
+        );
+
+        let text = format!("{} was invited by {}", username, ctx.username);
+        post(&ctx, MessageType::System, text).await
+    })
+}
+
+fn role(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let role = args
+            .get(1)
+            .unwrap_or_default()
+            .to_lowercase()
+            .parse::<Role>()?;
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        roles::set_role(
+            &ctx.state,
+            ctx.room_id,
+            ctx.user_id,
+            &ctx.username,
+            user_id,
+            role,
+        )
+        .await?;
+
+        let text = format!("{} made {} {}", ctx.username, username, role);
+        post(&ctx, MessageType::System, text).await
+    })
+}
+
+fn logins(ctx: CommandContext, _args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let events = logins::recent(&ctx.state.db, ctx.user_id, LOGINS_SHOWN).await?;
+        if events.is_empty() {
+            return Ok(CommandOutcome::Reply("No logins recorded".to_string()));
+        }
+
+        let text = events
+            .into_iter()
+            .map(|event| {
+                let outcome = match (event.success, event.reason.as_deref()) {
+                    (true, _) => "ok",
+                    (false, Some("locked")) => "refused, locked",
+                    (false, Some("bad_code")) => "failed, wrong code",
+                    (false, Some("bad_key")) => "failed, bad SSH key",
+                    (false, _) => "failed",
+                };
+                format!(
+                    "{} {} from {} ({})",
+                    event.created_at.format("%Y-%m-%d %H:%M"),
+                    outcome,
+                    event.ip,
+                    event.user_agent.as_deref().unwrap_or("unknown client")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        Ok(CommandOutcome::Reply(text))
+    })
+}
+
+fn unlock(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        logins::unlock(&ctx.state.db, ctx.user_id, user_id).await?;
+        Ok(CommandOutcome::Reply(format!("Unlocked {}", username)))
+    })
+}
+
+fn reset_password(ctx: CommandContext, args: CommandArgs) -> HandlerFuture {
+    Box::pin(async move {
+        let username = args.get(0).unwrap_or_default().to_string();
+        let user_id = lookup_user(&ctx, &username).await?;
+
+        let reset = passwords::issue_reset(&ctx.state, ctx.user_id, user_id).await?;
+        Ok(CommandOutcome::Reply(format!(
+            "Reset token for {}, valid until {} UTC: {}",
+            username,
+            reset.expires_at.format("%Y-%m-%d %H:%M"),
+            reset.token
+        )))
+    })
+}
@@ -0,0 +1,74 @@
+use thiserror::Error;
+
+use crate::api::{
+    logins::error::LoginsError, moderation::error::ModerationError,
+    passwords::error::PasswordError, roles::error::RolesError,
+};
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("Unknown command: /{0}")]
+    Unknown(String),
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("You do not have permission to run this command")]
+    Forbidden,
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Message is too long")]
+    TooLong,
+    #[error(transparent)]
+    Moderation(#[from] ModerationError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl CommandError {
+    /// Stable, machine readable code sent to clients alongside the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CommandError::Unknown(_) => "unknown_command",
+            CommandError::Usage(_) => "usage",
+            CommandError::InvalidArgument(_) => "invalid_argument",
+            CommandError::Forbidden => "forbidden",
+            CommandError::NotFound(_) => "not_found",
+            CommandError::TooLong => "too_long",
+            CommandError::Moderation(e) => e.code(),
+            CommandError::Database(_) => "internal",
+        }
+    }
+}
+
+impl From<RolesError> for CommandError {
+    fn from(e: RolesError) -> Self {
+        match e {
+            RolesError::Invalid => CommandError::InvalidArgument(e.to_string()),
+            RolesError::NotFound => CommandError::NotFound(e.to_string()),
+            RolesError::NotMember | RolesError::Forbidden => CommandError::Forbidden,
+            RolesError::Database(e) => CommandError::Database(e),
+        }
+    }
+}
+
+impl From<LoginsError> for CommandError {
+    fn from(e: LoginsError) -> Self {
+        match e {
+            LoginsError::NotFound => CommandError::NotFound(e.to_string()),
+            LoginsError::Forbidden => CommandError::Forbidden,
+            LoginsError::Database(e) => CommandError::Database(e),
+        }
+    }
+}
+
+impl From<PasswordError> for CommandError {
+    fn from(e: PasswordError) -> Self {
+        match e {
+            PasswordError::NotFound => CommandError::NotFound(e.to_string()),
+            PasswordError::Forbidden => CommandError::Forbidden,
+            PasswordError::Database(e) => CommandError::Database(e),
+            e => CommandError::InvalidArgument(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+pub mod builtin;
+pub mod error;
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use futures::future::BoxFuture;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::{
+    moderation,
+    roles::{self, Permissions},
+    AppState,
+};
+
+use self::error::CommandError;
+
+/// Who is allowed to run a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Any authenticated user.
+    Anyone,
+    /// Users holding these permissions in the room the command is run in.
+    Room(Permissions),
+    /// Server admins, wherever they run it.
+    ServerAdmin,
+}
+
+/// Everything a handler needs to know about who ran it and where.
+#[derive(Clone)]
+pub struct CommandContext {
+    pub state: Arc<AppState>,
+    pub user_id: Uuid,
+    pub username: String,
+    pub room_id: Uuid,
+}
+
+/// Arguments as typed by the user, plus the same input split on whitespace
+/// (double quotes group words together).
+#[derive(Debug, Clone)]
+pub struct CommandArgs {
+    pub raw: String,
+    pub positional: Vec<String>,
+    /// Where each positional argument starts in `raw`.
+    starts: Vec<usize>,
+}
+
+impl CommandArgs {
+    pub fn parse(raw: &str) -> Result<Self, CommandError> {
+        let raw = raw.trim();
+        let mut positional = Vec::new();
+        let mut starts = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut pending = false;
+
+        for (i, c) in raw.char_indices() {
+            match c {
+                '"' => {
+                    if !pending {
+                        starts.push(i);
+                    }
+                    in_quotes = !in_quotes;
+                    pending = true;
+                }
+                c if c.is_whitespace() && !in_quotes => {
+                    if pending {
+                        positional.push(std::mem::take(&mut current));
+                        pending = false;
+                    }
+                }
+                c => {
+                    if !pending {
+                        starts.push(i);
+                    }
+                    current.push(c);
+                    pending = true;
+                }
+            }
+        }
+
+        if in_quotes {
+            return Err(CommandError::InvalidArgument(
+                "unterminated quote".to_string(),
+            ));
+        }
+        if pending {
+            positional.push(current);
+        }
+
+        Ok(Self {
+            raw: raw.to_string(),
+            positional,
+            starts,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    /// Everything after the first `skip` positional arguments, as typed.
+    pub fn rest(&self, skip: usize) -> Option<String> {
+        let start = *self.starts.get(skip)?;
+        Some(self.raw[start..].to_string())
+    }
+}
+
+/// What to tell the user that ran the command once it succeeds. Anything the
+/// rest of the room should see is published by the handler itself.
+#[derive(Debug, Clone)]
+pub enum CommandOutcome {
+    Reply(String),
+    Done,
+}
+
+pub type HandlerFuture = BoxFuture<'static, Result<CommandOutcome, CommandError>>;
+pub type Handler = fn(CommandContext, CommandArgs) -> HandlerFuture;
+
+#[derive(Clone)]
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub permission: Permission,
+    pub min_args: usize,
+    pub max_args: Option<usize>,
+    pub handler: Handler,
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("permission", &self.permission)
+            .finish()
+    }
+}
+
+/// The description of a command handed to clients for help and completion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandInfo {
+    pub name: String,
+    pub usage: String,
+    pub help: String,
+}
+
+impl From<&Command> for CommandInfo {
+    fn from(command: &Command) -> Self {
+        Self {
+            name: command.name.to_string(),
+            usage: command.usage.to_string(),
+            help: command.help.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Command>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with every command radon ships with.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for command in builtin::commands() {
+            registry.register(command);
+        }
+        registry
+    }
+
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    pub fn list(&self) -> Vec<CommandInfo> {
+        self.commands.values().map(CommandInfo::from).collect()
+    }
+
+    pub async fn dispatch(
+        &self,
+        ctx: CommandContext,
+        name: &str,
+        raw_args: &str,
+    ) -> Result<CommandOutcome, CommandError> {
+        let name = name.trim_start_matches('/').to_lowercase();
+        let command = self
+            .get(&name)
+            .ok_or_else(|| CommandError::Unknown(name.clone()))?;
+
+        authorize(&ctx, command.permission).await?;
+
+        let args = CommandArgs::parse(raw_args)?;
+        let count = args.positional.len();
+        if count < command.min_args || command.max_args.is_some_and(|max| count > max) {
+            return Err(CommandError::Usage(command.usage));
+        }
+
+        (command.handler)(ctx, args).await
+    }
+}
+
+async fn authorize(ctx: &CommandContext, permission: Permission) -> Result<(), CommandError> {
+    match permission {
+        Permission::Anyone => Ok(()),
+        Permission::Room(permissions) => {
+            roles::authorize(&ctx.state.db, ctx.room_id, ctx.user_id, permissions).await?;
+            // Commands that post are held to mutes and slow mode like any
+            // other message.
+            if permissions.contains(Permissions::POST) {
+                moderation::check_post(&ctx.state.db, ctx.room_id, ctx.user_id).await?;
+            }
+            Ok(())
+        }
+        Permission::ServerAdmin => {
+            if roles::is_server_admin(&ctx.state.db, ctx.user_id).await? {
+                Ok(())
+            } else {
+                Err(CommandError::Forbidden)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_group_words() {
+        let args = CommandArgs::parse(r#"  bob "two  words" three "#).unwrap();
+        assert_eq!(args.positional, ["bob", "two  words", "three"]);
+        assert_eq!(args.raw, r#"bob "two  words" three"#);
+    }
+
+    #[test]
+    fn rest_keeps_what_was_typed() {
+        let args = CommandArgs::parse(r#"bob 1h  "way too"   loud"#).unwrap();
+        assert_eq!(args.rest(1).as_deref(), Some(r#"1h  "way too"   loud"#));
+        assert_eq!(args.rest(2).as_deref(), Some(r#""way too"   loud"#));
+        assert_eq!(args.rest(4), None);
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        assert!(CommandArgs::parse(r#"bob "oops"#).is_err());
+    }
+}
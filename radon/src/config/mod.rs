@@ -17,7 +17,198 @@ pub struct ServerConfig {
     pub db_port: String,
     pub db_name: String,
     pub db_connection_string: String,
+    /// Shared secret for HS256 tokens. Only signs when `jwt_keys` is
+    /// empty, and must be changed from the default outside the dev profile.
     pub jwt_secret: String,
+    /// Keys tokens are signed and verified with. Every key verifies, so old
+    /// keys can stay listed, without a private key, while tokens signed by
+    /// them expire.
+    pub jwt_keys: Vec<JwtKeyConfig>,
+    /// Which of `jwt_keys` signs new tokens. Defaults to the first one with
+    /// a private key.
+    pub jwt_signing_kid: Option<String>,
+    /// "dev" allows insecure defaults like the built in `jwt_secret`.
+    pub profile: String,
+    /// How long a typing indicator lasts without a refresh.
+    pub typing_timeout_secs: u64,
+    /// How long after sending a message its author may edit it. 0 means forever.
+    pub edit_window_secs: u64,
+    /// Where uploaded files are stored, named by their SHA-256.
+    pub upload_dir: String,
+    /// Largest single upload, in bytes.
+    pub max_upload_bytes: u64,
+    /// Total size of everything one user has uploaded, in bytes.
+    pub user_upload_quota_bytes: u64,
+    /// Total size of all stored files, in bytes.
+    pub upload_quota_bytes: u64,
+    /// Usernames made server admins at startup.
+    pub admins: Vec<String>,
+    pub rate_limits: RateLimits,
+    /// Failed logins in a row before an account is locked.
+    pub lockout_threshold: i32,
+    /// How long the first lockout lasts. Each further failure doubles it.
+    pub lockout_base_secs: u64,
+    /// Longest lockout.
+    pub lockout_max_secs: u64,
+    pub password_policy: PasswordPolicy,
+    /// How long an admin issued password reset token lasts.
+    pub password_reset_secs: u64,
+    pub argon2: Argon2Config,
+    pub outgoing_webhooks: OutgoingWebhooks,
+}
+
+/// How events are delivered to outgoing webhooks.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct OutgoingWebhooks {
+    /// Tries before a delivery is given up on and marked dead.
+    pub max_attempts: i32,
+    /// Wait before the first retry. Each further retry doubles it.
+    pub backoff_base_secs: u64,
+    /// Longest wait between retries.
+    pub backoff_max_secs: u64,
+    /// How long an endpoint has to respond.
+    pub timeout_secs: u64,
+    /// Let hooks point at private, loopback and link local addresses.
+    pub allow_private: bool,
+}
+
+impl Default for OutgoingWebhooks {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            backoff_base_secs: 10,
+            backoff_max_secs: 60 * 60,
+            timeout_secs: 10,
+            allow_private: false,
+        }
+    }
+}
+
+pub const DEFAULT_JWT_SECRET: &str = "secret";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JwtAlgorithm {
+    EdDSA,
+    ES256,
+}
+
+/// A token signing key, as PEM files: the public key as SubjectPublicKeyInfo
+/// and the private key as PKCS#8.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JwtKeyConfig {
+    /// Put in the `kid` header of tokens this key signs.
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    pub public_key: String,
+    pub private_key: Option<String>,
+}
+
+/// Argon2id cost for new password hashes. Run `radon argon2-bench` for
+/// values that suit this machine. Hashes made with weaker settings are
+/// upgraded when their user next logs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Argon2Config {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    /// The argon2 crate's defaults, as OWASP recommends.
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// What a new password has to look like.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PasswordPolicy {
+    /// Fewest characters.
+    pub min_length: usize,
+    /// Most bytes. Hashing cost grows with length, so this is bounded.
+    pub max_length: usize,
+    /// Passwords refused on top of the built in list of common ones,
+    /// compared ignoring case.
+    pub blacklist: Vec<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 10,
+            max_length: 128,
+            blacklist: Vec::new(),
+        }
+    }
+}
+
+/// A token bucket: up to `burst` requests at once, refilling at
+/// `per_minute`. A `per_minute` of 0 turns the limit off.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimits {
+    /// Login attempts per address, and separately per username.
+    pub login: RateLimit,
+    /// Accounts created per address.
+    pub register: RateLimit,
+    /// Other REST calls per caller and route.
+    pub rest: RateLimit,
+    /// Frames on a single websocket.
+    pub ws_connection: RateLimit,
+    /// Frames across all of a user's websockets.
+    pub ws_user: RateLimit,
+    /// Posts to one incoming webhook.
+    pub webhook: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            login: RateLimit {
+                burst: 5,
+                per_minute: 5,
+            },
+            register: RateLimit {
+                burst: 3,
+                per_minute: 1,
+            },
+            rest: RateLimit {
+                burst: 60,
+                per_minute: 120,
+            },
+            ws_connection: RateLimit {
+                burst: 20,
+                per_minute: 120,
+            },
+            ws_user: RateLimit {
+                burst: 40,
+                per_minute: 240,
+            },
+            webhook: RateLimit {
+                burst: 10,
+                per_minute: 30,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct BenchArgs {
+    /// How long hashing one password should take, in milliseconds
+    #[arg(long = "target-ms", default_value_t = 500)]
+    pub target_ms: u64,
+    /// Threads to hash with
+    #[arg(long = "parallelism", default_value_t = 1)]
+    pub parallelism: u32,
 }
 
 #[derive(Debug, Parser)]
@@ -64,7 +255,25 @@ impl Default for ServerConfig {
             db_port: "5432".to_string(),
             db_name: "radon".to_string(),
             db_connection_string: "".to_string(),
-            jwt_secret: "secret".to_string(),
+            jwt_secret: DEFAULT_JWT_SECRET.to_string(),
+            jwt_keys: Vec::new(),
+            jwt_signing_kid: None,
+            profile: "production".to_string(),
+            typing_timeout_secs: 6,
+            edit_window_secs: 15 * 60,
+            upload_dir: "uploads".to_string(),
+            max_upload_bytes: 25 * 1024 * 1024,
+            user_upload_quota_bytes: 500 * 1024 * 1024,
+            upload_quota_bytes: 20 * 1024 * 1024 * 1024,
+            admins: Vec::new(),
+            rate_limits: RateLimits::default(),
+            lockout_threshold: 5,
+            lockout_base_secs: 60,
+            lockout_max_secs: 24 * 60 * 60,
+            password_policy: PasswordPolicy::default(),
+            password_reset_secs: 24 * 60 * 60,
+            argon2: Argon2Config::default(),
+            outgoing_webhooks: OutgoingWebhooks::default(),
         }
     }
 }
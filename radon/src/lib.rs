@@ -1,4 +1,10 @@
 pub mod api;
 pub mod client;
+pub mod commands;
 pub mod config;
 pub mod message;
+pub mod middleware;
+pub mod ratelimit;
+
+/// The database migrations, run at startup.
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
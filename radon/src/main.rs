@@ -1,11 +1,11 @@
 use clap::{crate_version, Parser, Subcommand};
 use log::info;
 use radon::{
-    api::{self, AppState},
-    config::{RunArgs, ServerConfig},
+    api::{self, auth::utils, AppState},
+    config::{BenchArgs, RunArgs, ServerConfig},
 };
 use sqlx::postgres::PgPoolOptions;
-use std::{env, process, sync::Arc};
+use std::{env, process, sync::Arc, time::Duration};
 
 #[derive(Debug, Parser)]
 #[command(name="radon", version=crate_version!(), about="terminal chat server", long_about = "Server to let you chat with friends in the terminal", arg_required_else_help(true))]
@@ -18,6 +18,8 @@ struct App {
 #[derive(Debug, Subcommand)]
 enum Commands {
     Run(RunArgs),
+    /// Suggest Argon2 settings for this machine
+    Argon2Bench(BenchArgs),
 }
 
 #[tokio::main]
@@ -39,14 +41,36 @@ async fn main() {
                 .await
                 .unwrap();
 
-            let app_state = AppState {
-                config,
-                db: db.clone(),
-            };
+            let app_state = AppState::new(config, db.clone()).unwrap();
+
+            radon::MIGRATOR.run(&db).await.unwrap();
+            api::roles::grant_server_admin(&db, &app_state.config.admins)
+                .await
+                .unwrap();
+            app_state.revocations.load(&db).await.unwrap();
+
+            let argon2 = app_state.config.argon2;
+            let hash_time = utils::time_hash(&argon2).unwrap();
+            info!(
+                "Hashing a password takes {}ms with {:?}",
+                hash_time.as_millis(),
+                argon2
+            );
 
-            sqlx::migrate!().run(&db).await.unwrap();
             api::run(Arc::new(app_state)).await;
         }
+        Some(Commands::Argon2Bench(arguments)) => {
+            let target = Duration::from_millis(arguments.target_ms);
+            let (suggested, took) = utils::suggest_params(target, arguments.parallelism).unwrap();
+            println!(
+                "# hashing takes {}ms on this machine with these settings",
+                took.as_millis()
+            );
+            println!("[argon2]");
+            println!("memory_kib = {}", suggested.memory_kib);
+            println!("iterations = {}", suggested.iterations);
+            println!("parallelism = {}", suggested.parallelism);
+        }
         None => {
             eprintln!("No command provided");
             process::exit(1);
@@ -1,10 +1,56 @@
+use std::{fmt, str::FromStr};
+
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::{
+    api::{roles::Role, rooms::ReadState},
+    client::PresenceInfo,
+    commands::CommandInfo,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum MessageType {
     Join,
     Leave,
     Text,
+    Action,
+    System,
+}
+
+impl MessageType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::Join => "join",
+            MessageType::Leave => "leave",
+            MessageType::Text => "text",
+            MessageType::Action => "action",
+            MessageType::System => "system",
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MessageType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "join" => Ok(MessageType::Join),
+            "leave" => Ok(MessageType::Leave),
+            "text" => Ok(MessageType::Text),
+            "action" => Ok(MessageType::Action),
+            "system" => Ok(MessageType::System),
+            other => Err(format!("unknown message type: {}", other)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -19,3 +65,305 @@ impl TextMessage {
         Self { kind, from, text }
     }
 }
+
+/// A message as it was persisted in a room.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub message_id: i64,
+    pub room_id: Uuid,
+    pub seq: i64,
+    pub kind: MessageType,
+    pub user_id: Option<Uuid>,
+    pub from: Option<String>,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+    pub edited_at: Option<NaiveDateTime>,
+    /// Deleted messages keep their place in the room with an empty text.
+    pub deleted: bool,
+    /// The thread this message replies in, if any.
+    pub parent_id: Option<i64>,
+    pub reply_to: Option<ReplyPreview>,
+    /// Set on thread roots once someone has replied.
+    pub thread: Option<ThreadSummary>,
+    pub reactions: Vec<Reaction>,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    /// `@username`
+    User,
+    /// `@here`
+    Here,
+    /// `@room`
+    Room,
+}
+
+impl FromStr for MentionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(MentionKind::User),
+            "here" => Ok(MentionKind::Here),
+            "room" => Ok(MentionKind::Room),
+            other => Err(format!("unknown mention kind: {}", other)),
+        }
+    }
+}
+
+/// A message that pinged someone, and how.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MentionInfo {
+    pub kind: MentionKind,
+    pub message: ChatMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pin {
+    pub message: ChatMessage,
+    pub pinned_by: Option<String>,
+    pub pinned_at: NaiveDateTime,
+}
+
+/// A message a user saved for later.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bookmark {
+    pub message: ChatMessage,
+    pub created_at: NaiveDateTime,
+}
+
+/// Everyone who reacted to a message with one emoji.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: i64,
+    pub user_ids: Vec<Uuid>,
+}
+
+/// A file posted with a message. Download it from `/uploads/:upload_id`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub upload_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub mime: String,
+}
+
+/// Enough of a parent message to quote it above a reply.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplyPreview {
+    pub message_id: i64,
+    pub from: Option<String>,
+    pub text: String,
+    pub deleted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ThreadSummary {
+    pub reply_count: i64,
+    pub last_reply_at: NaiveDateTime,
+    pub last_reply_from: Option<String>,
+}
+
+/// Frames sent by clients over `/ws`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Send {
+        room_id: Uuid,
+        text: String,
+        /// Reply in the thread of this message.
+        #[serde(default)]
+        parent_id: Option<i64>,
+        /// Uploads from `POST /uploads` to post with the message.
+        #[serde(default)]
+        attachments: Vec<Uuid>,
+    },
+    Command {
+        room_id: Uuid,
+        name: String,
+        #[serde(default)]
+        args: String,
+    },
+    ListCommands,
+    /// Mark this connection away (or back).
+    SetPresence {
+        away: bool,
+    },
+    Typing {
+        room_id: Uuid,
+        typing: bool,
+    },
+    /// Everything up to and including `seq` has been read.
+    MarkRead {
+        room_id: Uuid,
+        seq: i64,
+    },
+    Edit {
+        message_id: i64,
+        text: String,
+    },
+    Delete {
+        message_id: i64,
+    },
+    React {
+        message_id: i64,
+        emoji: String,
+    },
+    Unreact {
+        message_id: i64,
+        emoji: String,
+    },
+    Pin {
+        message_id: i64,
+    },
+    Unpin {
+        message_id: i64,
+    },
+    Bookmark {
+        message_id: i64,
+    },
+    Unbookmark {
+        message_id: i64,
+    },
+}
+
+/// Events sent by the server over `/ws`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Message(ChatMessage),
+    MessageEdited {
+        room_id: Uuid,
+        message_id: i64,
+        text: String,
+        edited_at: NaiveDateTime,
+    },
+    MessageDeleted {
+        room_id: Uuid,
+        message_id: i64,
+        deleted_by: Uuid,
+    },
+    ReactionAdded {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    ReactionRemoved {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    MessagePinned(Pin),
+    MessageUnpinned {
+        room_id: Uuid,
+        message_id: i64,
+        unpinned_by: String,
+    },
+    /// Sent to the user's own connections only.
+    BookmarkAdded(Bookmark),
+    BookmarkRemoved {
+        message_id: i64,
+    },
+    Topic {
+        room_id: Uuid,
+        topic: String,
+        set_by: String,
+    },
+    MemberJoined {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+    },
+    MemberKicked {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+    },
+    MemberBanned {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+    },
+    /// `by` is empty when the ban ran out.
+    MemberUnbanned {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: Option<String>,
+    },
+    MemberMuted {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+    },
+    /// `by` is empty when the mute ran out.
+    MemberUnmuted {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: Option<String>,
+    },
+    SlowMode {
+        room_id: Uuid,
+        seconds: i32,
+        by: String,
+    },
+    RoleChanged {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        role: Role,
+        by: String,
+    },
+    Presence(PresenceInfo),
+    /// Sent to a mentioned user's connections, whichever rooms they're in.
+    Mention(MentionInfo),
+    Typing {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        typing: bool,
+    },
+    /// Sent to all of a user's connections when their read marker moves.
+    ReadMarker {
+        room_id: Uuid,
+        #[serde(flatten)]
+        read: ReadState,
+    },
+    /// A frame was dropped because the client is sending too fast.
+    Throttled {
+        scope: String,
+        retry_after_ms: u64,
+    },
+    CommandOk {
+        command: String,
+        text: Option<String>,
+    },
+    CommandError {
+        command: String,
+        code: String,
+        message: String,
+    },
+    Commands {
+        commands: Vec<CommandInfo>,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+}
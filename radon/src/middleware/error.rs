@@ -1 +1,39 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
 use thiserror::Error;
+
+use crate::ratelimit::error::Throttled;
+
+#[derive(Error, Debug)]
+pub enum MiddlewareError {
+    #[error("Missing bearer token")]
+    MissingToken,
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("This token's scopes don't allow that")]
+    OutOfScope,
+    #[error(transparent)]
+    RateLimited(#[from] Throttled),
+}
+
+impl IntoResponse for MiddlewareError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, error_message) = match self {
+            MiddlewareError::MissingToken => {
+                (StatusCode::UNAUTHORIZED, "Missing bearer token".to_string())
+            }
+            MiddlewareError::InvalidToken => {
+                (StatusCode::UNAUTHORIZED, "Invalid token".to_string())
+            }
+            MiddlewareError::OutOfScope => (
+                StatusCode::FORBIDDEN,
+                "This token's scopes don't allow that".to_string(),
+            ),
+            MiddlewareError::RateLimited(throttled) => return throttled.into_response(),
+        };
+
+        let body = Json(json!({ "error": error_message }));
+
+        (status, body).into_response()
+    }
+}
@@ -1,3 +1,172 @@
 pub mod error;
 
-async fn requires_auth(request: Request, next: Next) -> Response {}
+use std::sync::Arc;
+
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, State},
+    http::{header::AUTHORIZATION, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        auth::utils,
+        tokens::{self, TokenGrant},
+        AppState,
+    },
+    ratelimit::Scope,
+};
+
+use self::error::MiddlewareError;
+
+/// The authenticated caller, inserted into request extensions by [`requires_auth`].
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: Uuid,
+    /// Set when the caller used an API token rather than a session.
+    pub token: Option<Arc<TokenGrant>>,
+}
+
+pub async fn requires_auth<B>(
+    State(state): State<Arc<AppState>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, MiddlewareError> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(MiddlewareError::MissingToken)?;
+
+    let user = authenticate(&state, token).await?;
+    if let Some(grant) = &user.token {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str())
+            .unwrap_or_default();
+        if !grant.allows(request.method(), route, request.uri().path()) {
+            return Err(MiddlewareError::OutOfScope);
+        }
+    }
+    request.extensions_mut().insert(user);
+
+    Ok(next.run(request).await)
+}
+
+/// Validate an access token or API token and return the user it was issued to.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, MiddlewareError> {
+    if token.starts_with(tokens::TOKEN_PREFIX) {
+        let (user_id, grant) = tokens::authenticate(&state.db, token)
+            .await
+            .map_err(|e| {
+                tracing::error!("failed to look up api token: {e}");
+                MiddlewareError::InvalidToken
+            })?
+            .ok_or(MiddlewareError::InvalidToken)?;
+        return Ok(AuthUser {
+            user_id,
+            token: Some(Arc::new(grant)),
+        });
+    }
+    authenticate_session(state, token)
+}
+
+/// Validate a session access token.
+fn authenticate_session(state: &AppState, token: &str) -> Result<AuthUser, MiddlewareError> {
+    let claims =
+        utils::decode_jwt(token, &state.keys).map_err(|_| MiddlewareError::InvalidToken)?;
+
+    if claims.iss != "radon-access" {
+        return Err(MiddlewareError::InvalidToken);
+    }
+
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| MiddlewareError::InvalidToken)?;
+    if state.revocations.is_revoked(user_id, claims.iat) {
+        return Err(MiddlewareError::InvalidToken);
+    }
+
+    Ok(AuthUser {
+        user_id,
+        token: None,
+    })
+}
+
+/// The address a request came from, if the server recorded it.
+pub fn client_ip<B>(request: &Request<B>) -> String {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Take a token for every request. Logins and registrations are counted per
+/// address, webhook posts per hook; everything else per caller (their user if
+/// they sent a valid token, otherwise their address) and route. API tokens are
+/// counted per token and per address, since they're only checked later.
+pub async fn rate_limit<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, MiddlewareError> {
+    let limits = &state.config.rate_limits;
+    let ip = client_ip(&request);
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let method = request.method().clone();
+
+    match (&method, route.as_str()) {
+        (
+            &Method::POST,
+            // Key login challenges are counted per address by their handler.
+            "/login" | "/login/2fa" | "/login/ssh" | "/password_reset",
+        ) => state.limiter.check(Scope::Login, &ip, limits.login)?,
+        (&Method::POST, "/users") => state.limiter.check(Scope::Register, &ip, limits.register)?,
+        (&Method::POST, "/hooks/:hook_id") => {
+            let hook = request.uri().path().to_string();
+            state.limiter.check(Scope::Webhook, &hook, limits.webhook)?
+        }
+        _ => {
+            let token = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|header| header.to_str().ok())
+                .and_then(|header| header.strip_prefix("Bearer "));
+            // API tokens aren't looked up until the handler runs, so made up
+            // ones would each get a bucket of their own. Count them against
+            // the address too.
+            let unchecked = token.is_some_and(|token| token.starts_with(tokens::TOKEN_PREFIX));
+            let caller = token.and_then(|token| caller(&state, token));
+            if unchecked || caller.is_none() {
+                let key = format!("{} {} {}", ip, method, route);
+                state.limiter.check(Scope::Rest, &key, limits.rest)?
+            }
+            if let Some(caller) = caller {
+                let key = format!("{} {} {}", caller, method, route);
+                state.limiter.check(Scope::Rest, &key, limits.rest)?
+            }
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Who a bearer token belongs to, for rate limiting, without touching the
+/// database: the user for session tokens, a hash of API tokens.
+fn caller(state: &AppState, token: &str) -> Option<String> {
+    if token.starts_with(tokens::TOKEN_PREFIX) {
+        return Some(tokens::limiter_key(token));
+    }
+    authenticate_session(state, token)
+        .ok()
+        .map(|user| user.user_id.to_string())
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use axum::{
+    http::{header::RETRY_AFTER, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use thiserror::Error;
+
+/// A request turned away because its bucket is empty.
+#[derive(Error, Debug, Clone, Copy)]
+#[error("Too many requests, retry in {}s", retry_secs(.retry_after))]
+pub struct Throttled {
+    pub retry_after: Duration,
+}
+
+/// Whole seconds to wait, rounded up so clients don't come back too early.
+fn retry_secs(retry_after: &Duration) -> u64 {
+    let secs = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 {
+        secs + 1
+    } else {
+        secs.max(1)
+    }
+}
+
+impl Throttled {
+    pub fn retry_secs(&self) -> u64 {
+        retry_secs(&self.retry_after)
+    }
+}
+
+impl IntoResponse for Throttled {
+    fn into_response(self) -> axum::response::Response {
+        let body = Json(json!({ "error": self.to_string() }));
+
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, self.retry_secs().to_string())],
+            body,
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,222 @@
+pub mod error;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::config::RateLimit;
+
+use self::error::Throttled;
+
+/// What a bucket is counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Login attempts from one address.
+    Login,
+    /// Login attempts against one username, from anywhere.
+    LoginUser,
+    Register,
+    /// REST calls to one route by one caller.
+    Rest,
+    /// Frames on one websocket.
+    WsConnection,
+    /// Frames across all of a user's websockets.
+    WsUser,
+    /// Posts to one incoming webhook.
+    Webhook,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Login => "login",
+            Scope::LoginUser => "login_user",
+            Scope::Register => "register",
+            Scope::Rest => "rest",
+            Scope::WsConnection => "ws_connection",
+            Scope::WsUser => "ws_user",
+            Scope::Webhook => "webhook",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets, one per scope and key. A bucket holds up to `burst`
+/// tokens and refills at `per_minute`; every request takes one. Nothing is
+/// persisted, so limits reset when radon restarts.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(Scope, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a token from the bucket for `key`, or say how long until one is
+    /// available.
+    pub fn check(&self, scope: Scope, key: &str, limit: RateLimit) -> Result<(), Throttled> {
+        self.check_at(scope, key, limit, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        scope: Scope,
+        key: &str,
+        limit: RateLimit,
+        now: Instant,
+    ) -> Result<(), Throttled> {
+        if limit.per_minute == 0 {
+            return Ok(());
+        }
+        let burst = f64::from(limit.burst.max(1));
+        let per_sec = f64::from(limit.per_minute) / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((scope, key.to_string())).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Throttled {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec),
+            })
+        }
+    }
+
+    /// Forget buckets nobody has touched in `idle`, which should be long
+    /// enough for any of them to have refilled.
+    pub fn sweep(&self, idle: Duration) {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| now.duration_since(bucket.updated) < idle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 3,
+        per_minute: 60,
+    };
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn burst_then_throttled() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at(Scope::Rest, "a", LIMIT, now).is_ok());
+        }
+        let throttled = limiter.check_at(Scope::Rest, "a", LIMIT, now).unwrap_err();
+        assert_eq!(throttled.retry_after, secs(1.0));
+
+        // Other keys and scopes have buckets of their own.
+        assert!(limiter.check_at(Scope::Rest, "b", LIMIT, now).is_ok());
+        assert!(limiter.check_at(Scope::Login, "a", LIMIT, now).is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at(Scope::Rest, "a", LIMIT, start).unwrap();
+        }
+
+        let throttled = limiter
+            .check_at(Scope::Rest, "a", LIMIT, start + secs(0.25))
+            .unwrap_err();
+        assert!((throttled.retry_after.as_secs_f64() - 0.75).abs() < 1e-6);
+        assert!(limiter
+            .check_at(Scope::Rest, "a", LIMIT, start + secs(1.0))
+            .is_ok());
+        assert!(limiter
+            .check_at(Scope::Rest, "a", LIMIT, start + secs(1.0))
+            .is_err());
+    }
+
+    #[test]
+    fn refills_no_further_than_burst() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        limiter.check_at(Scope::Rest, "a", LIMIT, start).unwrap();
+
+        let later = start + secs(60.0 * 60.0);
+        for _ in 0..3 {
+            assert!(limiter.check_at(Scope::Rest, "a", LIMIT, later).is_ok());
+        }
+        assert!(limiter.check_at(Scope::Rest, "a", LIMIT, later).is_err());
+    }
+
+    #[test]
+    fn zero_per_minute_is_unlimited() {
+        let limiter = RateLimiter::new();
+        let unlimited = RateLimit {
+            burst: 0,
+            per_minute: 0,
+        };
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.check_at(Scope::Rest, "a", unlimited, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn zero_burst_still_allows_one() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit {
+            burst: 0,
+            per_minute: 60,
+        };
+        let now = Instant::now();
+        assert!(limiter.check_at(Scope::Rest, "a", limit, now).is_ok());
+        assert!(limiter.check_at(Scope::Rest, "a", limit, now).is_err());
+    }
+
+    #[test]
+    fn retry_after_rounds_up() {
+        let retry_secs = |retry_after| Throttled { retry_after }.retry_secs();
+        assert_eq!(retry_secs(Duration::ZERO), 1);
+        assert_eq!(retry_secs(Duration::from_nanos(1)), 1);
+        assert_eq!(retry_secs(secs(0.5)), 1);
+        assert_eq!(retry_secs(secs(1.0)), 1);
+        assert_eq!(retry_secs(secs(1.001)), 2);
+        assert_eq!(retry_secs(secs(30.0)), 30);
+    }
+
+    #[test]
+    fn sweep_forgets_idle_buckets() {
+        let limiter = RateLimiter::new();
+        limiter.check(Scope::Rest, "a", LIMIT).unwrap();
+        limiter.sweep(Duration::from_secs(60));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        limiter.sweep(Duration::ZERO);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    multipart::{Form, Part},
+    Body, Client, RequestBuilder, Response,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::message::{
+    Bookmark, ChatMessage, Member, MentionInfo, Pin, Room, SearchPage, Thread, Upload,
+};
+use crate::ssh;
+
+#[derive(Debug, Serialize)]
+struct LoginRequest<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct MfaLoginRequest<'a> {
+    challenge_token: &'a str,
+    code: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaChallenge {
+    challenge_token: String,
+}
+
+/// `/login` answers with a session, or a challenge if the account has
+/// two-factor authentication on.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LoginOutcome {
+    Session(LoginResponse),
+    Challenge(MfaChallenge),
+}
+
+#[derive(Debug, Serialize)]
+struct SshChallengeRequest<'a> {
+    username: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct SshChallenge {
+    challenge_id: Uuid,
+    nonce: String,
+    namespace: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SshLoginRequest<'a> {
+    challenge_id: Uuid,
+    public_key: &'a str,
+    signature: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct AddSshKeyRequest<'a> {
+    public_key: &'a str,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SshKey {
+    pub key_id: Uuid,
+    pub fingerprint: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TwoFactorCode<'a> {
+    code: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct DisableTwoFactorRequest<'a> {
+    password: &'a str,
+    code: &'a str,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TwoFactorConfirmed {
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoginResponse {
+    pub id: Uuid,
+    pub username: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Failed attempts at our account since we last logged in.
+    #[serde(default)]
+    pub failed_attempts: i64,
+}
+
+/// A logged in REST client for radon.
+#[derive(Debug, Clone)]
+pub struct Api {
+    http: Client,
+    base_url: String,
+    pub session: LoginResponse,
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("radon returned {}: {}", status, body));
+    }
+    response.json().await.context("failed to decode response")
+}
+
+fn http_client() -> Result<Client> {
+    Client::builder()
+        .user_agent(concat!("xenon/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .context("failed to build http client")
+}
+
+/// Turn a non-success status into an error, for calls with no body.
+async fn ok(response: Response) -> Result<()> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("radon returned {}: {}", status, body));
+    }
+    Ok(())
+}
+
+impl Api {
+    /// Log in, asking `code` for a second factor if the account needs one.
+    pub async fn login(
+        base_url: &str,
+        username: &str,
+        password: &str,
+        code: impl FnOnce() -> Result<String>,
+    ) -> Result<Self> {
+        let http = http_client()?;
+        let response = http
+            .post(format!("{}/login", base_url))
+            .json(&LoginRequest { username, password })
+            .send()
+            .await
+            .context("failed to reach radon")?;
+        let session = match json(response).await.context("login failed")? {
+            LoginOutcome::Session(session) => session,
+            LoginOutcome::Challenge(challenge) => {
+                let code = code()?;
+                let response = http
+                    .post(format!("{}/login/2fa", base_url))
+                    .json(&MfaLoginRequest {
+                        challenge_token: &challenge.challenge_token,
+                        code: code.trim(),
+                    })
+                    .send()
+                    .await
+                    .context("failed to reach radon")?;
+                json(response).await.context("login failed")?
+            }
+        };
+
+        Ok(Self {
+            http,
+            base_url: base_url.to_string(),
+            session,
+        })
+    }
+
+    /// Log in by signing a challenge with an SSH key registered on the
+    /// account. See [`ssh::sign`] for which key is used.
+    pub async fn login_ssh(
+        base_url: &str,
+        username: &str,
+        key_path: Option<&Path>,
+    ) -> Result<Self> {
+        let http = http_client()?;
+        let response = http
+            .post(format!("{}/login/ssh/challenge", base_url))
+            .json(&SshChallengeRequest { username })
+            .send()
+            .await
+            .context("failed to reach radon")?;
+        let challenge: SshChallenge = json(response).await.context("login failed")?;
+
+        let (public_key, signature) =
+            ssh::sign(&challenge.namespace, challenge.nonce.as_bytes(), key_path).await?;
+        let response = http
+            .post(format!("{}/login/ssh", base_url))
+            .json(&SshLoginRequest {
+                challenge_id: challenge.challenge_id,
+                public_key: &public_key,
+                signature: &signature,
+            })
+            .send()
+            .await
+            .context("failed to reach radon")?;
+        let session = json(response).await.context("login failed")?;
+
+        Ok(Self {
+            http,
+            base_url: base_url.to_string(),
+            session,
+        })
+    }
+
+    pub fn token(&self) -> &str {
+        &self.session.access_token
+    }
+
+    /// The `/ws` url for this server, with our token attached.
+    pub fn websocket_url(&self) -> String {
+        let base = self
+            .base_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        format!("{}/ws?token={}", base, self.token())
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.http
+            .get(format!("{}{}", self.base_url, path))
+            .bearer_auth(self.token())
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.http
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(self.token())
+    }
+
+    pub async fn rooms(&self) -> Result<Vec<Room>> {
+        json(self.get("/rooms").send().await?).await
+    }
+
+    pub async fn members(&self, room_id: Uuid) -> Result<Vec<Member>> {
+        json(
+            self.get(&format!("/rooms/{}/members", room_id))
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn history(&self, room_id: Uuid) -> Result<Vec<ChatMessage>> {
+        json(
+            self.get(&format!("/rooms/{}/messages", room_id))
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn pins(&self, room_id: Uuid) -> Result<Vec<Pin>> {
+        json(self.get(&format!("/rooms/{}/pins", room_id)).send().await?).await
+    }
+
+    /// Our saved messages, most recently saved first.
+    pub async fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        json(self.get("/me/bookmarks").send().await?).await
+    }
+
+    /// Up to `limit` messages before `seq`, to show a message in context.
+    pub async fn history_before(
+        &self,
+        room_id: Uuid,
+        seq: i64,
+        limit: i64,
+    ) -> Result<Vec<ChatMessage>> {
+        json(
+            self.get(&format!("/rooms/{}/messages", room_id))
+                .query(&[("before", seq), ("limit", limit)])
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn search(&self, q: &str, cursor: Option<i64>) -> Result<SearchPage> {
+        let mut request = self.get("/search").query(&[("q", q)]);
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        json(request.send().await?).await
+    }
+
+    pub async fn thread(&self, message_id: i64) -> Result<Thread> {
+        json(
+            self.get(&format!("/messages/{}/thread", message_id))
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Our most recent mentions, newest first.
+    pub async fn mentions(&self) -> Result<Vec<MentionInfo>> {
+        json(self.get("/me/mentions").send().await?).await
+    }
+
+    /// Send a file to radon, ready to attach to a message.
+    pub async fn upload(&self, path: &Path) -> Result<Upload> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?
+            .to_string();
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let length = file.metadata().await?.len();
+        let part = Part::stream_with_length(Body::from(file), length).file_name(filename);
+
+        json(
+            self.http
+                .post(format!("{}/uploads", self.base_url))
+                .bearer_auth(self.token())
+                .multipart(Form::new().part("file", part))
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Start turning on two-factor authentication. It only takes effect
+    /// once a code from the new secret is confirmed.
+    pub async fn setup_two_factor(&self) -> Result<TwoFactorSetup> {
+        json(self.post("/me/2fa/setup").send().await?).await
+    }
+
+    /// Turn on two-factor authentication, returning our recovery codes.
+    pub async fn confirm_two_factor(&self, code: &str) -> Result<Vec<String>> {
+        let confirmed: TwoFactorConfirmed = json(
+            self.post("/me/2fa/confirm")
+                .json(&TwoFactorCode { code })
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(confirmed.recovery_codes)
+    }
+
+    pub async fn disable_two_factor(&self, password: &str, code: &str) -> Result<()> {
+        ok(self
+            .post("/me/2fa/disable")
+            .json(&DisableTwoFactorRequest { password, code })
+            .send()
+            .await?)
+        .await
+    }
+
+    /// Register a public key, in OpenSSH format, to log in with.
+    pub async fn add_ssh_key(&self, public_key: &str) -> Result<SshKey> {
+        json(
+            self.post("/me/ssh_keys")
+                .json(&AddSshKeyRequest { public_key })
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Fetch an attachment's contents.
+    pub async fn download(&self, upload_id: Uuid) -> Result<Vec<u8>> {
+        let response = self.get(&format!("/uploads/{}", upload_id)).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("radon returned {}: {}", status, body));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
@@ -1,57 +1,892 @@
-use anyhow::Result;
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use client::message::{
+    Bookmark, ChatMessage, ClientFrame, Member, MentionInfo, MessageType, Pin, Presence, Reaction,
+    ReplyPreview, Role, Room, SearchHit, SearchPage, ServerEvent, Thread, ThreadSummary,
+};
+use uuid::Uuid;
+
+/// Drop someone from the typing line if radon hasn't refreshed them in this
+/// long, in case we missed the stop.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(8);
+/// How often we tell radon we're still typing.
+pub const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+
+/// What the reaction picker offers, as shortcode and emoji. radon stores
+/// these shortcodes as the emoji, so either spelling lands on the same chip.
+pub const REACTIONS: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("tada", "🎉"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("thinking", "🤔"),
+    ("pray", "🙏"),
+    ("white_check_mark", "✅"),
+];
 
 pub enum CurrentScreen {
     Main,
-    Editing,
+    /// The reaction picker, with the highlighted entry of `REACTIONS`.
+    Reacting(usize),
+    /// The mentions list, with the highlighted entry.
+    Mentions(usize),
+    Search,
+    /// Saved messages, with the highlighted entry.
+    Bookmarks(usize),
+    /// Two-factor setup details or recovery codes, from `app.two_factor`.
+    TwoFactor,
     Exiting,
 }
 
-pub enum CurrentlyEditing {
-    Key,
-    Value,
+/// Everything we know about one room.
+pub struct RoomView {
+    pub room: Room,
+    pub messages: Vec<ChatMessage>,
+    pub members: Vec<Member>,
+    /// Most recently pinned first.
+    pub pins: Vec<Pin>,
+    /// Other users typing here, with when we last heard about it.
+    pub typing: Vec<(Uuid, String, Instant)>,
+    /// Where the "new messages" line goes: the read marker as it was when we
+    /// opened the room.
+    pub read_marker: Option<i64>,
+    /// Whether history and members have been fetched yet.
+    pub loaded: bool,
+    /// The message picked out with Alt-Up/Alt-Down, as an index into
+    /// `messages`. `None` follows the latest message.
+    pub cursor: Option<usize>,
+    /// Whether `messages` is a window around a search result rather than the
+    /// latest history. New messages aren't appended while it is.
+    pub context: bool,
+}
+
+impl RoomView {
+    pub fn new(room: Room) -> Self {
+        Self {
+            room,
+            messages: Vec::new(),
+            members: Vec::new(),
+            pins: Vec::new(),
+            typing: Vec::new(),
+            read_marker: None,
+            loaded: false,
+            cursor: None,
+            context: false,
+        }
+    }
+
+    /// The message under the cursor, or the latest one.
+    pub fn cursor_message(&self) -> Option<&ChatMessage> {
+        match self.cursor {
+            Some(i) => self.messages.get(i),
+            None => self.messages.last(),
+        }
+    }
+
+    pub fn cursor_up(&mut self) {
+        let last = match self.messages.len() {
+            0 => return,
+            len => len - 1,
+        };
+        self.cursor = Some(match self.cursor {
+            Some(i) => i.saturating_sub(1),
+            None => last,
+        });
+    }
+
+    pub fn cursor_down(&mut self) {
+        self.cursor = match self.cursor {
+            Some(i) if i + 1 < self.messages.len() => Some(i + 1),
+            _ => None,
+        };
+    }
+
+    pub fn set_members(&mut self, mut members: Vec<Member>) {
+        sort_members(&mut members);
+        self.members = members;
+    }
+
+    /// "alice is typing…" for the status line, if anyone is.
+    pub fn typing_line(&self) -> Option<String> {
+        let names = self
+            .typing
+            .iter()
+            .map(|(_, name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+        match names.as_slice() {
+            [] => None,
+            [one] => Some(format!("{} is typing…", one)),
+            [one, two] => Some(format!("{} and {} are typing…", one, two)),
+            _ => Some("several people are typing…".to_string()),
+        }
+    }
+}
+
+/// The search screen: the query being typed and the results of the last run.
+#[derive(Default)]
+pub struct SearchView {
+    pub query: String,
+    /// The query `results` are for.
+    pub ran: Option<String>,
+    pub results: Vec<SearchHit>,
+    pub selected: usize,
+    pub next_cursor: Option<i64>,
+}
+
+impl SearchView {
+    /// Whether Enter should run the query rather than open a result.
+    pub fn is_stale(&self) -> bool {
+        self.ran.as_deref() != Some(self.query.trim())
+    }
+
+    pub fn set_results(&mut self, page: SearchPage) {
+        self.ran = Some(self.query.trim().to_string());
+        self.results = page.results;
+        self.next_cursor = page.next_cursor;
+        self.selected = 0;
+    }
+
+    pub fn append_results(&mut self, page: SearchPage) {
+        self.results.extend(page.results);
+        self.next_cursor = page.next_cursor;
+    }
+}
+
+/// Online first, then away, then offline, alphabetical within each.
+fn sort_members(members: &mut [Member]) {
+    members.sort_by(|a, b| {
+        presence_rank(a.presence)
+            .cmp(&presence_rank(b.presence))
+            .then_with(|| b.role.cmp(&a.role))
+            .then_with(|| a.username.cmp(&b.username))
+    });
+}
+
+fn presence_rank(presence: Presence) -> u8 {
+    match presence {
+        Presence::Online => 0,
+        Presence::Away => 1,
+        Presence::Offline => 2,
+    }
+}
+
+/// Add or take away one user's reaction on a message.
+fn apply_reaction(message: &mut ChatMessage, emoji: &str, user_id: Uuid, added: bool) {
+    let existing = message
+        .reactions
+        .iter()
+        .position(|reaction| reaction.emoji == emoji);
+    match (existing, added) {
+        (Some(i), true) => {
+            let reaction = &mut message.reactions[i];
+            if !reaction.user_ids.contains(&user_id) {
+                reaction.user_ids.push(user_id);
+                reaction.count += 1;
+            }
+        }
+        (None, true) => message.reactions.push(Reaction {
+            emoji: emoji.to_string(),
+            count: 1,
+            user_ids: vec![user_id],
+        }),
+        (Some(i), false) => {
+            let reaction = &mut message.reactions[i];
+            if let Some(pos) = reaction.user_ids.iter().position(|id| *id == user_id) {
+                reaction.user_ids.remove(pos);
+                reaction.count -= 1;
+            }
+            if reaction.count <= 0 {
+                message.reactions.remove(i);
+            }
+        }
+        (None, false) => {}
+    }
+}
+
+/// Count a new reply on its thread root.
+fn until(expires_at: Option<NaiveDateTime>) -> String {
+    match expires_at {
+        Some(expires_at) => format!(" until {}", expires_at.format("%F %R")),
+        None => String::new(),
+    }
+}
+
+fn bump_thread(root: &mut ChatMessage, reply: &ChatMessage) {
+    let reply_count = root.thread.as_ref().map_or(0, |thread| thread.reply_count) + 1;
+    root.thread = Some(ThreadSummary {
+        reply_count,
+        last_reply_at: reply.created_at,
+        last_reply_from: reply.from.clone(),
+    });
 }
 
 pub struct App {
-    pub key_input: String,
-    pub value_input: String,
-    pub pairs: HashMap<String, String>,
+    pub user_id: Uuid,
+    pub username: String,
+    pub rooms: Vec<RoomView>,
+    pub selected: usize,
+    pub input: String,
+    pub status: Option<String>,
+    /// The message being edited, if the input box holds an edit.
+    pub editing: Option<i64>,
+    /// The message the next send replies to.
+    pub replying: Option<ReplyPreview>,
+    /// The thread open in the side pane.
+    pub thread: Option<Thread>,
+    pub search: SearchView,
+    /// Messages that pinged us, newest first.
+    pub mentions: Vec<MentionInfo>,
+    /// Our saved messages, most recently saved first.
+    pub bookmarks: Vec<Bookmark>,
+    /// What the two-factor popup shows.
+    pub two_factor: Vec<String>,
+    /// Set when the terminal bell should ring; the main loop rings it.
+    pub bell: bool,
+    /// When we last told radon we were typing, if we are.
+    pub typing_sent: Option<Instant>,
+    /// Whether the terminal has focus, i.e. whether what's on screen is read.
+    pub focused: bool,
     pub current_screen: CurrentScreen,
-    pub currently_editing: Option<CurrentlyEditing>,
 }
+
 impl App {
-    pub fn new() -> App {
+    pub fn new(user_id: Uuid, username: String, rooms: Vec<Room>) -> App {
         App {
-            key_input: String::new(),
-            value_input: String::new(),
-            pairs: HashMap::new(),
+            user_id,
+            username,
+            rooms: rooms.into_iter().map(RoomView::new).collect(),
+            selected: 0,
+            input: String::new(),
+            status: None,
+            editing: None,
+            replying: None,
+            thread: None,
+            search: SearchView::default(),
+            mentions: Vec::new(),
+            bookmarks: Vec::new(),
+            two_factor: Vec::new(),
+            bell: false,
+            typing_sent: None,
+            focused: true,
             current_screen: CurrentScreen::Main,
-            currently_editing: None,
         }
     }
 
-    pub fn save_key_value(&mut self) {
-        self.pairs
-            .insert(self.key_input.clone(), self.value_input.clone());
-        self.key_input = String::new();
-        self.value_input = String::new();
-        self.currently_editing = None;
+    pub fn current_room(&self) -> Option<&RoomView> {
+        self.rooms.get(self.selected)
     }
 
-    pub fn toggle_editing(&mut self) {
-        if let Some(edit_mode) = &self.currently_editing {
-            match edit_mode {
-                CurrentlyEditing::Key => self.currently_editing = Some(CurrentlyEditing::Value),
-                CurrentlyEditing::Value => self.currently_editing = Some(CurrentlyEditing::Key),
+    pub fn current_room_mut(&mut self) -> Option<&mut RoomView> {
+        self.rooms.get_mut(self.selected)
+    }
+
+    pub fn room_mut(&mut self, room_id: Uuid) -> Option<&mut RoomView> {
+        self.rooms
+            .iter_mut()
+            .find(|view| view.room.room_id == room_id)
+    }
+
+    pub fn member_mut(&mut self, room_id: Uuid, user_id: Uuid) -> Option<&mut Member> {
+        self.room_mut(room_id)?
+            .members
+            .iter_mut()
+            .find(|member| member.user_id == user_id)
+    }
+
+    /// Drop a room we were removed from.
+    fn remove_room(&mut self, room_id: Uuid) {
+        self.rooms.retain(|view| view.room.room_id != room_id);
+        if self
+            .thread
+            .as_ref()
+            .is_some_and(|thread| thread.root.room_id == room_id)
+        {
+            self.thread = None;
+        }
+        self.selected = self.selected.min(self.rooms.len().saturating_sub(1));
+    }
+
+    pub fn message_mut(&mut self, room_id: Uuid, message_id: i64) -> Option<&mut ChatMessage> {
+        self.room_mut(room_id)?
+            .messages
+            .iter_mut()
+            .find(|message| message.message_id == message_id)
+    }
+
+    /// A message in the open thread pane.
+    fn thread_message_mut(&mut self, message_id: i64) -> Option<&mut ChatMessage> {
+        let thread = self.thread.as_mut()?;
+        std::iter::once(&mut thread.root)
+            .chain(thread.replies.iter_mut())
+            .find(|message| message.message_id == message_id)
+    }
+
+    pub fn next_room(&mut self) {
+        if !self.rooms.is_empty() {
+            self.selected = (self.selected + 1) % self.rooms.len();
+        }
+    }
+
+    pub fn previous_room(&mut self) {
+        if !self.rooms.is_empty() {
+            self.selected = (self.selected + self.rooms.len() - 1) % self.rooms.len();
+        }
+    }
+
+    /// Put the "new messages" line where our read marker is now, if there's
+    /// anything unread. Called when a room comes on screen.
+    pub fn place_read_marker(&mut self) {
+        if let Some(view) = self.current_room_mut() {
+            view.read_marker = if view.room.unread > 0 {
+                Some(view.room.last_read_seq)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// If the current room has messages past our read marker and we can see
+    /// them, move the marker and return the seq to report to radon.
+    pub fn read_current(&mut self) -> Option<(Uuid, i64)> {
+        if !self.focused {
+            return None;
+        }
+        let view = self.current_room_mut()?;
+        let seq = view.messages.last()?.seq;
+        if seq <= view.room.last_read_seq {
+            return None;
+        }
+        view.room.last_read_seq = seq;
+        view.room.unread = 0;
+        view.room.mentions = 0;
+        Some((view.room.room_id, seq))
+    }
+
+    /// Whether a message pinged us, for highlighting. radon tells us about
+    /// new mentions; older ones we can only guess at from the text.
+    pub fn mentions_me(&self, message: &ChatMessage) -> bool {
+        if message.user_id == Some(self.user_id) {
+            return false;
+        }
+        if self
+            .mentions
+            .iter()
+            .any(|mention| mention.message.message_id == message.message_id)
+        {
+            return true;
+        }
+        let text = message.text.to_lowercase();
+        text.contains(&format!("@{}", self.username.to_lowercase())) || text.contains("@room")
+    }
+
+    /// Switch to a room. Returns false if we aren't in it.
+    pub fn select_room(&mut self, room_id: Uuid) -> bool {
+        match self
+            .rooms
+            .iter()
+            .position(|view| view.room.room_id == room_id)
+        {
+            Some(i) => {
+                self.selected = i;
+                true
             }
+            None => false,
+        }
+    }
+
+    /// Show `messages`, fetched around `message_id`, in place of the current
+    /// room's history with the cursor on that message.
+    pub fn show_context(&mut self, message_id: i64, messages: Vec<ChatMessage>) {
+        let Some(view) = self.current_room_mut() else {
+            return;
+        };
+        view.cursor = messages
+            .iter()
+            .position(|message| message.message_id == message_id);
+        view.messages = messages;
+        view.context = true;
+        view.read_marker = None;
+    }
+
+    /// Switch to the room of the mention at `index` in the list.
+    pub fn jump_to_mention(&mut self, index: usize) {
+        if let Some(room_id) = self
+            .mentions
+            .get(index)
+            .map(|mention| mention.message.room_id)
+        {
+            self.select_room(room_id);
+        }
+    }
+
+    /// Take whatever is in the input box, leaving it empty.
+    pub fn take_input(&mut self) -> String {
+        std::mem::take(&mut self.input)
+    }
+
+    /// Load our latest message in the current room into the input box to edit.
+    pub fn edit_last(&mut self) {
+        let me = self.user_id;
+        let last = self.current_room().and_then(|view| {
+            view.messages
+                .iter()
+                .rev()
+                .find(|message| message.user_id == Some(me) && !message.deleted)
+                .filter(|message| matches!(message.kind, MessageType::Text | MessageType::Action))
+                .map(|message| (message.message_id, message.text.clone()))
+        });
+        if let Some((message_id, text)) = last {
+            self.editing = Some(message_id);
+            self.input = text;
+        }
+    }
+
+    /// Reply to the message under the cursor.
+    pub fn reply_to_cursor(&mut self) {
+        let preview = self
+            .current_room()
+            .and_then(|view| view.cursor_message())
+            .filter(|message| !message.deleted)
+            .map(|message| ReplyPreview {
+                message_id: message.parent_id.unwrap_or(message.message_id),
+                from: message.from.clone(),
+                text: message.text.lines().next().unwrap_or_default().to_string(),
+                deleted: false,
+            });
+        if preview.is_some() {
+            self.cancel_edit();
+            self.replying = preview;
+        }
+    }
+
+    /// The thread a message typed now goes to: an explicit reply, else the
+    /// thread open in the side pane.
+    pub fn send_parent(&self) -> Option<i64> {
+        self.replying
+            .as_ref()
+            .map(|reply| reply.message_id)
+            .or_else(|| self.thread.as_ref().map(|thread| thread.root.message_id))
+    }
+
+    /// The message id to open a thread for: whatever's under the cursor.
+    pub fn thread_to_open(&self) -> Option<i64> {
+        self.current_room()
+            .and_then(|view| view.cursor_message())
+            .map(|message| message.parent_id.unwrap_or(message.message_id))
+    }
+
+    /// React with `emoji` to the message under the cursor, or take our
+    /// reaction back if it's already there.
+    pub fn toggle_reaction(&self, emoji: &str) -> Option<ClientFrame> {
+        let message = self.current_room()?.cursor_message()?;
+        if message.deleted {
+            return None;
+        }
+        let reacted = message
+            .reactions
+            .iter()
+            .any(|reaction| reaction.emoji == emoji && reaction.user_ids.contains(&self.user_id));
+        let message_id = message.message_id;
+        let emoji = emoji.to_string();
+        Some(if reacted {
+            ClientFrame::Unreact { message_id, emoji }
         } else {
-            self.currently_editing = Some(CurrentlyEditing::Key)
+            ClientFrame::React { message_id, emoji }
+        })
+    }
+
+    /// Pin the message under the cursor, or unpin it if it already is.
+    /// radon decides whether we're allowed to.
+    pub fn toggle_pin(&self) -> Option<ClientFrame> {
+        let view = self.current_room()?;
+        let message_id = view.cursor_message()?.message_id;
+        let pinned = view
+            .pins
+            .iter()
+            .any(|pin| pin.message.message_id == message_id);
+        Some(if pinned {
+            ClientFrame::Unpin { message_id }
+        } else {
+            ClientFrame::Pin { message_id }
+        })
+    }
+
+    /// Save the message under the cursor, or forget it if it's saved.
+    pub fn toggle_bookmark(&self) -> Option<ClientFrame> {
+        let message_id = self.current_room()?.cursor_message()?.message_id;
+        let saved = self
+            .bookmarks
+            .iter()
+            .any(|bookmark| bookmark.message.message_id == message_id);
+        Some(if saved {
+            ClientFrame::Unbookmark { message_id }
+        } else {
+            ClientFrame::Bookmark { message_id }
+        })
+    }
+
+    /// The emoji for input that is nothing but a known `:shortcode:`.
+    pub fn shortcode_reaction(input: &str) -> Option<&'static str> {
+        let code = input.trim().strip_prefix(':')?.strip_suffix(':')?;
+        REACTIONS
+            .iter()
+            .find(|(name, _)| *name == code)
+            .map(|(_, emoji)| *emoji)
+    }
+
+    /// Drop an edit in progress.
+    pub fn cancel_edit(&mut self) {
+        if self.editing.take().is_some() {
+            self.input.clear();
+        }
+    }
+
+    /// Whether a typing-start should go out now. Keeps us to one every
+    /// `TYPING_THROTTLE` while the user keeps typing.
+    pub fn should_send_typing(&mut self) -> bool {
+        let now = Instant::now();
+        match self.typing_sent {
+            Some(sent) if now.duration_since(sent) < TYPING_THROTTLE => false,
+            _ => {
+                self.typing_sent = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Forget typers we haven't heard from in a while.
+    pub fn expire_typing(&mut self) {
+        let now = Instant::now();
+        for view in self.rooms.iter_mut() {
+            view.typing
+                .retain(|(_, _, seen)| now.duration_since(*seen) < TYPING_TIMEOUT);
         }
     }
 
-    pub fn print_json(&self) -> Result<()> {
-        let json = serde_json::to_string_pretty(&self.pairs).unwrap();
-        println!("{}", json);
-        Ok(())
+    pub fn apply(&mut self, event: ServerEvent) {
+        match event {
+            ServerEvent::Message(message) => {
+                let mine = message.user_id == Some(self.user_id);
+                if let Some(view) = self.room_mut(message.room_id) {
+                    if let Some(user_id) = message.user_id {
+                        view.typing.retain(|(typer, _, _)| *typer != user_id);
+                    }
+                    if !mine && message.seq > view.room.last_read_seq {
+                        view.room.unread += 1;
+                    }
+                    if let Some(parent_id) = message.parent_id {
+                        if let Some(parent) = view
+                            .messages
+                            .iter_mut()
+                            .find(|parent| parent.message_id == parent_id)
+                        {
+                            bump_thread(parent, &message);
+                        }
+                    }
+                    if !view.context {
+                        view.messages.push(message.clone());
+                    }
+                }
+                if let Some(thread) = self.thread.as_mut() {
+                    if message.parent_id == Some(thread.root.message_id) {
+                        bump_thread(&mut thread.root, &message);
+                        thread.replies.push(message);
+                    }
+                }
+            }
+            ServerEvent::MessageEdited {
+                room_id,
+                message_id,
+                text,
+                edited_at,
+            } => {
+                if let Some(message) = self.thread_message_mut(message_id) {
+                    message.text = text.clone();
+                    message.edited_at = Some(edited_at);
+                }
+                if let Some(message) = self.message_mut(room_id, message_id) {
+                    message.text = text;
+                    message.edited_at = Some(edited_at);
+                }
+            }
+            ServerEvent::MessageDeleted {
+                room_id,
+                message_id,
+                ..
+            } => {
+                if let Some(message) = self.thread_message_mut(message_id) {
+                    message.text.clear();
+                    message.deleted = true;
+                }
+                if let Some(message) = self.message_mut(room_id, message_id) {
+                    message.text.clear();
+                    message.deleted = true;
+                }
+                if let Some(view) = self.room_mut(room_id) {
+                    view.pins.retain(|pin| pin.message.message_id != message_id);
+                }
+                self.bookmarks
+                    .retain(|bookmark| bookmark.message.message_id != message_id);
+                if self.editing == Some(message_id) {
+                    self.cancel_edit();
+                }
+            }
+            ServerEvent::ReactionAdded {
+                room_id,
+                message_id,
+                emoji,
+                user_id,
+                ..
+            } => {
+                if let Some(message) = self.thread_message_mut(message_id) {
+                    apply_reaction(message, &emoji, user_id, true);
+                }
+                if let Some(message) = self.message_mut(room_id, message_id) {
+                    apply_reaction(message, &emoji, user_id, true);
+                }
+            }
+            ServerEvent::ReactionRemoved {
+                room_id,
+                message_id,
+                emoji,
+                user_id,
+                ..
+            } => {
+                if let Some(message) = self.thread_message_mut(message_id) {
+                    apply_reaction(message, &emoji, user_id, false);
+                }
+                if let Some(message) = self.message_mut(room_id, message_id) {
+                    apply_reaction(message, &emoji, user_id, false);
+                }
+            }
+            ServerEvent::MessagePinned(pin) => {
+                if let Some(view) = self.room_mut(pin.message.room_id) {
+                    view.pins
+                        .retain(|pinned| pinned.message.message_id != pin.message.message_id);
+                    view.pins.insert(0, pin);
+                }
+            }
+            ServerEvent::MessageUnpinned {
+                room_id,
+                message_id,
+                ..
+            } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    view.pins.retain(|pin| pin.message.message_id != message_id);
+                }
+            }
+            ServerEvent::BookmarkAdded(bookmark) => {
+                self.bookmarks
+                    .retain(|saved| saved.message.message_id != bookmark.message.message_id);
+                self.bookmarks.insert(0, bookmark);
+            }
+            ServerEvent::BookmarkRemoved { message_id } => {
+                self.bookmarks
+                    .retain(|bookmark| bookmark.message.message_id != message_id);
+            }
+            ServerEvent::Topic { room_id, topic, .. } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    view.room.topic = topic;
+                }
+            }
+            ServerEvent::MemberJoined {
+                room_id,
+                user_id,
+                username,
+            } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    if !view.members.iter().any(|member| member.user_id == user_id) {
+                        view.members.push(Member {
+                            user_id,
+                            username,
+                            role: Role::Member,
+                            muted: false,
+                            muted_until: None,
+                            presence: Presence::Online,
+                            last_seen: None,
+                        });
+                        sort_members(&mut view.members);
+                    }
+                }
+            }
+            ServerEvent::MemberKicked {
+                room_id,
+                user_id,
+                username,
+                by,
+                ..
+            } => {
+                if user_id == self.user_id {
+                    self.remove_room(room_id);
+                    self.status = Some(format!("You were kicked by {}", by));
+                } else if let Some(view) = self.room_mut(room_id) {
+                    view.members.retain(|member| member.user_id != user_id);
+                    self.status = Some(format!("{} was kicked by {}", username, by));
+                }
+            }
+            ServerEvent::MemberBanned {
+                room_id,
+                user_id,
+                username,
+                by,
+                expires_at,
+                ..
+            } => {
+                let until = until(expires_at);
+                if user_id == self.user_id {
+                    self.remove_room(room_id);
+                    self.status = Some(format!("You were banned by {}{}", by, until));
+                } else if let Some(view) = self.room_mut(room_id) {
+                    view.members.retain(|member| member.user_id != user_id);
+                    self.status = Some(format!("{} was banned by {}{}", username, by, until));
+                }
+            }
+            ServerEvent::MemberUnbanned { username, by, .. } => {
+                self.status = Some(match by {
+                    Some(by) => format!("{} was unbanned by {}", username, by),
+                    None => format!("{}'s ban ran out", username),
+                });
+            }
+            ServerEvent::MemberMuted {
+                room_id,
+                user_id,
+                username,
+                by,
+                expires_at,
+                ..
+            } => {
+                if let Some(member) = self.member_mut(room_id, user_id) {
+                    member.muted = true;
+                    member.muted_until = expires_at;
+                }
+                let until = until(expires_at);
+                self.status = Some(if user_id == self.user_id {
+                    format!("You were muted by {}{}", by, until)
+                } else {
+                    format!("{} was muted by {}{}", username, by, until)
+                });
+            }
+            ServerEvent::MemberUnmuted {
+                room_id,
+                user_id,
+                username,
+                by,
+            } => {
+                if let Some(member) = self.member_mut(room_id, user_id) {
+                    member.muted = false;
+                    member.muted_until = None;
+                }
+                self.status = Some(match (user_id == self.user_id, by) {
+                    (true, _) => "You can post again".to_string(),
+                    (false, Some(by)) => format!("{} was unmuted by {}", username, by),
+                    (false, None) => format!("{}'s mute ran out", username),
+                });
+            }
+            ServerEvent::SlowMode {
+                room_id, seconds, ..
+            } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    view.room.slow_mode_secs = seconds;
+                }
+            }
+            ServerEvent::RoleChanged {
+                room_id,
+                user_id,
+                username,
+                role,
+                by,
+            } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    if let Some(member) = view
+                        .members
+                        .iter_mut()
+                        .find(|member| member.user_id == user_id)
+                    {
+                        member.role = role;
+                    }
+                    sort_members(&mut view.members);
+                }
+                self.status = Some(if user_id == self.user_id {
+                    format!("{} made you {}", by, role)
+                } else {
+                    format!("{} made {} {}", by, username, role)
+                });
+            }
+            ServerEvent::Mention(mention) => {
+                let room_id = mention.message.room_id;
+                let looking = self.focused
+                    && self
+                        .current_room()
+                        .is_some_and(|view| view.room.room_id == room_id);
+                if let Some(view) = self.room_mut(room_id) {
+                    if mention.message.seq > view.room.last_read_seq {
+                        view.room.mentions += 1;
+                    }
+                }
+                if !looking {
+                    self.bell = true;
+                }
+                self.mentions.insert(0, mention);
+            }
+            ServerEvent::Presence(info) => {
+                for view in self.rooms.iter_mut() {
+                    let mut changed = false;
+                    for member in view.members.iter_mut() {
+                        if member.user_id == info.user_id {
+                            member.presence = info.presence;
+                            member.last_seen = info.last_seen;
+                            changed = true;
+                        }
+                    }
+                    if changed {
+                        sort_members(&mut view.members);
+                    }
+                }
+            }
+            ServerEvent::Typing {
+                room_id,
+                user_id,
+                username,
+                typing,
+            } => {
+                let me = self.user_id;
+                if let Some(view) = self.room_mut(room_id) {
+                    view.typing.retain(|(typer, _, _)| *typer != user_id);
+                    if typing && user_id != me {
+                        view.typing.push((user_id, username, Instant::now()));
+                    }
+                }
+            }
+            ServerEvent::ReadMarker {
+                room_id,
+                last_read_seq,
+                unread,
+                mentions,
+            } => {
+                if let Some(view) = self.room_mut(room_id) {
+                    if last_read_seq >= view.room.last_read_seq {
+                        view.room.last_read_seq = last_read_seq;
+                        view.room.unread = unread;
+                        view.room.mentions = mentions;
+                    }
+                }
+            }
+            ServerEvent::CommandOk { text, .. } => {
+                self.status = text;
+            }
+            ServerEvent::Throttled { retry_after_ms, .. } => {
+                let secs = retry_after_ms.div_ceil(1000).max(1);
+                self.status = Some(format!("Slow down, try again in {}s", secs));
+            }
+            ServerEvent::CommandError { message, .. } | ServerEvent::Error { message, .. } => {
+                self.status = Some(message);
+            }
+            ServerEvent::Commands { .. } | ServerEvent::Unknown => {}
+        }
     }
 }
@@ -1,35 +1,42 @@
+pub mod api;
 pub mod message;
+pub mod ssh;
 
-use anyhow::Result;
-use log::error;
-use message::{Message, MessageType};
-use serde::Deserialize;
-use tokio::{
-    io::{
-        self, stdin, stdout, AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader,
-    },
-    net::TcpStream,
-    task,
+use anyhow::{Context, Result};
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment,
 };
-
 use futures::{sink::SinkExt, stream::StreamExt};
-use std::{
-    collections::HashSet,
-    sync::{Arc, Mutex},
-};
-use tokio::sync::broadcast;
+use log::error;
+use message::{ClientFrame, ServerEvent};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
+    pub server: String,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Ring the terminal bell when someone mentions us.
+    pub bell: bool,
+    /// Where `Alt-d` saves attachments.
+    pub download_dir: String,
+    /// The SSH private key to log in with when no password is set. Its
+    /// public half is read from the same path plus `.pub`.
+    pub ssh_key: Option<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            server: "http://127.0.0.1:8080".to_string(),
             username: Some(String::new()),
             password: Some(String::new()),
+            bell: true,
+            download_dir: ".".to_string(),
+            ssh_key: None,
         }
     }
 }
@@ -39,94 +46,66 @@ impl Config {
         Self {
             username: Some(username),
             password: Some(password),
+            ..Self::default()
         }
     }
-}
-
-pub async fn connect() -> Result<()> {
-    let server_address = "127.0.0.1:8080";
-    let socket = TcpStream::connect(server_address).await?;
-    println!("Connected to server at {}", server_address);
-    let (mut reader, mut writer) = socket.into_split();
-    let read_task = tokio::spawn(async move {
-        loop {
-            let mut len_buf = [0u8; 4];
-            let read_result = reader.read_exact(&mut len_buf).await;
 
-            if read_result.is_err() {
-                error!("failed to read from socket {:?}", read_result);
-                continue;
-            }
-
-            let msg_len = u32::from_be_bytes(len_buf) as usize;
-            let mut msg_buf = vec![0u8; msg_len];
+    pub fn load() -> Result<Self> {
+        Figment::from(Serialized::defaults(Self::default()))
+            .merge(Toml::file("xenon.toml"))
+            .merge(Env::prefixed("XENON_"))
+            .extract()
+            .context("invalid xenon configuration")
+    }
+}
 
-            if let Err(e) = reader.read_exact(&mut msg_buf).await {
-                error!("failed to read from socket: {}", e);
+/// Open the websocket. Frames sent on the returned sender go to radon, events
+/// from radon arrive on the receiver until the connection drops.
+pub async fn connect(
+    url: &str,
+) -> Result<(
+    mpsc::UnboundedSender<ClientFrame>,
+    mpsc::UnboundedReceiver<ServerEvent>,
+)> {
+    let (socket, _) = connect_async(url)
+        .await
+        .context("failed to open websocket")?;
+    let (mut writer, mut reader) = socket.split();
+
+    let (frame_tx, mut frame_rx) = mpsc::unbounded_channel::<ClientFrame>();
+    let (event_tx, event_rx) = mpsc::unbounded_channel::<ServerEvent>();
+
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = reader.next().await {
+            let Message::Text(text) = message else {
                 continue;
-            }
-            let received_message: Result<Message, _> = serde_json::from_slice(&msg_buf);
-            match received_message {
-                Ok(message) => match message.message_type {
-                    MessageType::Chat => {
-                        println!("Received chat message");
-                        println!("Message: {}", message.payload);
-                    }
-                    MessageType::Register => {
-                        println!("Received register message");
+            };
+            match serde_json::from_str::<ServerEvent>(&text) {
+                Ok(event) => {
+                    if event_tx.send(event).is_err() {
+                        break;
                     }
-                },
-                Err(e) => {
-                    eprintln!("Failed to parse message: {}", e);
                 }
+                Err(e) => error!("failed to parse event: {}", e),
             }
-            stdout().flush().await.unwrap();
-            tokio::task::yield_now().await;
         }
     });
 
-    let write_task = tokio::spawn(async move {
-        let mut reader = BufReader::new(stdin());
-        loop {
-            let mut input_buffer = Vec::new();
-            // Wait for input from the terminal
-            println!("Enter text to send: ");
-            stdout().flush().await.unwrap();
-
-            if reader.read_until(b'\n', &mut input_buffer).await.is_err() {
-                eprintln!("Failed to read from stdin");
-                break;
-            }
-
-            let message_str = match String::from_utf8(input_buffer.clone()) {
-                Ok(v) => v,
+    tokio::spawn(async move {
+        while let Some(frame) = frame_rx.recv().await {
+            let text = match serde_json::to_string(&frame) {
+                Ok(text) => text,
                 Err(e) => {
-                    eprintln!("Invalid UTF-8 sequence: {}", e);
+                    error!("failed to encode frame: {}", e);
                     continue;
                 }
             };
-
-            let message = Message::new(MessageType::Chat, message_str);
-            let serialized_message = message.encode();
-
-            // Write the input to the writer
-            if let Err(e) = writer.write_all(&serialized_message).await {
-                eprintln!("Failed to write to socket: {}", e);
+            if let Err(e) = writer.send(Message::Text(text)).await {
+                error!("failed to write to socket: {}", e);
                 break;
             }
-
-            tokio::task::yield_now().await;
         }
     });
 
-    // Optionally, you can await these tasks if you want the main function to wait for them.
-    let result = tokio::try_join!(read_task, write_task);
-    if let Err(e) = result {
-        eprintln!("Failed to join read/write tasks: {}", e);
-    }
-
-    Ok(())
-}
-pub fn send_message(message: String) {
-    println!("Sending message: {}", message);
+    Ok((frame_tx, event_rx))
 }
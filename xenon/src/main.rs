@@ -1,84 +1,650 @@
 pub mod app;
-use app::{App, CurrentScreen};
+pub mod ui;
+
+use app::{App, CurrentScreen, REACTIONS};
+use client::{
+    api::Api,
+    message::{ChatMessage, ClientFrame, ServerEvent},
+    ssh, Config,
+};
 use crossterm::{
-    event::{self, DisableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{
+        DisableFocusChange, DisableMouseCapture, EnableFocusChange, Event, EventStream, KeyCode,
+        KeyEventKind, KeyModifiers,
+    },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
 };
+use futures::StreamExt;
+use qrcode::{render::unicode::Dense1x2, QrCode};
 
-use anyhow::Result;
-use ratatui::{
-    prelude::{Backend, CrosstermBackend, Terminal},
-    style::Stylize,
-    widgets::Paragraph,
+use anyhow::{anyhow, Result};
+use ratatui::prelude::{Backend, CrosstermBackend, Terminal};
+use std::{
+    io::{stderr, Write},
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use std::io::{self, stderr};
+use tokio::sync::mpsc;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::load()?;
+    let username = config.username.clone().unwrap_or_default();
+    let password = config.password.clone().unwrap_or_default();
+    if username.is_empty() {
+        return Err(anyhow!("set username in xenon.toml"));
+    }
+
+    // Without a password we sign in with an SSH key.
+    let api = if password.is_empty() {
+        let key_path = config.ssh_key.as_deref().map(Path::new);
+        Api::login_ssh(&config.server, &username, key_path).await?
+    } else {
+        Api::login(&config.server, &username, &password, prompt_code).await?
+    };
+    let rooms = api.rooms().await?;
+    let (frames, events) = client::connect(&api.websocket_url()).await?;
 
-pub type Frame<'a> = ratatui::Frame<'a, CrosstermBackend<std::io::Stderr>>;
+    let mut app = App::new(api.session.id, api.session.username.clone(), rooms);
+    if api.session.failed_attempts > 0 {
+        app.status = Some(format!(
+            "{} failed login attempts since your last login, see /logins",
+            api.session.failed_attempts
+        ));
+    }
+    match api.mentions().await {
+        Ok(mentions) => app.mentions = mentions,
+        Err(e) => app.status = Some(format!("failed to load mentions: {}", e)),
+    }
+    match api.bookmarks().await {
+        Ok(bookmarks) => app.bookmarks = bookmarks,
+        Err(e) => app.status = Some(format!("failed to load saved messages: {}", e)),
+    }
+    load_current_room(&api, &mut app).await;
+    app.place_read_marker();
+    mark_read(&mut app, &frames);
 
-fn main() -> Result<()> {
-    crossterm::terminal::enable_raw_mode()?;
-    crossterm::execute!(stderr(), crossterm::terminal::EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    crossterm::execute!(stderr(), EnterAlternateScreen, EnableFocusChange)?;
 
     let backend = CrosstermBackend::new(stderr());
     let mut terminal = Terminal::new(backend)?;
-    let mut app = App::new();
 
-    let res = run_app(&mut terminal, &mut app);
+    let res = run_app(&mut terminal, &mut app, &api, &config, frames, events).await;
 
     disable_raw_mode()?;
     crossterm::execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
+        DisableFocusChange,
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
 
-    if let Ok(do_print) = res {
-        if do_print {
-            app.print_json()?;
-        }
-    } else if let Err(err) = res {
+    if let Err(err) = res {
         println!("{err:?}");
     }
 
     Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<bool> {
-    loop {
-        terminal.draw(|f| ui(f, app))?;
-        if let Event::Key(key) = event::read()? {
-            dbg!(key.code);
+/// Fetch history, members and pins the first time a room is shown.
+async fn load_current_room(api: &Api, app: &mut App) {
+    let Some(view) = app.current_room_mut() else {
+        return;
+    };
+    if view.loaded {
+        return;
+    }
+    let room_id = view.room.room_id;
+
+    let history = api.history(room_id).await;
+    let members = api.members(room_id).await;
+    let pins = api.pins(room_id).await;
+
+    match (history, members, pins) {
+        (Ok(history), Ok(members), Ok(pins)) => {
+            if let Some(view) = app.current_room_mut() {
+                view.messages = history;
+                view.set_members(members);
+                view.pins = pins;
+                view.loaded = true;
+            }
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            app.status = Some(format!("failed to load room: {}", e))
+        }
+    }
+}
+
+/// Switch to a message's room with the history around it on screen.
+async fn show_in_context(api: &Api, app: &mut App, message: &ChatMessage) {
+    app.cancel_edit();
+    app.replying = None;
+    app.thread = None;
+    if !app.select_room(message.room_id) {
+        app.status = Some("you're no longer in that room".to_string());
+        return;
+    }
+    load_current_room(api, app).await;
+    // Half a page either side of the message.
+    match api
+        .history_before(message.room_id, message.seq + 25, 50)
+        .await
+    {
+        Ok(history) => app.show_context(message.message_id, history),
+        Err(e) => app.status = Some(format!("failed to load message: {}", e)),
+    }
+}
+
+/// Report the current room as read up to its latest message, if it's on screen.
+fn mark_read(app: &mut App, frames: &mpsc::UnboundedSender<ClientFrame>) {
+    if let Some((room_id, seq)) = app.read_current() {
+        let _ = frames.send(ClientFrame::MarkRead { room_id, seq });
+    }
+}
+
+/// Tell radon we stopped typing in the current room, if we said we started.
+fn stop_typing(app: &mut App, frames: &mpsc::UnboundedSender<ClientFrame>) {
+    if app.typing_sent.take().is_none() {
+        return;
+    }
+    if let Some(view) = app.current_room() {
+        let _ = frames.send(ClientFrame::Typing {
+            room_id: view.room.room_id,
+            typing: false,
+        });
+    }
+}
+
+/// Upload a file and post it to the current room, in the thread we're
+/// replying to if there is one.
+async fn upload_file(
+    api: &Api,
+    app: &mut App,
+    frames: &mpsc::UnboundedSender<ClientFrame>,
+    path: &str,
+) {
+    let Some(room_id) = app.current_room().map(|view| view.room.room_id) else {
+        return;
+    };
+    match api.upload(Path::new(path)).await {
+        Ok(upload) => {
+            let parent_id = app.send_parent();
+            let _ = frames.send(ClientFrame::Send {
+                room_id,
+                text: String::new(),
+                parent_id,
+                attachments: vec![upload.upload_id],
+            });
+            app.replying = None;
+            app.status = Some(format!("uploaded {}", upload.filename));
         }
+        Err(e) => app.status = Some(format!("upload failed: {}", e)),
+    }
+}
+
+/// Ask for a second factor on the terminal, before the UI takes it over.
+fn prompt_code() -> Result<String> {
+    eprint!("Two-factor code (or a recovery code): ");
+    stderr().flush()?;
+    let mut code = String::new();
+    std::io::stdin().read_line(&mut code)?;
+    Ok(code)
+}
+
+/// A QR code for `data`, drawn with half blocks for a dark terminal.
+fn qr_lines(data: &str) -> Result<Vec<String>> {
+    let code = QrCode::new(data.as_bytes())?;
+    let text = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    Ok(text.lines().map(str::to_string).collect())
+}
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == event::KeyEventKind::Release {
+/// `/2fa setup`, `/2fa confirm <code>` and `/2fa disable <password> <code>`.
+async fn two_factor(api: &Api, app: &mut App, text: &str) {
+    let args = text.split_whitespace().skip(1).collect::<Vec<&str>>();
+    match args.as_slice() {
+        ["setup"] => match api.setup_two_factor().await {
+            Ok(setup) => {
+                let mut lines = match qr_lines(&setup.otpauth_uri) {
+                    Ok(lines) => lines,
+                    Err(e) => vec![format!("failed to draw QR code: {}", e)],
+                };
+                lines.push(format!("secret: {}", setup.secret));
+                lines.push("scan it, then run /2fa confirm <code>".to_string());
+                app.two_factor = lines;
+                app.current_screen = CurrentScreen::TwoFactor;
+            }
+            Err(e) => app.status = Some(format!("2fa setup failed: {}", e)),
+        },
+        ["confirm", code] => match api.confirm_two_factor(code).await {
+            Ok(codes) => {
+                let mut lines = vec![
+                    "two-factor authentication is on".to_string(),
+                    "keep these recovery codes somewhere safe,".to_string(),
+                    "each logs you in once:".to_string(),
+                    String::new(),
+                ];
+                lines.extend(codes);
+                app.two_factor = lines;
+                app.current_screen = CurrentScreen::TwoFactor;
+            }
+            Err(e) => app.status = Some(format!("2fa confirm failed: {}", e)),
+        },
+        ["disable", password, code] => match api.disable_two_factor(password, code).await {
+            Ok(()) => app.status = Some("two-factor authentication is off".to_string()),
+            Err(e) => app.status = Some(format!("2fa disable failed: {}", e)),
+        },
+        _ => {
+            app.status = Some(
+                "usage: /2fa setup | /2fa confirm <code> | /2fa disable <password> <code>"
+                    .to_string(),
+            )
+        }
+    }
+}
+
+/// Register the public half of an SSH key, by default the one we log in
+/// with, so we can log in with it.
+async fn add_ssh_key(api: &Api, app: &mut App, config: &Config, path: &str) {
+    let key_path = if path.is_empty() {
+        config
+            .ssh_key
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(ssh::default_key_path)
+    } else {
+        PathBuf::from(path.strip_suffix(".pub").unwrap_or(path))
+    };
+    let added = async {
+        let public_key = ssh::read_public_key(&key_path)?.to_openssh()?;
+        api.add_ssh_key(&public_key).await
+    };
+    match added.await {
+        Ok(key) => app.status = Some(format!("added SSH key {} ({})", key.name, key.fingerprint)),
+        Err(e) => app.status = Some(format!("failed to add SSH key: {}", e)),
+    }
+}
+
+/// A path in `dir` for `filename` that doesn't clobber an existing file.
+fn download_path(dir: &Path, filename: &str) -> PathBuf {
+    // Never let a name from the server point outside `dir`.
+    let filename = Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("download");
+    let path = dir.join(filename);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (filename, String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
+
+/// Save the picked message's attachments into `dir`.
+async fn download_attachments(api: &Api, app: &mut App, dir: &str) {
+    let attachments = app
+        .current_room()
+        .and_then(|view| view.cursor_message())
+        .map(|message| message.attachments.clone())
+        .unwrap_or_default();
+    if attachments.is_empty() {
+        app.status = Some("no attachments on that message".to_string());
+        return;
+    }
+
+    let dir = Path::new(dir);
+    let mut saved = Vec::new();
+    for attachment in attachments {
+        let result = match api.download(attachment.upload_id).await {
+            Ok(bytes) => {
+                let path = download_path(dir, &attachment.filename);
+                tokio::fs::write(&path, bytes).await.map(|_| path)
+            }
+            Err(e) => {
+                app.status = Some(format!("download failed: {}", e));
+                return;
+            }
+        };
+        match result {
+            Ok(path) => saved.push(path.display().to_string()),
+            Err(e) => {
+                app.status = Some(format!("failed to save {}: {}", attachment.filename, e));
+                return;
+            }
+        }
+    }
+    app.status = Some(format!("saved {}", saved.join(", ")));
+}
+
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    api: &Api,
+    config: &Config,
+    frames: mpsc::UnboundedSender<ClientFrame>,
+    mut events: mpsc::UnboundedReceiver<ServerEvent>,
+) -> Result<()> {
+    let mut input = EventStream::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        terminal.draw(|f| ui::ui(f, app))?;
+
+        let event = tokio::select! {
+            _ = tick.tick() => {
+                app.expire_typing();
                 continue;
             }
-            match app.current_screen {
-                CurrentScreen::Main => match key.code {
-                    KeyCode::Char('e') => {
-                        app.current_screen = CurrentScreen::Edit;
-                        app.currently_editing = Some(CurrentlyEditing::Key);
+            event = input.next() => match event {
+                Some(event) => event?,
+                None => return Ok(()),
+            },
+            event = events.recv() => match event {
+                Some(event) => {
+                    app.apply(event);
+                    if std::mem::take(&mut app.bell) && config.bell {
+                        let mut out = stderr();
+                        let _ = out.write_all(b"\x07").and_then(|_| out.flush());
                     }
-                    KeyCode::Char('q') => {
-                        app.current_screen = CurrentScreen::Exiting;
+                    mark_read(app, &frames);
+                    continue;
+                }
+                None => return Err(anyhow!("disconnected from radon")),
+            },
+        };
+
+        let key = match event {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            Event::FocusLost => {
+                app.focused = false;
+                let _ = frames.send(ClientFrame::SetPresence { away: true });
+                continue;
+            }
+            Event::FocusGained => {
+                app.focused = true;
+                let _ = frames.send(ClientFrame::SetPresence { away: false });
+                mark_read(app, &frames);
+                continue;
+            }
+            _ => continue,
+        };
+
+        match app.current_screen {
+            CurrentScreen::Main => match key.code {
+                KeyCode::Esc if app.editing.is_some() => app.cancel_edit(),
+                KeyCode::Esc if app.replying.is_some() => app.replying = None,
+                KeyCode::Esc if app.thread.is_some() => app.thread = None,
+                KeyCode::Esc if app.current_room().is_some_and(|view| view.context) => {
+                    if let Some(view) = app.current_room_mut() {
+                        view.context = false;
+                        view.loaded = false;
+                        view.cursor = None;
                     }
-                    _ => {}
-                },
-                CurrentScreen::Exiting => match key.code {
-                    KeyCode::Char('y') => {
-                        return Ok(true);
+                    load_current_room(api, app).await;
+                    mark_read(app, &frames);
+                }
+                KeyCode::Esc if app.current_room().is_some_and(|view| view.cursor.is_some()) => {
+                    if let Some(view) = app.current_room_mut() {
+                        view.cursor = None;
+                    }
+                }
+                KeyCode::Esc => app.current_screen = CurrentScreen::Exiting,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.current_screen = CurrentScreen::Exiting;
+                }
+                KeyCode::Up if key.modifiers.contains(KeyModifiers::ALT) => {
+                    if let Some(view) = app.current_room_mut() {
+                        view.cursor_up();
+                    }
+                }
+                KeyCode::Down if key.modifiers.contains(KeyModifiers::ALT) => {
+                    if let Some(view) = app.current_room_mut() {
+                        view.cursor_down();
+                    }
+                }
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.reply_to_cursor();
+                }
+                KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    if let Some(message_id) = app.thread_to_open() {
+                        match api.thread(message_id).await {
+                            Ok(thread) => app.thread = Some(thread),
+                            Err(e) => app.status = Some(format!("failed to load thread: {}", e)),
+                        }
                     }
-                    KeyCode::Char('n') => {
-                        return Ok(false);
+                }
+                KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.current_screen = CurrentScreen::Reacting(0);
+                }
+                KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    if let Some(frame) = app.toggle_pin() {
+                        let _ = frames.send(frame);
                     }
-                    _ => {}
+                }
+                KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    if let Some(frame) = app.toggle_bookmark() {
+                        let _ = frames.send(frame);
+                    }
+                }
+                KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    download_attachments(api, app, &config.download_dir).await;
+                }
+                KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.current_screen = CurrentScreen::Bookmarks(0);
+                }
+                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.current_screen = CurrentScreen::Search;
+                }
+                KeyCode::Char('m') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.current_screen = CurrentScreen::Mentions(0);
+                }
+                KeyCode::Up if app.input.is_empty() => app.edit_last(),
+                KeyCode::Tab => {
+                    app.cancel_edit();
+                    app.replying = None;
+                    app.thread = None;
+                    stop_typing(app, &frames);
+                    app.next_room();
+                    load_current_room(api, app).await;
+                    app.place_read_marker();
+                    mark_read(app, &frames);
+                }
+                KeyCode::BackTab => {
+                    app.cancel_edit();
+                    app.replying = None;
+                    app.thread = None;
+                    stop_typing(app, &frames);
+                    app.previous_room();
+                    load_current_room(api, app).await;
+                    app.place_read_marker();
+                    mark_read(app, &frames);
+                }
+                KeyCode::Enter => {
+                    // radon clears our indicator when the message lands.
+                    app.typing_sent = None;
+                    let text = app.take_input();
+                    let reaction = App::shortcode_reaction(&text).filter(|_| app.editing.is_none());
+                    if let Some(emoji) = reaction {
+                        if let Some(frame) = app.toggle_reaction(emoji) {
+                            let _ = frames.send(frame);
+                        }
+                    } else if let Some(message_id) = app.editing.take() {
+                        // Clearing an edit deletes the message.
+                        let frame = if text.trim().is_empty() {
+                            ClientFrame::Delete { message_id }
+                        } else {
+                            ClientFrame::Edit { message_id, text }
+                        };
+                        let _ = frames.send(frame);
+                    } else if let Some(path) = text.strip_prefix("/upload ") {
+                        upload_file(api, app, &frames, path.trim()).await;
+                    } else if text == "/2fa" || text.starts_with("/2fa ") {
+                        two_factor(api, app, &text).await;
+                    } else if let Some(path) = text
+                        .strip_prefix("/sshkey")
+                        .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+                    {
+                        add_ssh_key(api, app, config, path.trim()).await;
+                    } else if let Some(view) = app.current_room() {
+                        if !text.trim().is_empty() {
+                            let room_id = view.room.room_id;
+                            let parent_id = app.send_parent();
+                            let _ = frames.send(ClientFrame::Send {
+                                room_id,
+                                text,
+                                parent_id,
+                                attachments: Vec::new(),
+                            });
+                            app.replying = None;
+                        }
+                    }
+                }
+                KeyCode::Backspace => {
+                    app.input.pop();
+                    if app.input.is_empty() {
+                        stop_typing(app, &frames);
+                    }
+                }
+                KeyCode::Char(c) => {
+                    app.input.push(c);
+                    if app.editing.is_none()
+                        && !app.input.starts_with('/')
+                        && app.should_send_typing()
+                    {
+                        if let Some(view) = app.current_room() {
+                            let room_id = view.room.room_id;
+                            let _ = frames.send(ClientFrame::Typing {
+                                room_id,
+                                typing: true,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            },
+            CurrentScreen::Reacting(i) => match key.code {
+                KeyCode::Left | KeyCode::Up => {
+                    app.current_screen =
+                        CurrentScreen::Reacting((i + REACTIONS.len() - 1) % REACTIONS.len());
+                }
+                KeyCode::Right | KeyCode::Down | KeyCode::Tab => {
+                    app.current_screen = CurrentScreen::Reacting((i + 1) % REACTIONS.len());
+                }
+                KeyCode::Enter => {
+                    if let Some(frame) = app.toggle_reaction(REACTIONS[i].1) {
+                        let _ = frames.send(frame);
+                    }
+                    app.current_screen = CurrentScreen::Main;
+                }
+                KeyCode::Esc => app.current_screen = CurrentScreen::Main,
+                _ => {}
+            },
+            CurrentScreen::Mentions(i) => match key.code {
+                KeyCode::Up => app.current_screen = CurrentScreen::Mentions(i.saturating_sub(1)),
+                KeyCode::Down => {
+                    let last = app.mentions.len().saturating_sub(1);
+                    app.current_screen = CurrentScreen::Mentions((i + 1).min(last));
+                }
+                KeyCode::Enter => {
+                    app.current_screen = CurrentScreen::Main;
+                    app.cancel_edit();
+                    app.replying = None;
+                    app.thread = None;
+                    stop_typing(app, &frames);
+                    app.jump_to_mention(i);
+                    load_current_room(api, app).await;
+                    app.place_read_marker();
+                    mark_read(app, &frames);
+                }
+                KeyCode::Esc => app.current_screen = CurrentScreen::Main,
+                _ => {}
+            },
+            CurrentScreen::Search => match key.code {
+                KeyCode::Esc => app.current_screen = CurrentScreen::Main,
+                KeyCode::Enter if app.search.is_stale() => match app.search.query.trim() {
+                    "" => {}
+                    query => match api.search(query, None).await {
+                        Ok(page) => app.search.set_results(page),
+                        Err(e) => app.status = Some(format!("search failed: {}", e)),
+                    },
                 },
-                CurrentScreen::Editing => match key.code 
-
+                KeyCode::Enter => {
+                    let Some(hit) = app.search.results.get(app.search.selected) else {
+                        continue;
+                    };
+                    let message = hit.message.clone();
+                    app.current_screen = CurrentScreen::Main;
+                    stop_typing(app, &frames);
+                    show_in_context(api, app, &message).await;
+                }
+                KeyCode::Up => app.search.selected = app.search.selected.saturating_sub(1),
+                KeyCode::Down => {
+                    if app.search.selected + 1 < app.search.results.len() {
+                        app.search.selected += 1;
+                    } else if let Some(cursor) = app.search.next_cursor {
+                        match api.search(app.search.query.trim(), Some(cursor)).await {
+                            Ok(page) => {
+                                app.search.append_results(page);
+                                if app.search.selected + 1 < app.search.results.len() {
+                                    app.search.selected += 1;
+                                }
+                            }
+                            Err(e) => app.status = Some(format!("search failed: {}", e)),
+                        }
+                    }
+                }
+                KeyCode::Backspace => {
+                    app.search.query.pop();
+                }
+                KeyCode::Char(c) => app.search.query.push(c),
+                _ => {}
+            },
+            CurrentScreen::Bookmarks(i) => match key.code {
+                KeyCode::Up => app.current_screen = CurrentScreen::Bookmarks(i.saturating_sub(1)),
+                KeyCode::Down => {
+                    let last = app.bookmarks.len().saturating_sub(1);
+                    app.current_screen = CurrentScreen::Bookmarks((i + 1).min(last));
+                }
+                KeyCode::Enter => {
+                    app.current_screen = CurrentScreen::Main;
+                    if let Some(bookmark) = app.bookmarks.get(i) {
+                        let message = bookmark.message.clone();
+                        stop_typing(app, &frames);
+                        show_in_context(api, app, &message).await;
+                    }
+                }
+                KeyCode::Delete | KeyCode::Char('d') => {
+                    if let Some(bookmark) = app.bookmarks.get(i) {
+                        let message_id = bookmark.message.message_id;
+                        let _ = frames.send(ClientFrame::Unbookmark { message_id });
+                    }
+                }
+                KeyCode::Esc => app.current_screen = CurrentScreen::Main,
+                _ => {}
+            },
+            CurrentScreen::TwoFactor => {
+                if key.code == KeyCode::Esc {
+                    // Don't leave the secret or recovery codes lying around.
+                    app.two_factor.clear();
+                    app.current_screen = CurrentScreen::Main;
+                }
             }
+            CurrentScreen::Exiting => match key.code {
+                KeyCode::Char('y') => return Ok(()),
+                KeyCode::Char('n') | KeyCode::Esc => app.current_screen = CurrentScreen::Main,
+                _ => {}
+            },
         }
     }
 }
@@ -1,31 +1,397 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum MessageType {
-    Register,
-    Chat,
+    Join,
+    Leave,
+    Text,
+    Action,
+    System,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Message {
-    pub message_type: MessageType,
-    pub payload: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub message_id: i64,
+    pub room_id: Uuid,
+    pub seq: i64,
+    pub kind: MessageType,
+    pub user_id: Option<Uuid>,
+    pub from: Option<String>,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub edited_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub reply_to: Option<ReplyPreview>,
+    #[serde(default)]
+    pub thread: Option<ThreadSummary>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
 }
 
-impl Message {
-    pub fn new(message_type: MessageType, payload: String) -> Self {
-        Self {
-            message_type,
-            payload,
-        }
-    }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub upload_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub mime: String,
+}
+
+/// What `POST /uploads` gives back.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Upload {
+    pub upload_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub mime: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pin {
+    pub message: ChatMessage,
+    pub pinned_by: Option<String>,
+    pub pinned_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bookmark {
+    pub message: ChatMessage,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub room_name: String,
+    /// Matches are wrapped in `«` and `»`.
+    pub snippet: String,
+    pub message: ChatMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchPage {
+    pub results: Vec<SearchHit>,
+    pub next_cursor: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    User,
+    Here,
+    Room,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MentionInfo {
+    pub kind: MentionKind,
+    pub message: ChatMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: i64,
+    pub user_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplyPreview {
+    pub message_id: i64,
+    pub from: Option<String>,
+    pub text: String,
+    pub deleted: bool,
+}
 
-    pub fn encode(&self) -> Vec<u8> {
-        let message_json = serde_json::to_string(&self).unwrap();
-        let message_len = message_json.len() as u32;
-        let message_len_buf = message_len.to_be_bytes().to_vec();
-        let mut message_buf = message_len_buf;
-        message_buf.extend_from_slice(message_json.as_bytes());
-        message_buf
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ThreadSummary {
+    pub reply_count: i64,
+    pub last_reply_at: NaiveDateTime,
+    pub last_reply_from: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Thread {
+    pub root: ChatMessage,
+    pub replies: Vec<ChatMessage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Room {
+    pub room_id: Uuid,
+    pub name: String,
+    pub topic: String,
+    #[serde(default)]
+    pub slow_mode_secs: i32,
+    #[serde(default)]
+    pub last_read_seq: i64,
+    #[serde(default)]
+    pub unread: i64,
+    #[serde(default)]
+    pub mentions: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Guest,
+    #[default]
+    Member,
+    Moderator,
+    Admin,
+    Owner,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        };
+        f.write_str(name)
     }
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Member {
+    pub user_id: Uuid,
+    pub username: String,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub muted_until: Option<NaiveDateTime>,
+    pub presence: Presence,
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PresenceInfo {
+    pub user_id: Uuid,
+    pub username: String,
+    pub presence: Presence,
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommandInfo {
+    pub name: String,
+    pub usage: String,
+    pub help: String,
+}
+
+/// Frames we send to radon over `/ws`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Send {
+        room_id: Uuid,
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parent_id: Option<i64>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<Uuid>,
+    },
+    Command {
+        room_id: Uuid,
+        name: String,
+        args: String,
+    },
+    ListCommands,
+    SetPresence {
+        away: bool,
+    },
+    Typing {
+        room_id: Uuid,
+        typing: bool,
+    },
+    MarkRead {
+        room_id: Uuid,
+        seq: i64,
+    },
+    Edit {
+        message_id: i64,
+        text: String,
+    },
+    Delete {
+        message_id: i64,
+    },
+    React {
+        message_id: i64,
+        emoji: String,
+    },
+    Unreact {
+        message_id: i64,
+        emoji: String,
+    },
+    Pin {
+        message_id: i64,
+    },
+    Unpin {
+        message_id: i64,
+    },
+    Bookmark {
+        message_id: i64,
+    },
+    Unbookmark {
+        message_id: i64,
+    },
+}
+
+/// Events radon sends us over `/ws`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Message(ChatMessage),
+    MessageEdited {
+        room_id: Uuid,
+        message_id: i64,
+        text: String,
+        edited_at: NaiveDateTime,
+    },
+    MessageDeleted {
+        room_id: Uuid,
+        message_id: i64,
+        deleted_by: Uuid,
+    },
+    ReactionAdded {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    ReactionRemoved {
+        room_id: Uuid,
+        message_id: i64,
+        emoji: String,
+        user_id: Uuid,
+        username: String,
+    },
+    MessagePinned(Pin),
+    MessageUnpinned {
+        room_id: Uuid,
+        message_id: i64,
+        unpinned_by: String,
+    },
+    BookmarkAdded(Bookmark),
+    BookmarkRemoved {
+        message_id: i64,
+    },
+    Topic {
+        room_id: Uuid,
+        topic: String,
+        set_by: String,
+    },
+    MemberJoined {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+    },
+    MemberKicked {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+    },
+    MemberBanned {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+    },
+    MemberUnbanned {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: Option<String>,
+    },
+    MemberMuted {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+    },
+    MemberUnmuted {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        by: Option<String>,
+    },
+    SlowMode {
+        room_id: Uuid,
+        seconds: i32,
+        by: String,
+    },
+    RoleChanged {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        role: Role,
+        by: String,
+    },
+    Presence(PresenceInfo),
+    Mention(MentionInfo),
+    Typing {
+        room_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        typing: bool,
+    },
+    ReadMarker {
+        room_id: Uuid,
+        last_read_seq: i64,
+        unread: i64,
+        mentions: i64,
+    },
+    Throttled {
+        scope: String,
+        retry_after_ms: u64,
+    },
+    CommandOk {
+        command: String,
+        text: Option<String>,
+    },
+    CommandError {
+        command: String,
+        code: String,
+        message: String,
+    },
+    Commands {
+        commands: Vec<CommandInfo>,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+    /// Anything newer than this client understands.
+    #[serde(other)]
+    Unknown,
+}
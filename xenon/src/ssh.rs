@@ -0,0 +1,181 @@
+//! Signing radon login challenges with an SSH key, through ssh-agent if it's
+//! running, otherwise straight from the key file.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ssh_key::{Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey, Signature, SshSig};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// Where ssh-keygen puts an ed25519 key by default.
+pub fn default_key_path() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_default();
+    Path::new(&home).join(".ssh").join("id_ed25519")
+}
+
+/// A connection to ssh-agent over its Unix socket.
+struct Agent {
+    stream: UnixStream,
+}
+
+impl Agent {
+    async fn connect() -> Result<Self> {
+        let path = env::var("SSH_AUTH_SOCK").context("SSH_AUTH_SOCK is not set")?;
+        let stream = UnixStream::connect(&path)
+            .await
+            .with_context(|| format!("failed to connect to ssh-agent at {}", path))?;
+        Ok(Self { stream })
+    }
+
+    async fn request(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        self.stream
+            .write_all(&(message.len() as u32).to_be_bytes())
+            .await?;
+        self.stream.write_all(message).await?;
+
+        let length = self.stream.read_u32().await? as usize;
+        let mut response = vec![0; length];
+        self.stream.read_exact(&mut response).await?;
+        Ok(response)
+    }
+
+    async fn identities(&mut self) -> Result<Vec<PublicKey>> {
+        let response = self.request(&[SSH_AGENTC_REQUEST_IDENTITIES]).await?;
+        let mut reader = Reader(&response);
+        if reader.byte()? != SSH_AGENT_IDENTITIES_ANSWER {
+            bail!("ssh-agent refused to list keys");
+        }
+
+        let count = reader.u32()?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            let blob = reader.string()?;
+            let _comment = reader.string()?;
+            // Skip key types we can't parse rather than giving up.
+            if let Ok(key) = PublicKey::from_bytes(blob) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn sign(&mut self, key: &PublicKey, data: &[u8]) -> Result<Signature> {
+        let blob = key.to_bytes()?;
+        let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut message, &blob);
+        put_string(&mut message, data);
+        message.extend_from_slice(&0u32.to_be_bytes());
+
+        let response = self.request(&message).await?;
+        let mut reader = Reader(&response);
+        if reader.byte()? != SSH_AGENT_SIGN_RESPONSE {
+            bail!("ssh-agent refused to sign");
+        }
+        Signature::try_from(reader.string()?).context("ssh-agent returned a bad signature")
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Reads the SSH wire encoding used by the agent protocol.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            bail!("truncated ssh-agent response");
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+/// Sign `message` in `namespace` as an SSHSIG, returning the public key that
+/// signed and the armored signature.
+///
+/// With `key_path`, only that key (or the agent's copy of it) is used.
+/// Otherwise the agent's first ed25519 key is used, falling back to
+/// `~/.ssh/id_ed25519`.
+pub async fn sign(
+    namespace: &str,
+    message: &[u8],
+    key_path: Option<&Path>,
+) -> Result<(String, String)> {
+    let wanted = match key_path {
+        Some(path) => Some(read_public_key(path)?),
+        None => None,
+    };
+
+    if let Ok(mut agent) = Agent::connect().await {
+        let key = agent
+            .identities()
+            .await?
+            .into_iter()
+            .find(|key| match &wanted {
+                Some(wanted) => key.key_data() == wanted.key_data(),
+                None => key.algorithm() == Algorithm::Ed25519,
+            });
+        if let Some(key) = key {
+            let hash = HashAlg::Sha512;
+            let data = SshSig::signed_data(namespace, hash, message)?;
+            let signature = agent.sign(&key, &data).await?;
+            let sshsig = SshSig::new(key.key_data().clone(), namespace, hash, signature)?;
+            return Ok((key.to_openssh()?, sshsig.to_pem(LineEnding::LF)?));
+        }
+    }
+
+    let path = key_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(default_key_path);
+    let key = PrivateKey::read_openssh_file(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    if key.is_encrypted() {
+        return Err(anyhow!(
+            "{} is passphrase protected, add it to ssh-agent first",
+            path.display()
+        ));
+    }
+    let sshsig = key.sign(namespace, HashAlg::Sha512, message)?;
+    Ok((
+        key.public_key().to_openssh()?,
+        sshsig.to_pem(LineEnding::LF)?,
+    ))
+}
+
+/// The public half of the key at `path`, from `path.pub`.
+pub fn read_public_key(path: &Path) -> Result<PublicKey> {
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    let public = PathBuf::from(public);
+    PublicKey::read_openssh_file(&public)
+        .with_context(|| format!("failed to read {}", public.display()))
+}
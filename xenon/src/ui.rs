@@ -1 +1,669 @@
-use anyhow::Result;
+use client::message::{ChatMessage, MessageType, Presence, Role};
+use ratatui::{
+    prelude::{Backend, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    Frame,
+};
+
+use uuid::Uuid;
+
+use crate::app::{App, CurrentScreen, REACTIONS};
+
+pub fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let side = if app.thread.is_some() {
+        Constraint::Percentage(35)
+    } else {
+        Constraint::Length(22)
+    };
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(20), Constraint::Min(20), side])
+        .split(f.size());
+
+    let banner = match app.current_room() {
+        Some(view) if !view.pins.is_empty() => 1,
+        _ => 0,
+    };
+    let center = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(banner),
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(3),
+        ])
+        .split(columns[1]);
+
+    render_rooms(f, app, columns[0]);
+    render_pins(f, app, center[0]);
+    render_messages(f, app, center[1]);
+    render_status(f, app, center[2]);
+    render_input(f, app, center[3]);
+    if app.thread.is_some() {
+        render_thread(f, app, columns[2]);
+    } else {
+        render_members(f, app, columns[2]);
+    }
+
+    match app.current_screen {
+        CurrentScreen::Exiting => render_exit(f),
+        CurrentScreen::Reacting(selected) => render_picker(f, selected),
+        CurrentScreen::Mentions(selected) => render_mentions(f, app, selected),
+        CurrentScreen::Search => render_search(f, app),
+        CurrentScreen::Bookmarks(selected) => render_bookmarks(f, app, selected),
+        CurrentScreen::TwoFactor => render_two_factor(f, app),
+        CurrentScreen::Main => {}
+    }
+}
+
+fn render_rooms<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let items = app
+        .rooms
+        .iter()
+        .enumerate()
+        .map(|(i, view)| {
+            let mut style = if i == app.selected {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            if view.room.unread > 0 {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            let mut spans = vec![Span::styled(format!("#{}", view.room.name), style)];
+            if view.room.mentions > 0 {
+                spans.push(Span::styled(
+                    format!(" @{}", view.room.mentions),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            } else if view.room.unread > 0 {
+                spans.push(Span::styled(
+                    format!(" {}", view.room.unread),
+                    Style::default().fg(Color::Cyan),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<ListItem>>();
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Rooms"));
+    f.render_widget(list, area);
+}
+
+/// A message, with the quote of what it replies to above it and its thread
+/// summary after it. Inside the thread pane neither is needed.
+fn message_lines(message: &ChatMessage, in_thread: bool, me: Uuid) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    if let (Some(reply), false) = (&message.reply_to, in_thread) {
+        let quote = if reply.deleted {
+            "↳ replying to a deleted message".to_string()
+        } else {
+            format!(
+                "↳ replying to {}: {}",
+                reply.from.clone().unwrap_or_default(),
+                reply.text
+            )
+        };
+        lines.push(Line::from(Span::styled(
+            format!("      {}", quote),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let mut line = message_line(message);
+    if let (Some(thread), false) = (&message.thread, in_thread) {
+        let replies = if thread.reply_count == 1 {
+            "1 reply".to_string()
+        } else {
+            format!("{} replies", thread.reply_count)
+        };
+        line.spans.push(Span::styled(
+            format!(
+                " [{}, last {}]",
+                replies,
+                thread.last_reply_at.format("%H:%M")
+            ),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    lines.push(line);
+    if !message.deleted {
+        for attachment in message.attachments.iter() {
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "      📎 {} ({}, {})",
+                    attachment.filename,
+                    human_size(attachment.size),
+                    attachment.mime
+                ),
+                Style::default().fg(Color::Magenta),
+            )));
+        }
+    }
+    if !message.reactions.is_empty() && !message.deleted {
+        lines.push(reaction_line(message, me));
+    }
+    lines
+}
+
+fn human_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Reaction chips, with ours highlighted.
+fn reaction_line(message: &ChatMessage, me: Uuid) -> Line<'static> {
+    let mut spans = vec![Span::raw("      ")];
+    for reaction in message.reactions.iter() {
+        let style = if reaction.user_ids.contains(&me) {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::Cyan)
+        };
+        spans.push(Span::styled(
+            format!(" {} {} ", reaction.emoji, reaction.count),
+            style,
+        ));
+        spans.push(Span::raw(" "));
+    }
+    Line::from(spans)
+}
+
+fn message_line(message: &ChatMessage) -> Line<'static> {
+    let time = message.created_at.format("%H:%M").to_string();
+    let from = message.from.clone().unwrap_or_default();
+    let time = Span::styled(format!("{} ", time), Style::default().fg(Color::DarkGray));
+
+    if message.deleted {
+        return Line::from(vec![
+            time,
+            Span::styled(
+                "message deleted",
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            ),
+        ]);
+    }
+
+    let mut line = match message.kind {
+        MessageType::Action => Line::from(vec![
+            time,
+            Span::styled(
+                format!("* {} {}", from, message.text),
+                Style::default().add_modifier(Modifier::ITALIC),
+            ),
+        ]),
+        MessageType::System | MessageType::Join | MessageType::Leave => Line::from(vec![
+            time,
+            Span::styled(message.text.clone(), Style::default().fg(Color::DarkGray)),
+        ]),
+        MessageType::Text => Line::from(vec![
+            time,
+            Span::styled(
+                format!("{}: ", from),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(message.text.clone()),
+        ]),
+    };
+    if message.edited_at.is_some() {
+        line.spans.push(Span::styled(
+            " (edited)",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    line
+}
+
+fn read_marker_line(width: u16) -> Line<'static> {
+    let label = " new ";
+    let rule = "─".repeat((width.saturating_sub(2) as usize).saturating_sub(label.len()) / 2);
+    Line::from(Span::styled(
+        format!("{}{}{}", rule, label, rule),
+        Style::default().fg(Color::Red),
+    ))
+}
+
+/// The latest pin, above the messages.
+fn render_pins<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let Some(view) = app.current_room() else {
+        return;
+    };
+    let Some(pin) = view.pins.first() else {
+        return;
+    };
+    let mut spans = vec![
+        Span::styled("📌 ", Style::default().fg(Color::Red)),
+        Span::styled(
+            format!("{}: ", pin.message.from.clone().unwrap_or_default()),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(
+            pin.message
+                .text
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        ),
+    ];
+    if view.pins.len() > 1 {
+        spans.push(Span::styled(
+            format!(" (+{} more pinned)", view.pins.len() - 1),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+fn render_messages<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let (title, lines) = match app.current_room() {
+        Some(view) => {
+            let mut title = if view.room.topic.is_empty() {
+                format!("#{}", view.room.name)
+            } else {
+                format!("#{} - {}", view.room.name, view.room.topic)
+            };
+            if view.room.slow_mode_secs > 0 {
+                title.push_str(&format!(" [slow mode: {}s]", view.room.slow_mode_secs));
+            }
+            if view.context {
+                title.push_str(" (earlier messages, esc to return)");
+            }
+            let mut lines = Vec::new();
+            let mut end = None;
+            for (i, message) in view.messages.iter().enumerate() {
+                let mut message_lines = message_lines(message, false, app.user_id);
+                if app.mentions_me(message) {
+                    for line in message_lines.iter_mut() {
+                        line.patch_style(Style::default().fg(Color::Yellow));
+                    }
+                }
+                if view.cursor == Some(i) {
+                    for line in message_lines.iter_mut() {
+                        line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+                    }
+                }
+                lines.extend(message_lines);
+                if view.cursor == Some(i) {
+                    end = Some(lines.len());
+                }
+                if view.read_marker == Some(message.seq)
+                    && view.messages.last().map(|last| last.seq) != Some(message.seq)
+                {
+                    lines.push(read_marker_line(area.width));
+                }
+            }
+            // Only so much fits; borders take two rows. Show the tail unless
+            // the cursor is further up.
+            let visible = area.height.saturating_sub(2) as usize;
+            let end = end.unwrap_or(lines.len()).max(visible).min(lines.len());
+            lines.truncate(end);
+            let lines = lines.split_off(lines.len().saturating_sub(visible));
+            (title, lines)
+        }
+        None => ("No rooms".to_string(), Vec::new()),
+    };
+
+    let messages = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(messages, area);
+}
+
+fn render_status<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let status = app
+        .current_room()
+        .and_then(|view| view.typing_line())
+        .or_else(|| app.status.clone())
+        .unwrap_or_default();
+    let status = Paragraph::new(Span::styled(status, Style::default().fg(Color::DarkGray)));
+    f.render_widget(status, area);
+}
+
+fn render_input<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let title = if app.editing.is_some() {
+        "editing (esc to cancel)".to_string()
+    } else if let Some(reply) = &app.replying {
+        format!(
+            "replying to {} (esc to cancel)",
+            reply.from.clone().unwrap_or_default()
+        )
+    } else if app.thread.is_some() {
+        format!("{} (in thread)", app.username)
+    } else {
+        app.username.clone()
+    };
+    let input = Paragraph::new(app.input.as_str())
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(input, area);
+    f.set_cursor(area.x + app.input.chars().count() as u16 + 1, area.y + 1);
+}
+
+fn presence_dot(presence: Presence) -> Span<'static> {
+    let color = match presence {
+        Presence::Online => Color::Green,
+        Presence::Away => Color::Yellow,
+        Presence::Offline => Color::DarkGray,
+    };
+    Span::styled("● ", Style::default().fg(color))
+}
+
+/// IRC style prefix for a member's role.
+fn role_badge(role: Role) -> &'static str {
+    match role {
+        Role::Owner => "~",
+        Role::Admin => "&",
+        Role::Moderator => "@",
+        Role::Member | Role::Guest => "",
+    }
+}
+
+fn render_members<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let items = app
+        .current_room()
+        .map(|view| {
+            view.members
+                .iter()
+                .map(|member| {
+                    let style = if member.presence == Presence::Offline {
+                        Style::default().fg(Color::DarkGray)
+                    } else {
+                        Style::default()
+                    };
+                    let mut spans = vec![
+                        presence_dot(member.presence),
+                        Span::styled(role_badge(member.role), Style::default().fg(Color::Yellow)),
+                        Span::styled(member.username.clone(), style),
+                    ];
+                    if member.muted {
+                        spans.push(Span::styled(
+                            " (muted)",
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    ListItem::new(Line::from(spans))
+                })
+                .collect::<Vec<ListItem>>()
+        })
+        .unwrap_or_default();
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Members"));
+    f.render_widget(list, area);
+}
+
+fn render_thread<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let Some(thread) = &app.thread else {
+        return;
+    };
+    let mut lines = message_lines(&thread.root, true, app.user_id);
+    lines.push(Line::from(Span::styled(
+        "─".repeat(area.width.saturating_sub(2) as usize),
+        Style::default().fg(Color::DarkGray),
+    )));
+    for reply in thread.replies.iter() {
+        lines.extend(message_lines(reply, true, app.user_id));
+    }
+    let visible = area.height.saturating_sub(2) as usize;
+    let lines = lines.split_off(lines.len().saturating_sub(visible));
+
+    let pane = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Thread (esc to close)"),
+    );
+    f.render_widget(pane, area);
+}
+
+fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn render_exit<B: Backend>(f: &mut Frame<B>) {
+    let area = centered(30, 3, f.size());
+    let popup =
+        Paragraph::new("Quit? (y/n)").block(Block::default().borders(Borders::ALL).title("Exit"));
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn render_picker<B: Backend>(f: &mut Frame<B>, selected: usize) {
+    let area = centered(30, REACTIONS.len() as u16 + 2, f.size());
+    let items = REACTIONS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, emoji))| {
+            let style = if i == selected {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Span::styled(format!("{} :{}:", emoji, name), style))
+        })
+        .collect::<Vec<ListItem>>();
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("React"));
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(list, area);
+}
+
+fn render_mentions<B: Backend>(f: &mut Frame<B>, app: &App, selected: usize) {
+    let size = f.size();
+    let area = centered(
+        size.width.saturating_sub(10),
+        size.height.saturating_sub(6),
+        size,
+    );
+    let items = app
+        .mentions
+        .iter()
+        .enumerate()
+        .map(|(i, mention)| {
+            let message = &mention.message;
+            let room = app
+                .rooms
+                .iter()
+                .find(|view| view.room.room_id == message.room_id)
+                .map(|view| view.room.name.clone())
+                .unwrap_or_default();
+            let style = if i == selected {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{} #{} ", message.created_at.format("%m-%d %H:%M"), room),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!(
+                        "{}: {}",
+                        message.from.clone().unwrap_or_default(),
+                        message.text
+                    ),
+                    style,
+                ),
+            ]))
+        })
+        .collect::<Vec<ListItem>>();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Mentions (enter to jump, esc to close)"),
+    );
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(list, area);
+}
+
+fn render_bookmarks<B: Backend>(f: &mut Frame<B>, app: &App, selected: usize) {
+    let size = f.size();
+    let area = centered(
+        size.width.saturating_sub(10),
+        size.height.saturating_sub(6),
+        size,
+    );
+    let items = app
+        .bookmarks
+        .iter()
+        .enumerate()
+        .map(|(i, bookmark)| {
+            let message = &bookmark.message;
+            let room = app
+                .rooms
+                .iter()
+                .find(|view| view.room.room_id == message.room_id)
+                .map(|view| view.room.name.clone())
+                .unwrap_or_default();
+            let style = if i == selected {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{} #{} ", message.created_at.format("%m-%d %H:%M"), room),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!(
+                        "{}: {}",
+                        message.from.clone().unwrap_or_default(),
+                        message.text
+                    ),
+                    style,
+                ),
+            ]))
+        })
+        .collect::<Vec<ListItem>>();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Saved (enter to jump, d to remove, esc to close)"),
+    );
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(list, area);
+}
+
+fn render_two_factor<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let width = app
+        .two_factor
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or_default() as u16;
+    let area = centered(width + 2, app.two_factor.len() as u16 + 2, f.size());
+    let lines = app
+        .two_factor
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<Line>>();
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Two-factor (esc to close)"),
+    );
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Split a search snippet into spans, highlighting what's between `«` and `»`.
+fn snippet_spans(snippet: &str) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut rest = snippet;
+    while let Some(start) = rest.find('«') {
+        spans.push(Span::raw(rest[..start].to_string()));
+        let after = &rest[start + '«'.len_utf8()..];
+        let end = after.find('»').unwrap_or(after.len());
+        spans.push(Span::styled(
+            after[..end].to_string(),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+        rest = after.get(end + '»'.len_utf8()..).unwrap_or_default();
+    }
+    spans.push(Span::raw(rest.to_string()));
+    spans
+}
+
+fn render_search<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let size = f.size();
+    let area = centered(
+        size.width.saturating_sub(10),
+        size.height.saturating_sub(6),
+        size,
+    );
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(area);
+
+    let search = &app.search;
+    let input = Paragraph::new(search.query.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Search (enter to search or jump, esc to close)"),
+    );
+
+    // Keep the selected result on screen.
+    let visible = rows[1].height.saturating_sub(2) as usize;
+    let skip = (search.selected + 1).saturating_sub(visible);
+    let items = search
+        .results
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .map(|(i, hit)| {
+            let message = &hit.message;
+            let mut spans = vec![Span::styled(
+                format!(
+                    "{} #{} {}: ",
+                    message.created_at.format("%m-%d %H:%M"),
+                    hit.room_name,
+                    message.from.clone().unwrap_or_default()
+                ),
+                if i == search.selected {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                },
+            )];
+            spans.extend(snippet_spans(&hit.snippet));
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<ListItem>>();
+    let title = match (&search.ran, search.results.len()) {
+        (None, _) => "Results".to_string(),
+        (Some(_), 0) => "No results".to_string(),
+        (Some(_), n) if search.next_cursor.is_some() => format!("{}+ results", n),
+        (Some(_), n) => format!("{} results", n),
+    };
+    let results = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(input, rows[0]);
+    f.render_widget(results, rows[1]);
+    f.set_cursor(
+        rows[0].x + search.query.chars().count() as u16 + 1,
+        rows[0].y + 1,
+    );
+}